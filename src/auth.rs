@@ -0,0 +1,76 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+use tracing::warn;
+
+use crate::error::{Error, Result};
+
+/// The metadata key the shared token is sent in, as `Bearer <token>`.
+pub const AUTHORIZATION_HEADER: &str = "authorization";
+
+/// Pick the shared token from the flag/env value or from a file, whose
+/// surrounding whitespace is ignored.
+pub fn load_token(token: Option<String>, token_file: Option<&Path>) -> Result<Option<String>> {
+    let token = match (token, token_file) {
+        (Some(_), Some(_)) => {
+            return Err(Error::Config(
+                "Give either a token or a token file, not both".into(),
+            ))
+        }
+        (Some(token), None) => token,
+        (None, Some(path)) => fs::read_to_string(path)
+            .map_err(|e| Error::io(format!("Read token file {:?}", path), e))?
+            .trim()
+            .to_owned(),
+        (None, None) => return Ok(None),
+    };
+    if token.is_empty() {
+        return Err(Error::Config("The shared token is empty".into()));
+    }
+    Ok(Some(token))
+}
+
+pub fn bearer(token: &str) -> Result<MetadataValue<Ascii>> {
+    MetadataValue::try_from(format!("Bearer {token}"))
+        .map_err(|e| Error::Auth(format!("Invalid shared token: {e}")))
+}
+
+/// Rejects every call that does not carry the shared token.
+#[derive(Clone)]
+pub struct AuthInterceptor {
+    expected: Option<Arc<MetadataValue<Ascii>>>,
+}
+
+impl AuthInterceptor {
+    /// Without a token every call is let through.
+    pub fn new(token: Option<&str>) -> Result<Self> {
+        let expected = token.map(bearer).transpose()?.map(Arc::new);
+        Ok(Self { expected })
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(expected) = &self.expected else {
+            return Ok(request);
+        };
+        let authorized = request
+            .metadata()
+            .get(AUTHORIZATION_HEADER)
+            .is_some_and(|actual| constant_time_eq(actual.as_bytes(), expected.as_bytes()));
+        if authorized {
+            Ok(request)
+        } else {
+            warn!("Reject unauthenticated peer: {:?}", request.remote_addr());
+            Err(Error::Auth("Invalid or missing shared token".to_owned()).into())
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
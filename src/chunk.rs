@@ -0,0 +1,139 @@
+use prost::Message;
+use tracing::info;
+
+use crate::error::{Error, Result};
+use crate::util::content_hash;
+use crate::{Chunk, Content};
+
+/// Clips whose encoding is larger travel in chunks of this size, well under
+/// tonic's 4 MiB limit for a decoded message.
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Whether `content` is too large for one message.
+pub fn is_large(content: &Content) -> bool {
+    content.encoded_len() > CHUNK_SIZE
+}
+
+/// What goes in the polling stream in place of a large clip.
+pub fn placeholder(content: &Content) -> Content {
+    Content {
+        stamp: content.stamp.clone(),
+        chunked: content.encoded_len() as u64,
+        chunked_hash: content_hash(content),
+        ..Content::default()
+    }
+}
+
+/// Cut the encoded `content` into chunks, at least one even when empty.
+pub fn split(content: &Content) -> Vec<Chunk> {
+    let encoded = content.encode_to_vec();
+    let size = encoded.len() as u64;
+    if encoded.is_empty() {
+        return vec![Chunk::default()];
+    }
+    encoded
+        .chunks(CHUNK_SIZE)
+        .map(|data| Chunk {
+            size,
+            data: data.to_vec(),
+        })
+        .collect()
+}
+
+/// Puts a clip back together from its chunks, refusing one over `max_size`.
+pub struct Assembler {
+    max_size: u64,
+    buffer: Vec<u8>,
+    size: Option<u64>,
+    progress: Option<Progress>,
+    label: &'static str,
+}
+
+impl Assembler {
+    /// `label` names the transfer in the progress logs.
+    pub fn new(label: &'static str, max_size: u64) -> Self {
+        Self {
+            max_size,
+            buffer: Vec::new(),
+            size: None,
+            progress: None,
+            label,
+        }
+    }
+
+    pub fn push(&mut self, chunk: Chunk) -> Result<()> {
+        let size = *self.size.get_or_insert(chunk.size);
+        if chunk.size != size {
+            return Err(Error::Corrupt(format!(
+                "Chunk announced {} bytes instead of {size}",
+                chunk.size
+            )));
+        }
+        if size > self.max_size {
+            return Err(Error::PayloadTooLarge(format!(
+                "Clip of {size} bytes is over the {} byte limit",
+                self.max_size
+            )));
+        }
+        if (self.buffer.len() + chunk.data.len()) as u64 > size {
+            return Err(Error::Corrupt(format!(
+                "Chunks exceed the announced {size} bytes"
+            )));
+        }
+        self.buffer.extend_from_slice(&chunk.data);
+        self.progress
+            .get_or_insert_with(|| Progress::new(self.label, size))
+            .advance(chunk.data.len());
+        Ok(())
+    }
+
+    /// The clip, once every chunk arrived. A transfer without any is
+    /// refused, it is no empty clip.
+    pub fn finish(self) -> Result<Content> {
+        let Some(size) = self.size else {
+            return Err(Error::Corrupt("Received no chunks".into()));
+        };
+        if self.buffer.len() as u64 != size {
+            return Err(Error::Corrupt(format!(
+                "Received {} of {size} bytes",
+                self.buffer.len()
+            )));
+        }
+        Content::decode(self.buffer.as_slice())
+            .map_err(|e| Error::Corrupt(format!("Invalid chunked clip: {e}")))
+    }
+}
+
+/// Logs how far a transfer got, every quarter.
+pub struct Progress {
+    label: &'static str,
+    total: u64,
+    done: u64,
+    quarters: u64,
+}
+
+impl Progress {
+    pub fn new(label: &'static str, total: u64) -> Self {
+        Self {
+            label,
+            total,
+            done: 0,
+            quarters: 0,
+        }
+    }
+
+    pub fn advance(&mut self, bytes: usize) {
+        self.done += bytes as u64;
+        let quarters = (self.done * 4).checked_div(self.total).unwrap_or(4);
+        if quarters > self.quarters {
+            self.quarters = quarters;
+            info!(
+                "{} {}/{} bytes ({}%)",
+                self.label,
+                self.done,
+                self.total,
+                quarters * 25
+            );
+        }
+    }
+}
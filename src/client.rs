@@ -1,161 +1,600 @@
+mod backoff;
+
+use crate::auth::{bearer, AUTHORIZATION_HEADER};
+use crate::chunk::{self, Assembler, Progress};
 use crate::clipboard::remote_clipboard::RemoteClipboard;
 use crate::clipboard::VirtualClipboard;
-use crate::{synclip_client, Content, Empty};
-use color_eyre::eyre::{eyre, Context};
-use color_eyre::Result;
+use crate::compression;
+use crate::direction::Direction;
+use crate::error::{Error, Result, TransportError};
+use crate::handshake::{check_version, Features};
+use crate::history::History;
+use crate::stamp::Stamper;
+use crate::util::{content_hash, now_millis, PEER_ID_HEADER};
+use crate::{synclip_client, Content, Download, Empty, Greeting, Peer};
+use prost::Message;
+use std::fmt::{Display, Formatter};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
 use tokio_util::sync::CancellationToken;
-use tonic::transport::Channel;
-use tracing::{error, info};
+use tonic::codegen::InterceptedService;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tonic::Code;
+use tracing::{error, info, warn};
+
+pub use backoff::Backoff;
+
+/// How long [`SynclipClient::new`] waits for the server to say hello.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub type GrpcClient = synclip_client::SynclipClient<InterceptedService<Channel, RequestMetadata>>;
+
+/// Identifies and authenticates this client on every call.
+#[derive(Clone)]
+pub struct RequestMetadata {
+    id: String,
+    peer_id: MetadataValue<Ascii>,
+    authorization: Option<MetadataValue<Ascii>>,
+}
+
+impl RequestMetadata {
+    pub fn new(peer_id: &str, token: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            id: peer_id.to_owned(),
+            peer_id: MetadataValue::try_from(peer_id)
+                .map_err(|e| Error::Auth(format!("Invalid peer id {peer_id:?}: {e}")))?,
+            authorization: token.map(bearer).transpose()?,
+        })
+    }
+}
+
+impl Interceptor for RequestMetadata {
+    fn call(
+        &mut self,
+        mut request: tonic::Request<()>,
+    ) -> Result<tonic::Request<()>, tonic::Status> {
+        let metadata = request.metadata_mut();
+        metadata.insert(PEER_ID_HEADER, self.peer_id.clone());
+        if let Some(authorization) = &self.authorization {
+            metadata.insert(AUTHORIZATION_HEADER, authorization.clone());
+        }
+        Ok(request)
+    }
+}
+
+/// What connects a session to the rest of the client.
+struct Link {
+    /// Local clips to send.
+    receiver: watch::Receiver<Content>,
+    /// Clips received from the server.
+    sender: Arc<watch::Sender<Content>>,
+    history: History,
+    /// What this client can do.
+    features: Features,
+    /// What the server and this client agreed on.
+    negotiated: Arc<Mutex<Option<Features>>>,
+    /// The server while connected.
+    server: Arc<Mutex<Option<Peer>>>,
+}
+
+/// How the client is doing at reaching the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Reconnecting { attempt: u32, retry_in: Duration },
+    Stopped,
+}
+
+impl Display for ConnectionState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionState::Connecting => write!(f, "connecting"),
+            ConnectionState::Connected => write!(f, "connected"),
+            ConnectionState::Reconnecting { attempt, retry_in } => {
+                write!(f, "reconnecting (attempt {attempt}, in {retry_in:?})")
+            }
+            ConnectionState::Stopped => write!(f, "stopped"),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct SynclipClient {
+    id: String,
+    stamper: Stamper,
     remote: RemoteClipboard,
-    handle: Arc<Mutex<Option<std::thread::JoinHandle<Result<()>>>>>,
+    /// Feeds the local clipboard, like clips from the server do.
+    local: Arc<watch::Sender<Content>>,
+    history: History,
+    negotiated: Arc<Mutex<Option<Features>>>,
+    server: Arc<Mutex<Option<Peer>>>,
+    state: watch::Receiver<ConnectionState>,
+    handle: Arc<Mutex<Option<JoinHandle<Result<()>>>>>,
 }
 
 impl SynclipClient {
+    /// Start syncing with the server at `address`.
+    ///
+    /// The connection is made in the background and retried with backoff
+    /// whenever it drops, see [`SynclipClient::state`]. Every clip sent or
+    /// received goes to `history`.
+    ///
+    /// A server that is up but cannot agree on a protocol with this client
+    /// is refused here, see [`crate::handshake`].
     pub async fn new(
         address: impl AsRef<str>,
-        initial: String,
+        initial: Content,
+        tls: Option<ClientTlsConfig>,
+        token: Option<String>,
+        history: History,
+        features: Features,
         cancel_token: CancellationToken,
     ) -> Result<Self> {
-        let client = synclip_client::SynclipClient::connect(address.as_ref().to_owned()).await?;
+        let address = address.as_ref();
+        let endpoint = endpoint(address, tls)?;
+        let id = uuid::Uuid::new_v4().to_string();
+        info!("Client id: {id}");
+        let metadata = RequestMetadata::new(&id, token.as_deref())?;
+        Self::check_server(&endpoint, &metadata, &id, &features)
+            .await
+            .map_err(|e| e.context(format!("Server at {address} is not compatible")))?;
+        history.record(None, &initial);
+        let negotiated = Arc::new(Mutex::new(None));
+        let negotiated_2 = negotiated.clone();
+        let server = Arc::new(Mutex::new(None));
+        let server_2 = server.clone();
         let (sender_1, receiver_1) = watch::channel(initial.clone());
         let (sender_2, receiver_2) = watch::channel(initial);
+        let sender_2 = Arc::new(sender_2);
+        let local = sender_2.clone();
+        let history_2 = history.clone();
+        let (state_sender, state) = watch::channel(ConnectionState::Connecting);
 
-        let handle = std::thread::spawn(move || {
-            let handle1 = Self::polling_local(client.clone(), receiver_1, cancel_token.clone());
-            let handle2 = Self::polling_server(client, sender_2, cancel_token);
-            handle1.join().unwrap();
-            handle2.join().unwrap()
-        });
+        let handle = tokio::spawn(Self::run(
+            endpoint,
+            metadata,
+            Link {
+                receiver: receiver_1,
+                sender: sender_2,
+                history: history_2,
+                features,
+                negotiated: negotiated_2,
+                server: server_2,
+            },
+            state_sender,
+            cancel_token,
+        ));
 
         let client = Self {
+            stamper: Stamper::new(id.as_str()),
+            id,
             remote: RemoteClipboard::new(sender_1, receiver_2),
+            local,
+            history,
+            negotiated,
+            server,
+            state,
             handle: Arc::new(Mutex::new(Some(handle))),
         };
 
         Ok(client)
     }
 
-    pub fn polling_server(
-        mut client: synclip_client::SynclipClient<Channel>,
-        sender: watch::Sender<String>,
+    /// The id this client reports to the server.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The current state of the connection to the server.
+    pub fn state(&self) -> ConnectionState {
+        self.state.borrow().clone()
+    }
+
+    /// Follow the state of the connection to the server.
+    pub fn watch_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.clone()
+    }
+
+    /// What the server and this client agreed on, once connected.
+    pub fn features(&self) -> Option<Features> {
+        self.negotiated.lock().unwrap().clone()
+    }
+
+    /// Every clip this client sent or received.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Make an older clip current again, locally and on the server.
+    pub async fn apply_history(&self, id: u64) -> Result<bool> {
+        let content = self
+            .history
+            .get(id)
+            .and_then(|entry| entry.content)
+            .ok_or_else(|| Error::NotFound(format!("No history entry {id}")))?;
+        // A sealed clip only opens under the stamp it was sealed with.
+        if content.is_sealed() {
+            return Err(Error::Crypto(format!(
+                "History entry {id} is encrypted, apply it through the clipboard holding the passphrase"
+            )));
+        }
+        // Copied anew so it wins over the current clip, and sent from here as
+        // an end-to-end sealed clip is not sent again once the local
+        // clipboard holds its plaintext.
+        let content = self.stamper.stamp(content.unstamped());
+        let replaced = self.remote.set(content.clone()).await?;
+        // Unstamped, the engine would take its own stamp for an echo.
+        self.local.send_replace(content.unstamped());
+        Ok(replaced)
+    }
+
+    /// Keep a session with the server alive until cancelled or a fatal error.
+    async fn run(
+        endpoint: Endpoint,
+        metadata: RequestMetadata,
+        mut link: Link,
+        state: watch::Sender<ConnectionState>,
         cancel_token: CancellationToken,
-    ) -> std::thread::JoinHandle<Result<()>> {
-        std::thread::spawn(move || {
-            let runtime = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()?;
-
-            runtime.block_on(async move {
-                let request = tonic::Request::new(Empty::default());
-                let response = client.polling_clipboard(request).await?;
-                let mut stream = response.into_inner();
-
-                loop {
-                    tokio::select! {
-                        _ = cancel_token.cancelled() => {
-                            info!("Polling [Client-Server] shutdown");
-                            break;
-                        }
-                        result = async {
-                            match stream.message().await {
-                                Ok(Some(Content { text })) => {
-                                    let _replaced = sender.send_if_modified(|prev| {
-                                        if prev != &text {
-                                            *prev = text;
-                                            true
-                                        } else {
-                                            false
-                                        }
-                                    });
-                                    Ok(())
-                                }
-                                Ok(None) => Err(eyre!("Get clipboard from [Remote] None")),
-                                Err(e) => Err(eyre!(e))
-                            }
-                        } => {
-                            if let Err(e) = result {
-                                error!("Polling [Client-Server] error: {:?}", e);
-                                break;
-                            }
-                        }
-                    }
+    ) -> Result<()> {
+        let mut backoff = Backoff::default();
+        // A local clip that has not reached the server yet.
+        let mut pending = false;
+        let result = loop {
+            let session = Self::session(
+                &endpoint,
+                &metadata,
+                &mut link,
+                &state,
+                &mut backoff,
+                &mut pending,
+            );
+            let error = tokio::select! {
+                _ = cancel_token.cancelled() => {
+                    info!("Polling [Client-Server] shutdown");
+                    break Ok(());
+                }
+                result = session => match result {
+                    Ok(()) => Error::from(TransportError::Closed).context("[Server] clipboard stream"),
+                    Err(e) => e,
                 }
+            };
+            *link.server.lock().unwrap() = None;
+            if is_fatal(&error) {
+                error!("Polling [Client-Server] error: {:?}", error);
                 cancel_token.cancel();
-                info!("End polling [Client-Server]");
-                Ok(())
-            })
-        })
+                break Err(error);
+            }
+            let retry_in = backoff.next_delay();
+            warn!("Polling [Client-Server] error: {error}, reconnect in {retry_in:?}");
+            Self::set_state(
+                &state,
+                ConnectionState::Reconnecting {
+                    attempt: backoff.attempt(),
+                    retry_in,
+                },
+            );
+            tokio::select! {
+                _ = cancel_token.cancelled() => {
+                    info!("Polling [Client-Server] shutdown");
+                    break Ok(());
+                }
+                _ = tokio::time::sleep(retry_in) => {}
+            }
+        };
+        Self::set_state(&state, ConnectionState::Stopped);
+        info!("End polling [Client-Server]");
+        result
     }
 
-    pub fn polling_local(
-        mut client: synclip_client::SynclipClient<Channel>,
-        mut receiver: watch::Receiver<String>,
-        cancel_token: CancellationToken,
-    ) -> std::thread::JoinHandle<()> {
-        std::thread::spawn(move || {
-            let runtime = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .unwrap();
-            runtime.block_on(async move {
-                loop {
-                    tokio::select! {
-                        _ = cancel_token.cancelled() => {
-                            info!("Polling [Client-Local] shutdown");
-                            break;
-                        }
-                        _ = receiver.changed() => {
-                            let content = receiver.borrow_and_update().clone();
-                            let request = tonic::Request::new(Content { text: content.clone() });
-                            let response = client
-                                .set_clipboard(request)
-                                .await
-                                .with_context(|| "Send clipboard to [Remote]");
-                            match response {
-                                Ok(response) => {
-                                    let replaced = response.into_inner().replaced;
-                                    if replaced {
-                                        info!("Set [Remote] with: [{replaced}] {:?}", content);
-                                    }
-                                }
-                                Err(e) => {
-                                    error!("Set [Remote] error: {:?}", e);
-                                    break;
-                                }
+    /// One connection to the server, returns when it breaks.
+    async fn session(
+        endpoint: &Endpoint,
+        metadata: &RequestMetadata,
+        link: &mut Link,
+        state: &watch::Sender<ConnectionState>,
+        backoff: &mut Backoff,
+        pending: &mut bool,
+    ) -> Result<()> {
+        let Link {
+            receiver,
+            sender,
+            history,
+            features,
+            negotiated,
+            server,
+        } = link;
+        let channel = endpoint.connect().await?;
+        let mut client = synclip_client::SynclipClient::with_interceptor(channel, metadata.clone());
+        let (features, greeting) = handshake(&mut client, &metadata.id, features).await?;
+        *negotiated.lock().unwrap() = Some(features.clone());
+        let now = now_millis();
+        *server.lock().unwrap() = Some(Peer {
+            id: greeting.node_id,
+            address: endpoint.uri().to_string(),
+            connected_at: now,
+            last_seen: now,
+            last_content_hash: 0,
+            compression: features.codec().unwrap_or_default().to_owned(),
+            direction: features.direction.to_string(),
+        });
+
+        // Whatever was copied while disconnected is newer than the server's clip.
+        if *pending || receiver.has_changed()? {
+            *pending = true;
+            let content = receiver.borrow_and_update().clone();
+            history.record(None, &content);
+            Self::send(&client, &features, content).await?;
+            *pending = false;
+        }
+        let request = tonic::Request::new(Empty::default());
+        let mut stream = client.polling_clipboard(request).await?.into_inner();
+        Self::set_state(state, ConnectionState::Connected);
+        backoff.reset();
+
+        loop {
+            tokio::select! {
+                message = stream.message() => {
+                    let Some(mut content) = message? else {
+                        return Ok(());
+                    };
+                    if !content.gzipped.is_empty() {
+                        content = compression::unpack(&content, features.max_payload)?;
+                    }
+                    if content.chunked > 0 {
+                        match Self::download(&client, &features, &content).await {
+                            Ok(downloaded) => content = downloaded,
+                            // The newer clip is announced next.
+                            Err(Error::NotFound(message)) => {
+                                info!("Skip download [Client-Server]: {message}");
+                                continue;
                             }
+                            Err(error) => return Err(error),
                         }
                     }
+                    if let Some(server) = server.lock().unwrap().as_mut() {
+                        server.last_seen = now_millis();
+                        server.last_content_hash = content_hash(&content);
+                    }
+                    let replaced = sender.send_if_modified(|prev| {
+                        if content.supersedes(prev) {
+                            *prev = content.clone();
+                            true
+                        } else {
+                            false
+                        }
+                    });
+                    if replaced {
+                        history.record(Some("server"), &content);
+                    }
                 }
-                cancel_token.cancel();
-                info!("End polling [Client-Local]");
+                changed = receiver.changed() => {
+                    changed?;
+                    *pending = true;
+                    let content = receiver.borrow_and_update().clone();
+                    history.record(None, &content);
+                    Self::send(&client, &features, content).await?;
+                    *pending = false;
+                }
+            }
+        }
+    }
+
+    /// Say hello to the server once before starting, so an incompatible one
+    /// is refused right away. An unreachable one is left to [`Self::run`].
+    async fn check_server(
+        endpoint: &Endpoint,
+        metadata: &RequestMetadata,
+        id: &str,
+        features: &Features,
+    ) -> Result<()> {
+        let attempt = async {
+            let channel = endpoint.connect().await?;
+            let mut client =
+                synclip_client::SynclipClient::with_interceptor(channel, metadata.clone());
+            handshake(&mut client, id, features).await.map(|_| ())
+        };
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, attempt).await {
+            Ok(Err(error)) if is_incompatible(&error) => Err(error),
+            Ok(Err(error)) => {
+                warn!("Hello [Client-Server] error: {error}, retrying in the background");
+                Ok(())
+            }
+            Ok(Ok(_)) => Ok(()),
+            Err(_) => {
+                warn!("Hello [Client-Server] timed out, retrying in the background");
+                Ok(())
+            }
+        }
+    }
+
+    /// Send a local clip, in chunks if it is large. A clip over the limit
+    /// stays local, it is no reason to drop the connection, and so does every
+    /// clip when the server only lets this client receive.
+    async fn send(client: &GrpcClient, features: &Features, content: Content) -> Result<()> {
+        if !features.direction.sends() {
+            warn!("Keep local, [Server] only lets this client receive");
+            return Ok(());
+        }
+        let content = features.filter(content);
+        let size = content.encoded_len() as u64;
+        if let Err(error) = features.check_size(size) {
+            warn!("Keep local: {error}");
+            return Ok(());
+        }
+        let mut client = client.clone();
+        let encoding = features.compress(size);
+        if let Some(encoding) = encoding {
+            client = client.send_compressed(encoding);
+        }
+        let response = if chunk::is_large(&content) {
+            let mut progress = Progress::new("Upload [Client-Server]", size);
+            let chunks = tokio_stream::iter(chunk::split(&content)).map(move |chunk| {
+                progress.advance(chunk.data.len());
+                chunk
             });
-        })
+            client.upload_clipboard(chunks).await
+        } else {
+            client.set_clipboard(content.clone()).await
+        };
+        let response = match response {
+            Err(status) if status.code() == Code::ResourceExhausted => {
+                warn!("Keep local, [Server] refused: {}", status.message());
+                return Ok(());
+            }
+            response => response
+                .map_err(|status| Error::from(status).context("Send clipboard to [Remote]"))?,
+        };
+        let replaced = response.into_inner().replaced;
+        if replaced {
+            let codec = encoding.and(features.codec()).unwrap_or("uncompressed");
+            info!("Set [Remote] with: [{replaced}] {} ({codec})", content);
+        }
+        Ok(())
+    }
+
+    /// Fetch the server's clip announced in the polling stream by `placeholder`.
+    async fn download(
+        client: &GrpcClient,
+        features: &Features,
+        placeholder: &Content,
+    ) -> Result<Content> {
+        let mut client = client.clone();
+        if let Some(encoding) = features.compress(placeholder.chunked) {
+            client = client.accept_compressed(encoding);
+        }
+        let hash = placeholder.chunked_hash;
+        let mut chunks = client
+            .download_clipboard(Download { hash })
+            .await
+            .map_err(|status| Error::from(status).context("Download clipboard from [Remote]"))?
+            .into_inner();
+        let mut assembler = Assembler::new("Download [Client-Server]", features.max_payload);
+        while let Some(chunk) = chunks.message().await? {
+            assembler.push(chunk)?;
+        }
+        assembler.finish()
+    }
+
+    fn set_state(state: &watch::Sender<ConnectionState>, new: ConnectionState) {
+        info!("Connection [Client-Server]: {new}");
+        state.send_replace(new);
     }
 
-    pub fn shutdown(self) -> Result<()> {
+    /// Wait for the connection to close, once the cancel token fired.
+    pub async fn shutdown(self) -> Result<()> {
         info!("Shutdown [Client]");
-        self.handle
-            .lock()
-            .unwrap()
-            .take()
-            .unwrap()
-            .join()
-            .unwrap()?;
+        // Taken by the first of the clones to shut down.
+        let handle = self.handle.lock().unwrap().take();
+        if let Some(handle) = handle {
+            match handle.await {
+                Ok(result) => result?,
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                Err(_) => return Err(Error::Shutdown),
+            }
+        }
         Ok(())
     }
 }
 
+/// A one-off connection to the server at `address`, for commands.
+pub async fn connect(
+    address: &str,
+    tls: Option<ClientTlsConfig>,
+    token: Option<&str>,
+) -> Result<GrpcClient> {
+    let channel = endpoint(address, tls)?
+        .connect()
+        .await
+        .map_err(|e| Error::from(e).context(format!("Connect to {address}")))?;
+    let id = uuid::Uuid::new_v4().to_string();
+    let metadata = RequestMetadata::new(&id, token)?;
+    Ok(synclip_client::SynclipClient::with_interceptor(
+        channel, metadata,
+    ))
+}
+
+/// Exchange hellos with the server and narrow `features` to what both can
+/// do, the server's own greeting comes along.
+pub async fn handshake(
+    client: &mut GrpcClient,
+    id: &str,
+    features: &Features,
+) -> Result<(Features, Greeting)> {
+    let response = match client.hello(features.hello(id)).await {
+        Ok(response) => response.into_inner(),
+        Err(status) if status.code() == Code::Unimplemented => {
+            return Err(Error::Protocol(
+                "Server predates the hello handshake, upgrade it to this release".to_owned(),
+            ));
+        }
+        Err(status) => return Err(status.into()),
+    };
+    check_version(&response)?;
+    let features = features
+        .narrow(&response)
+        .restrict(Direction::from_greeting(&response.direction)?)?;
+    info!(
+        "Hello [Server] {}: {:?}, compression: {}, direction: {}",
+        response.node_id,
+        features.content_types,
+        features.codec().unwrap_or("none"),
+        features.direction
+    );
+    Ok((features, response))
+}
+
+fn endpoint(address: &str, tls: Option<ClientTlsConfig>) -> Result<Endpoint> {
+    let mut endpoint = Channel::from_shared(address.to_owned())
+        .map_err(|e| Error::from(TransportError::Address(e)).context(address))?;
+    if let Some(tls) = tls {
+        endpoint = endpoint.tls_config(tls)?;
+    }
+    Ok(endpoint)
+}
+
+/// Errors retrying cannot fix, like a wrong token.
+fn is_fatal(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::Auth(_) | Error::Forbidden(_) | Error::Protocol(_)
+    )
+}
+
+/// Errors from a server this client cannot talk to at all, or not in the
+/// direction it is configured for.
+fn is_incompatible(error: &Error) -> bool {
+    matches!(error, Error::Protocol(_) | Error::Forbidden(_))
+}
+
 impl VirtualClipboard for SynclipClient {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn stamper(&self) -> Stamper {
+        self.stamper.clone()
+    }
+
     fn remote(&self) -> &RemoteClipboard {
         &self.remote
     }
 
-    fn shutdown(self) -> Result<()> {
-        self.shutdown()
+    /// The server, once connected.
+    fn peers(&self) -> Vec<Peer> {
+        self.server.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn history(&self) -> Option<&History> {
+        Some(&self.history)
+    }
+
+    async fn shutdown(self) -> Result<()> {
+        self.shutdown().await
     }
 }
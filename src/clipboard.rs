@@ -1,108 +1,389 @@
+pub mod backend;
 pub mod local_clipboard;
 pub mod remote_clipboard;
 
+use crate::client::Backoff;
+use crate::clipboard::backend::{ClipboardBackend, SystemBackend};
 use crate::clipboard::local_clipboard::LocalClipboard;
 use crate::clipboard::remote_clipboard::RemoteClipboard;
+use crate::crypto::Cipher;
+use crate::direction::Direction;
+use crate::error::Error;
+use crate::filter::Filter;
+use crate::history::History;
+use crate::metrics::Metrics;
+use crate::stamp::Stamper;
+use crate::util::now_millis;
+use crate::{Content, Peer, Stamp};
+use color_eyre::eyre::eyre;
 use color_eyre::Result;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use tokio::sync::{broadcast, watch};
-use tokio::time::interval;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::{broadcast, watch, Mutex, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Interval, MissedTickBehavior};
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
 
-pub type ClipboardSender = broadcast::Sender<String>;
-pub type ClipboardReceiver = watch::Receiver<String>;
+/// How often a backend that reports its changes is still polled, in case
+/// an event is missed.
+const WATCHED_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// The milliseconds between two reads of the local clipboard, unless
+/// configured.
+pub const DEFAULT_POLL_INTERVAL: u64 = 500;
+/// The longest wait before reading a local clipboard that failed again.
+const MAX_LOCAL_RETRY: Duration = Duration::from_secs(10);
+/// `paused_until` of a pause that lasts until resumed.
+const UNTIL_RESUMED: u64 = u64::MAX;
 
-#[derive(Clone)]
-pub struct Clipboard<T: VirtualClipboard> {
+pub type ClipboardSender = broadcast::Sender<Content>;
+pub type ClipboardReceiver = watch::Receiver<Content>;
+/// A local clip and what the filter made of it.
+type Filtered = Arc<Mutex<Option<(Content, Option<Content>)>>>;
+
+pub struct Clipboard<T: VirtualClipboard, B: ClipboardBackend = SystemBackend> {
     remote: T,
-    local: LocalClipboard,
+    local: LocalClipboard<B>,
     frequency: Arc<AtomicU64>,
+    frequency_changed: Arc<Notify>,
     cancel_token: CancellationToken,
+    cipher: Option<Cipher>,
+    filter: Option<Filter>,
+    direction: Direction,
+    /// When the pause ends in Unix milliseconds, 0 while syncing.
+    paused_until: Arc<AtomicU64>,
+    /// Keep the next new local clip local.
+    skip_next: Arc<AtomicBool>,
+    /// The last local clip filtered and what the filter made of it, so a
+    /// clip kept local is not filtered and logged on every poll.
+    filtered: Filtered,
+    stamper: Stamper,
+    /// The plaintext last sent or applied, a local clipboard holding it has
+    /// nothing new to send.
+    synced: Arc<Mutex<Option<Content>>>,
+    /// The stamp of the newest clip sent or applied.
+    latest: Arc<Mutex<Option<Stamp>>>,
+    /// Bumped before and after a remote clip is applied, odd while one is:
+    /// a local read taken meanwhile may predate it.
+    applying: Arc<AtomicU64>,
+    metrics: Arc<Metrics>,
+    /// The error syncing stopped on, the first one only.
+    failure: Arc<OnceLock<Error>>,
+}
+
+impl<T: VirtualClipboard, B: ClipboardBackend> Clone for Clipboard<T, B> {
+    fn clone(&self) -> Self {
+        Self {
+            remote: self.remote.clone(),
+            local: self.local.clone(),
+            frequency: self.frequency.clone(),
+            frequency_changed: self.frequency_changed.clone(),
+            cancel_token: self.cancel_token.clone(),
+            cipher: self.cipher.clone(),
+            filter: self.filter.clone(),
+            direction: self.direction,
+            paused_until: self.paused_until.clone(),
+            skip_next: self.skip_next.clone(),
+            filtered: self.filtered.clone(),
+            stamper: self.stamper.clone(),
+            synced: self.synced.clone(),
+            latest: self.latest.clone(),
+            applying: self.applying.clone(),
+            metrics: self.metrics.clone(),
+            failure: self.failure.clone(),
+        }
+    }
 }
 
 pub enum ClipboardEvent {
-    SetLocal(String),
+    SetLocal(Box<Content>),
     Shutdown,
 }
 
-impl<T: VirtualClipboard + 'static> Clipboard<T> {
+impl<T: VirtualClipboard + 'static, B: ClipboardBackend> Clipboard<T, B> {
     pub fn new(
-        local: LocalClipboard,
+        local: LocalClipboard<B>,
         remote: T,
         frequency: u64,
         cancel_token: CancellationToken,
     ) -> Self {
         let frequency = Arc::new(AtomicU64::new(frequency));
+        let stamper = remote.stamper();
         Self {
             remote,
             local,
             frequency,
+            frequency_changed: Arc::new(Notify::new()),
             cancel_token,
+            cipher: None,
+            filter: None,
+            direction: Direction::Both,
+            paused_until: Arc::new(AtomicU64::new(0)),
+            skip_next: Arc::new(AtomicBool::new(false)),
+            filtered: Arc::new(Mutex::new(None)),
+            stamper,
+            synced: Arc::new(Mutex::new(None)),
+            latest: Arc::new(Mutex::new(None)),
+            applying: Arc::new(AtomicU64::new(0)),
+            metrics: Arc::new(Metrics::default()),
+            failure: Arc::new(OnceLock::new()),
         }
     }
 
-    pub fn start(&mut self) -> std::thread::JoinHandle<()> {
-        let this = self.clone();
-        let handle1 = std::thread::spawn(move || {
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .unwrap();
-            rt.block_on(this.polling_local());
-        });
-        let this = self.clone();
-        let handle2 = std::thread::spawn(move || {
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .unwrap();
-            rt.block_on(this.listen_remote());
-        });
-        std::thread::spawn(move || {
-            handle1.join().unwrap();
-            handle2.join().unwrap();
+    /// Encrypt clips end-to-end, the remote only sees ciphertext.
+    pub fn with_cipher(mut self, cipher: Cipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Check every local clip before it leaves the machine.
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Only send local clips or only apply remote ones.
+    pub fn with_direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// What clips are synced through.
+    pub fn remote(&self) -> &T {
+        &self.remote
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Stop syncing both ways, clips copied while paused are never sent.
+    pub fn pause(&self) {
+        self.paused_until.store(UNTIL_RESUMED, Ordering::Relaxed);
+        info!("Sync paused");
+    }
+
+    /// Pause for `duration` only, syncing resumes by itself afterwards.
+    pub fn pause_for(&self, duration: Duration) {
+        let until = now_millis().saturating_add(duration.as_millis() as u64);
+        self.paused_until.store(until, Ordering::Relaxed);
+        info!("Sync paused for {:?}", duration);
+    }
+
+    pub fn resume(&self) {
+        if self.paused_until.swap(0, Ordering::Relaxed) != 0 {
+            info!("Sync resumed");
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_until().is_some()
+    }
+
+    /// When the pause ends in Unix milliseconds, `Some(None)` for a pause
+    /// that lasts until resumed and `None` while syncing.
+    pub fn paused_until(&self) -> Option<Option<u64>> {
+        match self.paused_until.load(Ordering::Relaxed) {
+            0 => None,
+            UNTIL_RESUMED => Some(None),
+            until if until > now_millis() => Some(Some(until)),
+            until => {
+                let expired = self.paused_until.compare_exchange(
+                    until,
+                    0,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                );
+                if expired.is_ok() {
+                    info!("Sync resumed, the pause is over");
+                }
+                None
+            }
+        }
+    }
+
+    /// Keep the next clip copied here local, later ones sync again.
+    pub fn skip_next(&self) {
+        self.skip_next.store(true, Ordering::Relaxed);
+        info!("Skip the next copy");
+    }
+
+    /// Whether the next copy is kept local.
+    pub fn skips_next(&self) -> bool {
+        self.skip_next.load(Ordering::Relaxed)
+    }
+
+    /// How syncing went so far.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// The error syncing stopped on, `None` while syncing or once stopped
+    /// as asked.
+    pub fn failure(&self) -> Option<&Error> {
+        self.failure.get()
+    }
+
+    /// The milliseconds between two reads of the local clipboard.
+    pub fn frequency(&self) -> u64 {
+        self.frequency.load(Ordering::Relaxed)
+    }
+
+    /// Change how often the local clipboard is read, effective immediately.
+    pub fn set_frequency(&self, frequency: u64) {
+        self.frequency.store(frequency, Ordering::Relaxed);
+        self.frequency_changed.notify_one();
+    }
+
+    /// Sync in the background on the current runtime, local clips are only
+    /// read when sending and remote clips only applied when receiving.
+    ///
+    /// The handle resolves once both directions stopped, after the cancel
+    /// token fired.
+    pub fn start(&mut self) -> JoinHandle<()> {
+        info!("Direction: {}", self.direction);
+        let mut handles = Vec::new();
+        if self.direction.sends() {
+            let this = self.clone();
+            handles.push(tokio::spawn(async move { this.polling_local().await }));
+        }
+        if self.direction.receives() {
+            let this = self.clone();
+            handles.push(tokio::spawn(async move { this.listen_remote().await }));
+        }
+        tokio::spawn(async move {
+            for handle in handles {
+                if let Err(e) = handle.await {
+                    if e.is_panic() {
+                        std::panic::resume_unwind(e.into_panic());
+                    }
+                }
+            }
         })
     }
 
-    pub async fn shutdown(self) -> Result<()> {
+    /// Make a clip of the node's history the local one again, it is then
+    /// sent on like any copy. `false` if the local clipboard held it already.
+    pub async fn apply_history(&self, id: u64) -> crate::Result<bool> {
+        let content = self
+            .remote
+            .history()
+            .and_then(|history| history.get(id))
+            .and_then(|entry| entry.content)
+            .ok_or_else(|| Error::NotFound(format!("No history entry {id}")))?;
+        let content = match &self.cipher {
+            Some(cipher) if content.is_sealed() => cipher.open(&content)?,
+            None if content.is_sealed() => {
+                return Err(Error::Protocol(format!(
+                    "History entry {id} is encrypted, set the passphrase to apply it"
+                )))
+            }
+            _ => content.unstamped(),
+        };
+        info!("Apply history entry {id}: {}", content);
+        self.local.set(content).await
+    }
+
+    /// Shut the remote down. Once the handle of [`Clipboard::start`]
+    /// resolved, the error syncing stopped on is returned first.
+    pub async fn shutdown(self) -> crate::Result<()> {
         info!("Shutdown [Remote]");
-        self.remote.shutdown()?;
-        Ok(())
+        let result = self.remote.shutdown().await;
+        match Arc::into_inner(self.failure).and_then(OnceLock::into_inner) {
+            Some(failure) => Err(failure),
+            None => result,
+        }
     }
 
     async fn polling_local(&self) {
-        let mut interval = interval(tokio::time::Duration::from_millis(
-            self.frequency.load(Ordering::Relaxed),
-        ));
+        let mut watcher = self.local.watch().await;
+        let mut interval = self.poll_interval(watcher.is_some());
+        let mut retry = self.local_retry();
         loop {
             tokio::select! {
                 _ = self.cancel_token.cancelled() => {
                     info!("Polling [Local] shutdown");
                     break;
                 }
-                _ = interval.tick() => {
-                    match self.local.get().await {
-                        Ok(content) => {
-                            match self.remote.remote().set(content.clone()).await {
-                                Ok(replaced) => {
-                                    if replaced {
-                                        info!("Set [Remote] with: [{replaced}] {:?}", content);
-                                    }
-                                }
-                                Err(e) => {
-                                    error!("Set [Remote] error: {:?}", e);
-                                    break;
-                                },
-                            }
+                _ = self.frequency_changed.notified() => {
+                    interval = self.poll_interval(watcher.is_some());
+                    retry = self.local_retry();
+                    continue;
+                }
+                _ = interval.tick() => {}
+                _ = local_changed(&mut watcher) => {}
+            }
+            let applying = self.applying.load(Ordering::Acquire);
+            let content = match self.local.get().await {
+                Ok(content) => {
+                    if retry.attempt() > 0 {
+                        info!("Get [Local] recovered");
+                        retry.reset();
+                    }
+                    content
+                }
+                Err(e) => match e.transient() {
+                    Some(transient) => {
+                        self.metrics.record_transient(transient);
+                        let delay = retry.next_delay();
+                        if retry.attempt() == 1 {
+                            warn!("Get [Local] failed, retrying: {}", e);
+                        } else {
+                            debug!("Get [Local] failed again, retry in {:?}: {}", delay, e);
                         }
-                        Err(e) => {
-                            error!("Get [Local] error: {:?}", e);
-                            break;
+                        // A copy ends the wait early, the clipboard likely
+                        // holds something readable again.
+                        tokio::select! {
+                            _ = self.cancel_token.cancelled() => {
+                                info!("Polling [Local] shutdown");
+                                break;
+                            }
+                            _ = tokio::time::sleep(delay) => {}
+                            _ = local_changed(&mut watcher) => {}
                         }
+                        interval.reset_immediately();
+                        continue;
+                    }
+                    None => {
+                        self.metrics.record_fatal();
+                        error!("Get [Local] error: {:?}", e);
+                        let _ = self.failure.set(e);
+                        break;
+                    }
+                },
+            };
+            if self.is_paused() {
+                self.skip(content, "while paused").await;
+                continue;
+            }
+            if self.skips_next() && self.skip(content.clone(), "as asked").await {
+                self.skip_next.store(false, Ordering::Relaxed);
+                continue;
+            }
+            let Some(content) = self.filter(content).await else {
+                continue;
+            };
+            let outgoing = match self.prepare(content.clone(), applying).await {
+                Ok(Some(outgoing)) => outgoing,
+                Ok(None) => continue,
+                // One clip that cannot go out is no reason to stop syncing.
+                Err(e) => {
+                    error!("Seal [Local] error: {:?}", e);
+                    continue;
+                }
+            };
+            match self.remote.remote().set(outgoing).await {
+                Ok(replaced) => {
+                    if replaced {
+                        info!("Set [Remote] with: [{replaced}] {}", content);
                     }
                 }
+                Err(e) => {
+                    error!("Set [Remote] error: {:?}", e);
+                    continue;
+                }
             }
         }
         self.cancel_token.cancel();
@@ -119,22 +400,43 @@ impl<T: VirtualClipboard + 'static> Clipboard<T> {
                 result =  async {
                     match self.remote.remote().get_new().await {
                         Ok(content) => {
-                            info!("Get [Remote] with: {:?}", content);
+                            if !self.accept(&content).await {
+                                return Ok(());
+                            }
+                            if self.is_paused() {
+                                info!("Ignore [Remote] while paused: {}", content);
+                                return Ok(());
+                            }
+                            info!("Get [Remote] with: {}", content);
+                            let _applying = Applying::start(&self.applying);
+                            let content = match self.open(content).await {
+                                Ok(content) => content,
+                                Err(e) => {
+                                    error!("Open [Remote] error: {:?}", e);
+                                    return Ok(());
+                                }
+                            };
                             match self.local.set(content.clone()).await {
                                 Ok(replaced) => {
                                     if replaced {
-                                        info!("Set [Local] with: [{replaced}] {:?}", content);
+                                        info!("Set [Local] with: [{replaced}] {}", content);
                                     }
                                     Ok(())
                                 }
+                                // One clip that cannot be applied is no reason
+                                // to stop receiving.
                                 Err(e) => {
                                     error!("Set [Local] error: {:?}", e);
-                                    Err(())
+                                    Ok(())
                                 },
                             }
                         }
+                        // The remote ending is no failure of its own, its
+                        // shutdown tells why.
+                        Err(Error::Shutdown) => Err(()),
                         Err(e) => {
                             error!("Get [Remote] error: {:?}", e);
+                            let _ = self.failure.set(e);
                             Err(())
                         }
                     }
@@ -147,10 +449,196 @@ impl<T: VirtualClipboard + 'static> Clipboard<T> {
         }
         info!("End listen [Remote]");
     }
+
+    /// Event driven backends only need a slow safety net.
+    fn poll_interval(&self, watched: bool) -> Interval {
+        let mut period = Duration::from_millis(self.frequency().max(1));
+        if watched {
+            period = period.max(WATCHED_POLL_INTERVAL);
+        }
+        info!("Polling [Local] every {:?}", period);
+        let mut interval = interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    }
+
+    /// Failed reads of the local clipboard are retried slower and slower,
+    /// starting at the poll interval.
+    fn local_retry(&self) -> Backoff {
+        let initial = Duration::from_millis(self.frequency().max(1));
+        Backoff::new(initial.min(MAX_LOCAL_RETRY), MAX_LOCAL_RETRY)
+    }
+
+    /// Take a local clip as synced without sending it, so it is not sent
+    /// later either. `false` if it was synced already.
+    async fn skip(&self, content: Content, reason: &str) -> bool {
+        let mut synced = self.synced.lock().await;
+        if synced
+            .as_ref()
+            .is_some_and(|previous| previous.reads_back_as(&content))
+        {
+            return false;
+        }
+        info!("Skip [Local] {reason}: {}", content);
+        *synced = Some(content);
+        true
+    }
+
+    /// The local clip as it may leave the machine, `None` if it must not.
+    async fn filter(&self, content: Content) -> Option<Content> {
+        let Some(filter) = &self.filter else {
+            return Some(content);
+        };
+        // A remote clip read back is not ours to filter, redacting it would
+        // send it out again.
+        let synced = self.synced.lock().await;
+        if synced
+            .as_ref()
+            .is_some_and(|previous| previous.reads_back_as(&content))
+        {
+            return Some(content);
+        }
+        drop(synced);
+        let mut filtered = self.filtered.lock().await;
+        if let Some((input, output)) = filtered.as_ref() {
+            if *input == content {
+                return output.clone();
+            }
+        }
+        let output = filter.apply(content.clone());
+        *filtered = Some((content, output.clone()));
+        output
+    }
+
+    /// Prepare a local clip for the remote, `None` if it was synced already
+    /// or read while a remote clip was applied, see [`Clipboard::applying`].
+    async fn prepare(&self, content: Content, applying: u64) -> Result<Option<Content>> {
+        let mut synced = self.synced.lock().await;
+        if synced.is_none() {
+            *synced = self.initial().await;
+        }
+        if applying % 2 != 0 || self.applying.load(Ordering::Acquire) != applying {
+            debug!(
+                "Skip [Local] read while applying a remote clip: {}",
+                content
+            );
+            return Ok(None);
+        }
+        // Also what a remote clip applied to the local clipboard reads back as.
+        if synced
+            .as_ref()
+            .is_some_and(|previous| previous.reads_back_as(&content))
+        {
+            return Ok(None);
+        }
+        let stamp = self.stamper.next();
+        *self.latest.lock().await = Some(stamp.clone());
+        let outgoing = content.clone().with_stamp(stamp);
+        let outgoing = match &self.cipher {
+            Some(cipher) => cipher.seal(&outgoing)?,
+            None => outgoing,
+        };
+        *synced = Some(content);
+        Ok(Some(outgoing))
+    }
+
+    /// The plaintext the remote was started with, starting a node must not
+    /// override the clip everyone else holds with the same one.
+    async fn initial(&self) -> Option<Content> {
+        let sent = self.remote.remote().sent().await;
+        match &self.cipher {
+            Some(cipher) if sent.is_sealed() => cipher.open(&sent).ok(),
+            _ => Some(sent.unstamped()),
+        }
+    }
+
+    /// Whether a remote clip should be applied: not an echo of our own and
+    /// newer than the latest clip, unstamped clips always are.
+    async fn accept(&self, content: &Content) -> bool {
+        let Some(stamp) = &content.stamp else {
+            return true;
+        };
+        if stamp.origin == self.stamper.origin() {
+            debug!("Ignore echo [Remote] {stamp}");
+            return false;
+        }
+        if stamp.is_too_far_ahead() {
+            warn!("Ignore [Remote] {stamp}, too far ahead of the local clock");
+            return false;
+        }
+        let mut latest = self.latest.lock().await;
+        if !stamp.is_newer(latest.as_ref()) {
+            info!("Ignore older [Remote] {stamp}");
+            return false;
+        }
+        self.stamper.observe(stamp);
+        *latest = Some(stamp.clone());
+        true
+    }
+
+    /// Turn a remote clip back into plaintext for the local clipboard.
+    async fn open(&self, content: Content) -> Result<Content> {
+        let content = match &self.cipher {
+            Some(cipher) => cipher.open(&content)?,
+            None if content.is_sealed() => {
+                return Err(eyre!(
+                    "Received an encrypted clip, set the passphrase to read it"
+                ))
+            }
+            None => content.unstamped(),
+        };
+        *self.synced.lock().await = Some(content.clone());
+        Ok(content)
+    }
+}
+
+/// Marks a remote clip as being applied until dropped.
+struct Applying<'a>(&'a AtomicU64);
+
+impl<'a> Applying<'a> {
+    fn start(applying: &'a AtomicU64) -> Self {
+        applying.fetch_add(1, Ordering::AcqRel);
+        Self(applying)
+    }
+}
+
+impl Drop for Applying<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+/// Resolves when the backend reports a change, never if it cannot.
+async fn local_changed(watcher: &mut Option<watch::Receiver<()>>) {
+    if let Some(receiver) = watcher {
+        if receiver.changed().await.is_ok() {
+            return;
+        }
+    }
+    std::future::pending().await
 }
 
 pub trait VirtualClipboard: Clone + Send + Sync {
+    /// The node id clips copied here are stamped with.
+    fn id(&self) -> &str;
+
+    /// Stamps the clips of this node, shared with whatever else stamps them.
+    fn stamper(&self) -> Stamper {
+        Stamper::new(self.id())
+    }
+
     fn remote(&self) -> &RemoteClipboard;
 
-    fn shutdown(self) -> Result<()>;
+    /// The nodes this one is connected to.
+    fn peers(&self) -> Vec<Peer> {
+        Vec::new()
+    }
+
+    /// The clips this node has seen, if it keeps them.
+    fn history(&self) -> Option<&History> {
+        None
+    }
+
+    /// Stop syncing with the other nodes, once the cancel token fired.
+    fn shutdown(self) -> impl Future<Output = crate::Result<()>> + Send;
 }
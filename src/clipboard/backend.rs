@@ -0,0 +1,65 @@
+pub mod file_backend;
+pub mod memory_backend;
+pub mod system_backend;
+mod system_owner;
+mod system_reader;
+mod system_watcher;
+
+use std::fmt::{Display, Formatter};
+
+use color_eyre::Result;
+use tokio::sync::watch;
+
+use crate::Content;
+
+pub use file_backend::FileBackend;
+pub use memory_backend::MemoryBackend;
+pub use system_backend::SystemBackend;
+
+/// A source and sink for the local clipboard content.
+///
+/// `LocalClipboard` is generic over this trait so the sync engine can run
+/// against the OS clipboard, an in-memory buffer or a plain file.
+pub trait ClipboardBackend: Send + 'static {
+    /// Read the current clipboard content.
+    ///
+    /// Fail with a [`Transient`] error when a later read may succeed, the
+    /// engine retries those instead of stopping.
+    fn get(&mut self) -> Result<Content>;
+
+    /// Replace the clipboard content.
+    ///
+    /// Backends that cannot hold some representations keep what they can.
+    fn set(&mut self, content: Content) -> Result<()>;
+
+    /// Subscribe to change notifications, if the backend can produce them.
+    ///
+    /// Backends returning `None` are polled instead.
+    fn watch(&mut self) -> Option<watch::Receiver<()>> {
+        None
+    }
+}
+
+/// Why a clipboard could not be read this time, a later read may succeed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Transient {
+    /// Nothing was copied, or only what cannot be told apart from nothing.
+    Empty,
+    /// What was copied has no representation the backend reads, like an
+    /// image.
+    UnsupportedFormat,
+    /// The application holding the clipboard did not answer in time.
+    OwnerBusy,
+}
+
+impl Display for Transient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transient::Empty => write!(f, "The clipboard is empty"),
+            Transient::UnsupportedFormat => write!(f, "The clipboard holds no text"),
+            Transient::OwnerBusy => write!(f, "The clipboard owner did not answer"),
+        }
+    }
+}
+
+impl std::error::Error for Transient {}
@@ -0,0 +1,41 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use color_eyre::eyre::Context;
+use color_eyre::Result;
+use tracing::debug;
+
+use crate::clipboard::backend::ClipboardBackend;
+use crate::Content;
+
+/// A clipboard stored in a plain text file, a missing file reads as empty.
+///
+/// Only the plain text representation is kept.
+#[derive(Clone)]
+pub struct FileBackend {
+    path: PathBuf,
+}
+
+impl FileBackend {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl ClipboardBackend for FileBackend {
+    fn get(&mut self) -> Result<Content> {
+        match fs::read_to_string(&self.path) {
+            Ok(text) => Ok(Content::from_text(text)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Content::default()),
+            Err(e) => Err(e).with_context(|| format!("Read {:?}", self.path)),
+        }
+    }
+
+    fn set(&mut self, content: Content) -> Result<()> {
+        if !content.representations.is_empty() {
+            debug!("Drop unsupported representations of: {}", content);
+        }
+        fs::write(&self.path, content.text).with_context(|| format!("Write {:?}", self.path))
+    }
+}
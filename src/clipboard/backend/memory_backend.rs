@@ -0,0 +1,49 @@
+use std::sync::{Arc, Mutex};
+
+use color_eyre::Result;
+use tokio::sync::watch;
+
+use crate::clipboard::backend::ClipboardBackend;
+use crate::Content;
+
+/// A clipboard that only lives in memory.
+///
+/// Clones share the same content, so a test can keep a handle to play the
+/// role of the user while the sync engine owns another one.
+#[derive(Clone)]
+pub struct MemoryBackend {
+    content: Arc<Mutex<Content>>,
+    notifier: Arc<watch::Sender<()>>,
+}
+
+impl MemoryBackend {
+    pub fn new(initial: impl Into<Content>) -> Self {
+        let (notifier, _) = watch::channel(());
+        Self {
+            content: Arc::new(Mutex::new(initial.into())),
+            notifier: Arc::new(notifier),
+        }
+    }
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self::new(Content::default())
+    }
+}
+
+impl ClipboardBackend for MemoryBackend {
+    fn get(&mut self) -> Result<Content> {
+        Ok(self.content.lock().unwrap().clone())
+    }
+
+    fn set(&mut self, content: Content) -> Result<()> {
+        *self.content.lock().unwrap() = content;
+        self.notifier.send_replace(());
+        Ok(())
+    }
+
+    fn watch(&mut self) -> Option<watch::Receiver<()>> {
+        Some(self.notifier.subscribe())
+    }
+}
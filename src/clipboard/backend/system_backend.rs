@@ -0,0 +1,116 @@
+use std::error::Error;
+use std::string::FromUtf8Error;
+
+use clipboard::ClipboardProvider;
+use color_eyre::eyre::eyre;
+use color_eyre::{Report, Result};
+use tokio::sync::watch;
+use tracing::debug;
+
+use crate::clipboard::backend::system_owner::SelectionOwner;
+use crate::clipboard::backend::system_reader::{is_hint, SelectionReader};
+use crate::clipboard::backend::system_watcher::Watcher;
+use crate::clipboard::backend::{ClipboardBackend, Transient};
+use crate::Content;
+
+/// The clipboard of the operating system, backed by the `clipboard` crate.
+///
+/// The `clipboard` crate only speaks plain text. On X11 clips are written
+/// with every representation and served for as long as the backend lives,
+/// elsewhere other representations are dropped when writing. HTML, images
+/// and password manager hints are read on X11 and Wayland, see
+/// [`SelectionReader`].
+pub struct SystemBackend {
+    context: clipboard::ClipboardContext,
+    reader: Option<SelectionReader>,
+    owner: Option<SelectionOwner>,
+    /// Started on the first watch, stopped with the backend.
+    watcher: Option<Watcher>,
+}
+
+impl SystemBackend {
+    pub fn new() -> Result<Self> {
+        let context = ClipboardProvider::new().map_err(|e| eyre!("{:?}", e))?;
+        Ok(Self {
+            context,
+            reader: SelectionReader::new(),
+            owner: SelectionOwner::new(),
+            watcher: None,
+        })
+    }
+}
+
+impl ClipboardBackend for SystemBackend {
+    fn get(&mut self) -> Result<Content> {
+        // Without text, what else was copied may still be read.
+        let mut unreadable = None;
+        let text = match self.context.get_contents() {
+            Ok(text) => text,
+            Err(e) => match transient(e.as_ref()) {
+                Some(transient @ (Transient::Empty | Transient::UnsupportedFormat)) => {
+                    unreadable = Some(report(Some(transient), e));
+                    String::new()
+                }
+                transient => return Err(report(transient, e)),
+            },
+        };
+        let mut content = Content::from_text(text);
+        if let Some(reader) = &mut self.reader {
+            content.representations.extend(reader.read());
+        }
+        // X11 reads an empty clipboard and one without text alike.
+        let hints_only = content.representations.iter().all(is_hint);
+        if content.text.is_empty() && hints_only {
+            return Err(unreadable.unwrap_or_else(|| Transient::Empty.into()));
+        }
+        Ok(content)
+    }
+
+    fn set(&mut self, content: Content) -> Result<()> {
+        if let Some(owner) = &mut self.owner {
+            return owner.own(&content);
+        }
+        if !content.representations.is_empty() {
+            debug!("Drop unsupported representations of: {}", content);
+        }
+        self.context
+            .set_contents(content.text)
+            .map_err(|e| eyre!("{:?}", e))
+    }
+
+    /// Selection events on X11 and Wayland, nothing elsewhere.
+    fn watch(&mut self) -> Option<watch::Receiver<()>> {
+        if self.watcher.is_none() {
+            self.watcher = Watcher::spawn();
+        }
+        self.watcher.as_ref().map(Watcher::subscribe)
+    }
+}
+
+/// Tell the failures a later read may not have apart, the `clipboard` crate
+/// only hands out boxed errors.
+fn transient(error: &(dyn Error + 'static)) -> Option<Transient> {
+    if error.is::<FromUtf8Error>() {
+        Some(Transient::UnsupportedFormat)
+    } else if error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied)
+    {
+        // Another application has the clipboard open on Windows.
+        Some(Transient::OwnerBusy)
+    } else {
+        match error.to_string() {
+            message if message == "Selection timed out" => Some(Transient::OwnerBusy),
+            message if message.ends_with("returned empty") => Some(Transient::Empty),
+            message if message.ends_with("returned null") => Some(Transient::UnsupportedFormat),
+            _ => None,
+        }
+    }
+}
+
+fn report(transient: Option<Transient>, error: Box<dyn Error>) -> Report {
+    match transient {
+        Some(transient) => Report::new(transient).wrap_err(format!("{:?}", error)),
+        None => eyre!("{:?}", error),
+    }
+}
@@ -0,0 +1,408 @@
+use color_eyre::Result;
+
+use crate::Content;
+
+/// Owns the system clipboard for the clips written to it, serving them to
+/// other applications until one of them copies something else.
+///
+/// On X11 a clip is gone once the process that wrote it stops answering, so
+/// the owner lives as long as the backend and serves every representation
+/// of the clip, not just its text.
+pub struct SelectionOwner {
+    inner: platform::Owner,
+}
+
+impl SelectionOwner {
+    /// `None` if the platform keeps clips by itself.
+    pub fn new() -> Option<Self> {
+        platform::Owner::new().map(|inner| Self { inner })
+    }
+
+    /// Take the clipboard over and serve `content` from now on.
+    pub fn own(&mut self, content: &Content) -> Result<()> {
+        self.inner.own(content)
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use tracing::{debug, info};
+    use x11rb::connection::{Connection, RequestConnection as _};
+    use x11rb::protocol::xproto::{
+        Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, CreateWindowAux, EventMask,
+        PropMode, Property, PropertyNotifyEvent, SelectionNotifyEvent, SelectionRequestEvent,
+        Window, WindowClass, SELECTION_NOTIFY_EVENT,
+    };
+    use x11rb::protocol::Event;
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::{CURRENT_TIME, NONE};
+
+    use crate::content::TEXT_PLAIN;
+    use crate::Content;
+
+    /// What the text of a clip is offered as, besides its MIME type.
+    const TEXT_TARGETS: [&str; 2] = ["UTF8_STRING", "text/plain;charset=utf-8"];
+
+    /// How long a requestor may take to ask for the next piece of a large
+    /// clip before the transfer is dropped.
+    const TRANSFER_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// The targets of the clip served and their data.
+    type Served = Arc<Mutex<Vec<(Atom, Arc<[u8]>)>>>;
+
+    pub struct Owner {
+        connection: Arc<RustConnection>,
+        window: Window,
+        clipboard: Atom,
+        text_targets: Vec<Atom>,
+        /// Latin-1 text, only offered for ASCII clips.
+        string: Atom,
+        served: Served,
+    }
+
+    impl Owner {
+        pub fn new() -> Option<Self> {
+            match Self::connect() {
+                Ok(owner) => Some(owner),
+                Err(e) => {
+                    debug!("Own [Local] unavailable: {:?}", e);
+                    None
+                }
+            }
+        }
+
+        fn connect() -> Result<Self> {
+            let (connection, screen) = x11rb::connect(None)?;
+            let root = connection.setup().roots[screen].root;
+            let window = connection.generate_id()?;
+            connection.create_window(
+                0,
+                window,
+                root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_ONLY,
+                x11rb::COPY_FROM_PARENT,
+                // Destroying the window ends the thread serving it.
+                &CreateWindowAux::new().event_mask(EventMask::STRUCTURE_NOTIFY),
+            )?;
+            let connection = Arc::new(connection);
+            let clipboard = intern(&connection, "CLIPBOARD")?;
+            let targets = intern(&connection, "TARGETS")?;
+            let text_targets = TEXT_TARGETS
+                .iter()
+                .map(|name| intern(&connection, name))
+                .collect::<Result<_>>()?;
+            let string = intern(&connection, "STRING")?;
+            let incr = intern(&connection, "INCR")?;
+            connection.flush()?;
+
+            let served = Served::default();
+            let server = Server {
+                connection: connection.clone(),
+                window,
+                clipboard,
+                targets,
+                incr,
+                served: served.clone(),
+                transfers: HashMap::new(),
+            };
+            std::thread::Builder::new()
+                .name("synclip-x11-owner".into())
+                .spawn(move || server.run())?;
+            Ok(Self {
+                connection,
+                window,
+                clipboard,
+                text_targets,
+                string,
+                served,
+            })
+        }
+
+        pub fn own(&mut self, content: &Content) -> Result<()> {
+            let mut served = Vec::new();
+            // An image alone is not offered as empty text too.
+            if !content.text.is_empty() {
+                for &target in &self.text_targets {
+                    served.push((target, content.text.clone().into_bytes().into()));
+                }
+                served.push((
+                    intern(&self.connection, TEXT_PLAIN)?,
+                    content.text.clone().into_bytes().into(),
+                ));
+                if content.text.is_ascii() {
+                    served.push((self.string, content.text.clone().into_bytes().into()));
+                }
+            }
+            for representation in &content.representations {
+                let target = intern(&self.connection, &representation.mime_type)?;
+                served.retain(|(served, _)| *served != target);
+                served.push((target, representation.data.clone().into()));
+            }
+            // Held until the clipboard is ours, a hand off seen meanwhile is
+            // an older one.
+            let mut current = self.served.lock().unwrap();
+            *current = served;
+            self.connection
+                .set_selection_owner(self.window, self.clipboard, CURRENT_TIME)?;
+            let owner = self
+                .connection
+                .get_selection_owner(self.clipboard)?
+                .reply()?
+                .owner;
+            if owner != self.window {
+                current.clear();
+                return Err(eyre!("Another application kept the clipboard"));
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for Owner {
+        fn drop(&mut self) {
+            let _ = self.connection.destroy_window(self.window);
+            let _ = self.connection.flush();
+        }
+    }
+
+    /// A clip too large for one property, sent piece by piece with the INCR
+    /// protocol: each time the requestor deletes the property, the next
+    /// piece is stored there, an empty one ending the transfer.
+    struct Transfer {
+        target: Atom,
+        data: Arc<[u8]>,
+        sent: usize,
+        /// When the requestor last asked for a piece.
+        active: Instant,
+    }
+
+    /// Answers the applications asking for the clip, on its own thread.
+    struct Server {
+        connection: Arc<RustConnection>,
+        window: Window,
+        clipboard: Atom,
+        targets: Atom,
+        incr: Atom,
+        served: Served,
+        /// By requestor window and property.
+        transfers: HashMap<(Window, Atom), Transfer>,
+    }
+
+    impl Server {
+        fn run(mut self) {
+            loop {
+                let result = match self.connection.wait_for_event() {
+                    Ok(Event::SelectionRequest(request)) => self.answer(request),
+                    Ok(Event::SelectionClear(clear)) if clear.selection == self.clipboard => {
+                        self.hand_off()
+                    }
+                    Ok(Event::PropertyNotify(notify)) if notify.state == Property::DELETE => {
+                        self.send_piece(notify)
+                    }
+                    Ok(Event::DestroyNotify(destroy)) if destroy.window == self.window => break,
+                    Ok(Event::DestroyNotify(destroy)) => {
+                        self.transfers
+                            .retain(|(requestor, _), _| *requestor != destroy.window);
+                        Ok(())
+                    }
+                    Ok(_) => Ok(()),
+                    Err(e) => {
+                        debug!("Own [Local] connection lost: {:?}", e);
+                        break;
+                    }
+                };
+                if let Err(e) = result {
+                    debug!("Own [Local] error: {:?}", e);
+                }
+            }
+        }
+
+        /// Stop serving once another application owns the clipboard, unless
+        /// the clipboard was taken back since.
+        fn hand_off(&self) -> Result<()> {
+            let mut served = self.served.lock().unwrap();
+            let owner = self
+                .connection
+                .get_selection_owner(self.clipboard)?
+                .reply()?
+                .owner;
+            if owner != self.window {
+                info!("Clipboard [Local] taken over by another application");
+                served.clear();
+            }
+            Ok(())
+        }
+
+        /// Store the requested target on the requestor's window and tell it,
+        /// with no property if the clip has no such target.
+        fn answer(&mut self, request: SelectionRequestEvent) -> Result<()> {
+            // Obsolete clients leave the property to us.
+            let mut property = match request.property {
+                NONE => request.target,
+                property => property,
+            };
+            let served = self.served.clone();
+            let served = served.lock().unwrap();
+            if request.selection != self.clipboard || served.is_empty() {
+                property = NONE;
+            } else if request.target == self.targets {
+                let mut targets = vec![self.targets];
+                targets.extend(served.iter().map(|(target, _)| *target));
+                self.connection.change_property32(
+                    PropMode::REPLACE,
+                    request.requestor,
+                    property,
+                    AtomEnum::ATOM,
+                    &targets,
+                )?;
+            } else {
+                match served.iter().find(|(target, _)| *target == request.target) {
+                    Some((target, data)) if data.len() < self.max_property() => {
+                        self.connection.change_property8(
+                            PropMode::REPLACE,
+                            request.requestor,
+                            property,
+                            *target,
+                            data,
+                        )?;
+                    }
+                    Some((target, data)) => {
+                        self.start_transfer(request.requestor, property, *target, data.clone())?;
+                    }
+                    None => property = NONE,
+                }
+            }
+            drop(served);
+            self.connection.send_event(
+                false,
+                request.requestor,
+                EventMask::NO_EVENT,
+                SelectionNotifyEvent {
+                    response_type: SELECTION_NOTIFY_EVENT,
+                    sequence: 0,
+                    time: request.time,
+                    requestor: request.requestor,
+                    selection: request.selection,
+                    target: request.target,
+                    property,
+                },
+            )?;
+            self.connection.flush()?;
+            Ok(())
+        }
+
+        /// Announce a large clip with its size, the requestor then deletes the
+        /// property to ask for the first piece.
+        fn start_transfer(
+            &mut self,
+            requestor: Window,
+            property: Atom,
+            target: Atom,
+            data: Arc<[u8]>,
+        ) -> Result<()> {
+            debug!("Send [Local] {} bytes incrementally", data.len());
+            let now = Instant::now();
+            self.transfers
+                .retain(|_, transfer| now - transfer.active < TRANSFER_TIMEOUT);
+            // Only the deletions of this client's own properties are seen,
+            // and the requestor going away.
+            self.connection.change_window_attributes(
+                requestor,
+                &ChangeWindowAttributesAux::new()
+                    .event_mask(EventMask::PROPERTY_CHANGE | EventMask::STRUCTURE_NOTIFY),
+            )?;
+            self.connection.change_property32(
+                PropMode::REPLACE,
+                requestor,
+                property,
+                self.incr,
+                &[data.len() as u32],
+            )?;
+            self.transfers.insert(
+                (requestor, property),
+                Transfer {
+                    target,
+                    data,
+                    sent: 0,
+                    active: now,
+                },
+            );
+            Ok(())
+        }
+
+        /// Store the next piece of a large clip once the requestor took the
+        /// previous one.
+        fn send_piece(&mut self, notify: PropertyNotifyEvent) -> Result<()> {
+            let key = (notify.window, notify.atom);
+            let piece = self.max_property();
+            let Some(transfer) = self.transfers.get_mut(&key) else {
+                return Ok(());
+            };
+            let end = transfer.data.len().min(transfer.sent + piece);
+            self.connection.change_property8(
+                PropMode::REPLACE,
+                notify.window,
+                notify.atom,
+                transfer.target,
+                &transfer.data[transfer.sent..end],
+            )?;
+            // The empty piece after the last one ends the transfer.
+            if transfer.sent == end {
+                self.transfers.remove(&key);
+                if !self.transfers.keys().any(|(window, _)| *window == key.0) {
+                    self.connection.change_window_attributes(
+                        key.0,
+                        &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT),
+                    )?;
+                }
+            } else {
+                transfer.sent = end;
+                transfer.active = Instant::now();
+            }
+            self.connection.flush()?;
+            Ok(())
+        }
+
+        /// The most bytes a single property change may carry.
+        fn max_property(&self) -> usize {
+            self.connection.maximum_request_bytes().saturating_sub(32)
+        }
+    }
+
+    fn intern(connection: &RustConnection, name: &str) -> Result<Atom> {
+        Ok(connection
+            .intern_atom(false, name.as_bytes())?
+            .reply()?
+            .atom)
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+mod platform {
+    use color_eyre::Result;
+
+    use crate::Content;
+
+    pub struct Owner;
+
+    impl Owner {
+        pub fn new() -> Option<Self> {
+            None
+        }
+
+        pub fn own(&mut self, _: &Content) -> Result<()> {
+            Ok(())
+        }
+    }
+}
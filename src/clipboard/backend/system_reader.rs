@@ -0,0 +1,404 @@
+use tracing::debug;
+
+use crate::filter::HINTS;
+use crate::Representation;
+
+/// Reads what the owner of the system clipboard offers besides text: rich
+/// representations like HTML or images, and password manager hints, see
+/// [`crate::filter::HINTS`].
+pub struct SelectionReader {
+    inner: platform::Reader,
+}
+
+impl SelectionReader {
+    /// `None` if the platform has no way to tell.
+    pub fn new() -> Option<Self> {
+        platform::Reader::new().map(|inner| Self { inner })
+    }
+
+    /// What was offered besides text, none if it cannot be read.
+    pub fn read(&mut self) -> Vec<Representation> {
+        match self.inner.read() {
+            Ok(representations) => representations,
+            Err(e) => {
+                debug!("Read [Local] representations error: {:?}", e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Whether a representation is a hint rather than the clip itself.
+pub fn is_hint(representation: &Representation) -> bool {
+    HINTS.contains(&representation.mime_type.as_str())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use std::env;
+    use std::io::{ErrorKind, Read};
+    use std::os::fd::{AsFd, BorrowedFd};
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use nix::errno::Errno;
+    use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+    use tracing::{debug, info};
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{
+        Atom, AtomEnum, ConnectionExt as _, CreateWindowAux, EventMask, Property, Window,
+        WindowClass,
+    };
+    use x11rb::protocol::Event;
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::{CURRENT_TIME, NONE};
+
+    use crate::content::{IMAGE_PNG, TEXT_HTML, TEXT_RTF, TEXT_URI_LIST};
+    use crate::filter::{HINTS, PASSWORD_MANAGER_HINT};
+    use crate::Representation;
+
+    /// The representations read besides text, when the clipboard owner offers
+    /// them.
+    const RICH: [&str; 4] = [TEXT_HTML, TEXT_RTF, TEXT_URI_LIST, IMAGE_PNG];
+
+    /// How long the clipboard owner gets to answer, and to send each piece
+    /// of a large clip.
+    const TIMEOUT: Duration = Duration::from_millis(200);
+
+    /// Fetch what is worth reading of the MIME types `offered`, rich
+    /// representations first, `fetch` answering `None` for those refused.
+    fn collect(
+        offered: &[&'static str],
+        mut fetch: impl FnMut(&'static str) -> Result<Option<Vec<u8>>>,
+    ) -> Result<Vec<Representation>> {
+        let mut representations = Vec::new();
+        for &mime_type in RICH.iter().chain(HINTS.iter()) {
+            if !offered.contains(&mime_type) {
+                continue;
+            }
+            // The other hints mean something by being offered at all.
+            let data = if RICH.contains(&mime_type) || mime_type == PASSWORD_MANAGER_HINT {
+                match fetch(mime_type)? {
+                    Some(data) => data,
+                    None => continue,
+                }
+            } else {
+                Vec::new()
+            };
+            if data.is_empty() && RICH.contains(&mime_type) {
+                continue;
+            }
+            representations.push(Representation {
+                mime_type: mime_type.to_owned(),
+                data,
+            });
+        }
+        Ok(representations)
+    }
+
+    /// Every MIME type worth reading.
+    fn wanted() -> impl Iterator<Item = &'static str> {
+        RICH.into_iter().chain(HINTS)
+    }
+
+    pub enum Reader {
+        Wayland,
+        X11(Box<X11Reader>),
+    }
+
+    impl Reader {
+        pub fn new() -> Option<Self> {
+            if env::var_os("WAYLAND_DISPLAY").is_some() {
+                match wl_paste(&["--version"]) {
+                    Ok(_) => {
+                        info!("Read [Local] representations with wl-paste");
+                        return Some(Reader::Wayland);
+                    }
+                    Err(e) => debug!("Read [Local] with wl-paste unavailable: {:?}", e),
+                }
+            }
+            match X11Reader::connect() {
+                Ok(reader) => Some(Reader::X11(Box::new(reader))),
+                Err(e) => {
+                    debug!("Read [Local] representations unavailable: {:?}", e);
+                    None
+                }
+            }
+        }
+
+        pub fn read(&mut self) -> Result<Vec<Representation>> {
+            match self {
+                Reader::Wayland => {
+                    let types = wl_paste(&["--list-types"])?;
+                    let types = String::from_utf8_lossy(&types);
+                    let offered = wanted()
+                        .filter(|wanted| types.lines().any(|offered| offered == *wanted))
+                        .collect::<Vec<_>>();
+                    collect(&offered, |mime_type| {
+                        wl_paste(&["--no-newline", "--type", mime_type]).map(Some)
+                    })
+                }
+                Reader::X11(reader) => reader.read(),
+            }
+        }
+    }
+
+    /// `wl-paste` speaks the data-control protocol so we do not have to, it
+    /// is given [`TIMEOUT`] to answer.
+    fn wl_paste(args: &[&str]) -> Result<Vec<u8>> {
+        let mut child = Command::new("wl-paste")
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut stdout = child.stdout.take().ok_or_else(|| eyre!("No stdout"))?;
+        let mut output = Vec::new();
+        let mut buffer = [0; 64 * 1024];
+        let result = loop {
+            if let Err(e) = wait(stdout.as_fd(), Instant::now() + TIMEOUT) {
+                break Err(e);
+            }
+            match stdout.read(&mut buffer) {
+                Ok(0) => break Ok(()),
+                Ok(read) => output.extend_from_slice(&buffer[..read]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => break Err(e.into()),
+            }
+        };
+        if result.is_err() {
+            let _ = child.kill();
+        }
+        let status = child.wait()?;
+        result?;
+        if !status.success() {
+            return Err(eyre!("wl-paste {} failed: {status}", args.join(" ")));
+        }
+        Ok(output)
+    }
+
+    /// Block until `fd` has something to read, or fail at `deadline`.
+    fn wait(fd: BorrowedFd<'_>, deadline: Instant) -> Result<()> {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+        match poll(&mut fds, PollTimeout::try_from(remaining)?) {
+            Ok(0) => Err(eyre!("Clipboard owner did not answer")),
+            Ok(_) | Err(Errno::EINTR) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub struct X11Reader {
+        connection: RustConnection,
+        window: Window,
+        clipboard: Atom,
+        targets: Atom,
+        timestamp: Atom,
+        incr: Atom,
+        property: Atom,
+        /// Every MIME type worth reading, by atom.
+        wanted: Vec<(Atom, &'static str)>,
+        /// The owner and the time it took the clipboard the last read was
+        /// of, and what it read.
+        last: Option<((Window, u32), Vec<Representation>)>,
+    }
+
+    impl X11Reader {
+        fn connect() -> Result<Self> {
+            let (connection, screen) = x11rb::connect(None)?;
+            let root = connection.setup().roots[screen].root;
+            let window = connection.generate_id()?;
+            connection.create_window(
+                0,
+                window,
+                root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_ONLY,
+                x11rb::COPY_FROM_PARENT,
+                // Large clips come in pieces, each one a property change.
+                &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+            )?;
+            let atom = |name: &str| -> Result<Atom> {
+                Ok(connection
+                    .intern_atom(false, name.as_bytes())?
+                    .reply()?
+                    .atom)
+            };
+            let clipboard = atom("CLIPBOARD")?;
+            let targets = atom("TARGETS")?;
+            let timestamp = atom("TIMESTAMP")?;
+            let incr = atom("INCR")?;
+            let property = atom("SYNCLIP_READ")?;
+            let wanted = wanted()
+                .map(|name| Ok((atom(name)?, name)))
+                .collect::<Result<_>>()?;
+            Ok(Self {
+                connection,
+                window,
+                clipboard,
+                targets,
+                timestamp,
+                incr,
+                property,
+                wanted,
+                last: None,
+            })
+        }
+
+        fn read(&mut self) -> Result<Vec<Representation>> {
+            let targets = match self.convert(self.targets)? {
+                Some(targets) => atoms(&targets),
+                None => return Ok(Vec::new()),
+            };
+            // A clip is only read again once another one was copied.
+            let copied = if targets.contains(&self.timestamp) {
+                let owner = self
+                    .connection
+                    .get_selection_owner(self.clipboard)?
+                    .reply()?
+                    .owner;
+                let time = self.convert(self.timestamp)?.map(|time| atoms(&time));
+                time.and_then(|time| time.first().copied())
+                    .map(|time| (owner, time))
+            } else {
+                None
+            };
+            if let (Some(copied), Some((last, representations))) = (copied, &self.last) {
+                if copied == *last {
+                    return Ok(representations.clone());
+                }
+            }
+            let offered = self
+                .wanted
+                .iter()
+                .filter(|(atom, _)| targets.contains(atom))
+                .map(|(_, name)| *name)
+                .collect::<Vec<_>>();
+            let representations = collect(&offered, |name| {
+                let atom = self
+                    .wanted
+                    .iter()
+                    .find(|(_, wanted)| *wanted == name)
+                    .map(|(atom, _)| *atom)
+                    .unwrap_or(NONE);
+                self.convert(atom)
+            })?;
+            self.last = copied.map(|copied| (copied, representations.clone()));
+            Ok(representations)
+        }
+
+        /// Ask the clipboard owner for `target`, `None` if there is no
+        /// owner or it refuses.
+        fn convert(&self, target: Atom) -> Result<Option<Vec<u8>>> {
+            self.connection.convert_selection(
+                self.window,
+                self.clipboard,
+                target,
+                self.property,
+                CURRENT_TIME,
+            )?;
+            self.connection.flush()?;
+            let deadline = Instant::now() + TIMEOUT;
+            let notify = loop {
+                match self.connection.poll_for_event()? {
+                    Some(Event::SelectionNotify(notify)) if notify.target == target => {
+                        break notify
+                    }
+                    Some(_) => continue,
+                    None => wait(self.connection.stream().as_fd(), deadline)?,
+                }
+            };
+            if notify.property == NONE {
+                return Ok(None);
+            }
+            // Deleting the property is what asks for the next piece.
+            let reply = self
+                .connection
+                .get_property(
+                    true,
+                    self.window,
+                    self.property,
+                    AtomEnum::ANY,
+                    0,
+                    u32::MAX / 4,
+                )?
+                .reply()?;
+            if reply.type_ != self.incr {
+                return Ok(Some(reply.value));
+            }
+            self.connection.flush()?;
+            self.incremental().map(Some)
+        }
+
+        /// Take a clip too large for one property piece by piece, the INCR
+        /// protocol, until the owner sends an empty piece.
+        fn incremental(&self) -> Result<Vec<u8>> {
+            let mut data = Vec::new();
+            loop {
+                let deadline = Instant::now() + TIMEOUT;
+                loop {
+                    match self.connection.poll_for_event()? {
+                        Some(Event::PropertyNotify(notify))
+                            if notify.window == self.window
+                                && notify.atom == self.property
+                                && notify.state == Property::NEW_VALUE =>
+                        {
+                            break
+                        }
+                        Some(_) => continue,
+                        None => wait(self.connection.stream().as_fd(), deadline)?,
+                    }
+                }
+                let piece = self
+                    .connection
+                    .get_property(
+                        true,
+                        self.window,
+                        self.property,
+                        AtomEnum::ANY,
+                        0,
+                        u32::MAX / 4,
+                    )?
+                    .reply()?;
+                self.connection.flush()?;
+                if piece.value.is_empty() {
+                    return Ok(data);
+                }
+                data.extend_from_slice(&piece.value);
+            }
+        }
+    }
+
+    /// The atoms of a TARGETS or TIMESTAMP reply.
+    fn atoms(value: &[u8]) -> Vec<Atom> {
+        value
+            .chunks_exact(4)
+            .map(|atom| u32::from_ne_bytes([atom[0], atom[1], atom[2], atom[3]]))
+            .collect()
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+mod platform {
+    use color_eyre::Result;
+
+    use crate::Representation;
+
+    pub struct Reader;
+
+    impl Reader {
+        pub fn new() -> Option<Self> {
+            None
+        }
+
+        pub fn read(&mut self) -> Result<Vec<Representation>> {
+            Ok(Vec::new())
+        }
+    }
+}
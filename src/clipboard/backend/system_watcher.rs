@@ -0,0 +1,204 @@
+use tokio::sync::watch;
+
+/// Reports changes of the system clipboard until dropped, which stops
+/// whatever it watches with.
+pub struct Watcher {
+    receiver: watch::Receiver<()>,
+    _inner: platform::Watcher,
+}
+
+impl Watcher {
+    /// `None` if the platform has no way to tell and the clipboard must be
+    /// polled.
+    pub fn spawn() -> Option<Self> {
+        platform::spawn().map(|(receiver, inner)| Self {
+            receiver,
+            _inner: inner,
+        })
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.receiver.clone()
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use std::env;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::Arc;
+    use std::thread::JoinHandle;
+
+    use color_eyre::eyre::eyre;
+    use color_eyre::Result;
+    use tracing::{debug, info};
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+    use x11rb::protocol::xproto::{
+        ConnectionExt as _, CreateWindowAux, EventMask, Window, WindowClass,
+    };
+    use x11rb::protocol::Event;
+    use x11rb::rust_connection::RustConnection;
+
+    use super::*;
+
+    type Spawned = (watch::Receiver<()>, Watcher);
+    type Spawn = fn() -> Result<Spawned>;
+
+    /// The process or thread reporting changes, torn down on drop.
+    pub enum Watcher {
+        Wayland {
+            child: Child,
+            thread: Option<JoinHandle<()>>,
+        },
+        X11 {
+            connection: Arc<RustConnection>,
+            window: Window,
+            thread: Option<JoinHandle<()>>,
+        },
+    }
+
+    impl Drop for Watcher {
+        fn drop(&mut self) {
+            let thread = match self {
+                // Its output ends with it, and so does the thread reading it.
+                Watcher::Wayland { child, thread } => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    thread.take()
+                }
+                Watcher::X11 {
+                    connection,
+                    window,
+                    thread,
+                } => {
+                    let _ = connection.destroy_window(*window);
+                    let _ = connection.flush();
+                    thread.take()
+                }
+            };
+            if let Some(thread) = thread {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    pub fn spawn() -> Option<Spawned> {
+        let watchers: [(&str, Spawn); 2] = [("Wayland data-control", wayland), ("X11 XFixes", x11)];
+        for (name, watcher) in watchers {
+            match watcher() {
+                Ok(spawned) => {
+                    info!("Watch [Local] with {name} events");
+                    return Some(spawned);
+                }
+                Err(e) => debug!("Watch [Local] with {name} unavailable: {:?}", e),
+            }
+        }
+        None
+    }
+
+    /// `wl-paste --watch` prints a line whenever the selection changes, it
+    /// speaks the data-control protocol so we do not have to.
+    fn wayland() -> Result<Spawned> {
+        if env::var_os("WAYLAND_DISPLAY").is_none() {
+            return Err(eyre!("WAYLAND_DISPLAY is not set"));
+        }
+        let mut child = Command::new("wl-paste")
+            .args(["--watch", "echo"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdout = child.stdout.take().ok_or_else(|| eyre!("No stdout"))?;
+        let (notifier, receiver) = watch::channel(());
+        let thread = std::thread::Builder::new()
+            .name("synclip-wayland-watch".into())
+            .spawn(move || {
+                for line in BufReader::new(stdout).lines() {
+                    if line.is_err() {
+                        break;
+                    }
+                    notifier.send_replace(());
+                }
+            });
+        let thread = match thread {
+            Ok(thread) => thread,
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(e.into());
+            }
+        };
+        let watcher = Watcher::Wayland {
+            child,
+            thread: Some(thread),
+        };
+        Ok((receiver, watcher))
+    }
+
+    /// XFixes reports every change of the CLIPBOARD selection owner.
+    fn x11() -> Result<Spawned> {
+        let (connection, screen) = x11rb::connect(None)?;
+        let root = connection.setup().roots[screen].root;
+        let window = connection.generate_id()?;
+        connection.create_window(
+            0,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            x11rb::COPY_FROM_PARENT,
+            // Destroying the window ends the thread watching with it.
+            &CreateWindowAux::new().event_mask(EventMask::STRUCTURE_NOTIFY),
+        )?;
+        connection.xfixes_query_version(5, 0)?.reply()?;
+        let clipboard = connection.intern_atom(false, b"CLIPBOARD")?.reply()?.atom;
+        connection.xfixes_select_selection_input(
+            window,
+            clipboard,
+            SelectionEventMask::SET_SELECTION_OWNER
+                | SelectionEventMask::SELECTION_WINDOW_DESTROY
+                | SelectionEventMask::SELECTION_CLIENT_CLOSE,
+        )?;
+        connection.flush()?;
+
+        let connection = Arc::new(connection);
+        let (notifier, receiver) = watch::channel(());
+        let events = connection.clone();
+        let thread = std::thread::Builder::new()
+            .name("synclip-x11-watch".into())
+            .spawn(move || {
+                while let Ok(event) = events.wait_for_event() {
+                    match event {
+                        Event::XfixesSelectionNotify(_) => {
+                            notifier.send_replace(());
+                        }
+                        Event::DestroyNotify(destroy) if destroy.window == window => break,
+                        _ => {}
+                    }
+                }
+            })?;
+        let watcher = Watcher::X11 {
+            connection,
+            window,
+            thread: Some(thread),
+        };
+        Ok((receiver, watcher))
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+mod platform {
+    use super::*;
+
+    pub struct Watcher;
+
+    pub fn spawn() -> Option<(watch::Receiver<()>, Watcher)> {
+        None
+    }
+}
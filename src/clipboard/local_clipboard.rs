@@ -1,36 +1,117 @@
-use clipboard::ClipboardProvider;
-use color_eyre::eyre::eyre;
-use color_eyre::Result;
+use color_eyre::eyre::{eyre, WrapErr};
+use color_eyre::Report;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::error;
+use std::time::Duration;
+use tokio::sync::{watch, Mutex};
+use tracing::info;
 
-type ClipboardContext = Arc<Mutex<clipboard::ClipboardContext>>;
+use crate::clipboard::backend::{ClipboardBackend, SystemBackend, Transient};
+use crate::error::{Error, Result};
+use crate::Content;
 
-#[derive(Clone)]
-pub struct LocalClipboard {
-    context: ClipboardContext,
+/// How often a write is read back before it counts as lost.
+const READ_BACK_ATTEMPTS: u32 = 5;
+const READ_BACK_DELAY: Duration = Duration::from_millis(20);
+
+pub struct LocalClipboard<B: ClipboardBackend = SystemBackend> {
+    backend: Arc<Mutex<B>>,
+}
+
+impl<B: ClipboardBackend> Clone for LocalClipboard<B> {
+    fn clone(&self) -> Self {
+        Self {
+            backend: self.backend.clone(),
+        }
+    }
 }
 
-impl LocalClipboard {
+impl LocalClipboard<SystemBackend> {
     pub fn new() -> Result<Self> {
-        let context = ClipboardProvider::new().map_err(|e| eyre!("{:?}", e))?;
-        let context = Arc::new(Mutex::new(context));
-        Ok(Self { context })
+        let backend = SystemBackend::new().map_err(Error::backend)?;
+        Ok(Self::with_backend(backend))
+    }
+}
+
+impl<B: ClipboardBackend> LocalClipboard<B> {
+    pub fn with_backend(backend: B) -> Self {
+        let backend = Arc::new(Mutex::new(backend));
+        Self { backend }
+    }
+
+    /// Write `content` unless the clipboard holds it already, `false` then.
+    ///
+    /// Fails when the backend cannot write or does not read back the text
+    /// written, representations it cannot hold are not checked.
+    pub async fn set(&self, content: Content) -> Result<bool> {
+        self.blocking(move |backend| {
+            // A clipboard that cannot be read, like an empty one, is written.
+            if backend.get().is_ok_and(|current| current == content) {
+                return Ok(false);
+            }
+            let text = content.text.clone();
+            backend.set(content).wrap_err("Write the clipboard")?;
+            read_back(backend, &text)?;
+            Ok(true)
+        })
+        .await
     }
 
-    pub async fn set(&self, content: impl AsRef<str>) -> Result<bool> {
-        let mut context = self.context.lock().await;
-        let current = context.get_contents();
-        let replaced = current.map(|c| c != content.as_ref()).unwrap_or(true);
-        if let (false, Err(e)) = (replaced, context.set_contents(content.as_ref().to_string())) {
-            error!("Set [Local] error: {:?}", e);
+    pub async fn get(&self) -> Result<Content> {
+        self.blocking(|backend| backend.get()).await
+    }
+
+    /// The content to start syncing with, nothing while the clipboard cannot
+    /// be read yet, like when it is empty.
+    pub async fn initial(&self) -> Result<Content> {
+        match self.get().await {
+            Err(e) if e.transient().is_some() => {
+                info!("Start with an empty clipboard: {}", e);
+                Ok(Content::default())
+            }
+            result => result,
         }
-        Ok(replaced)
     }
 
-    pub async fn get(&self) -> Result<String> {
-        let mut context = self.context.lock().await;
-        context.get_contents().map_err(|e| eyre!("{:?}", e))
+    /// Backends may block, like the system clipboard waiting on its owner,
+    /// so they are called off the runtime's workers.
+    async fn blocking<R: Send + 'static>(
+        &self,
+        call: impl FnOnce(&mut B) -> color_eyre::Result<R> + Send + 'static,
+    ) -> Result<R> {
+        let mut backend = self.backend.clone().lock_owned().await;
+        tokio::task::spawn_blocking(move || call(&mut backend))
+            .await
+            .map_err(Report::new)
+            .and_then(|result| result)
+            .map_err(Error::backend)
+    }
+
+    pub async fn watch(&self) -> Option<watch::Receiver<()>> {
+        let mut backend = self.backend.lock().await;
+        backend.watch()
+    }
+}
+
+/// Some clipboards take the new content over in the background, like X11
+/// where another thread becomes the selection owner.
+fn read_back<B: ClipboardBackend>(backend: &mut B, text: &str) -> color_eyre::Result<()> {
+    let mut attempt = 1;
+    loop {
+        let written = match backend.get() {
+            Ok(written) => written.text,
+            // The system clipboard reads no text as empty.
+            Err(e) if text.is_empty() && e.downcast_ref() == Some(&Transient::Empty) => {
+                String::new()
+            }
+            Err(e) => return Err(e.wrap_err("Read back the clipboard")),
+        };
+        if written == text {
+            return Ok(());
+        }
+        if attempt == READ_BACK_ATTEMPTS {
+            return Err(eyre!("The clipboard did not keep what was written"));
+        }
+        attempt += 1;
+        std::thread::sleep(READ_BACK_DELAY);
     }
 }
@@ -1,24 +1,27 @@
-use color_eyre::Result;
 use std::sync::Arc;
 use tokio::sync::{watch, Mutex};
 
+use crate::error::Result;
+use crate::Content;
+
 #[derive(Clone)]
 pub struct RemoteClipboard {
-    sender: Arc<Mutex<watch::Sender<String>>>,
-    receiver: Arc<Mutex<watch::Receiver<String>>>,
+    sender: Arc<Mutex<watch::Sender<Content>>>,
+    receiver: Arc<Mutex<watch::Receiver<Content>>>,
 }
 
 impl RemoteClipboard {
-    pub fn new(sender: watch::Sender<String>, receiver: watch::Receiver<String>) -> Self {
+    pub fn new(sender: watch::Sender<Content>, receiver: watch::Receiver<Content>) -> Self {
         Self {
             sender: Arc::new(Mutex::new(sender)),
             receiver: Arc::new(Mutex::new(receiver)),
         }
     }
 
-    pub async fn set(&self, content: String) -> Result<bool> {
+    /// Replace the clip if `content` supersedes it, see [`Content::supersedes`].
+    pub async fn set(&self, content: Content) -> Result<bool> {
         let replaced = self.sender.lock().await.send_if_modified(|prev| {
-            if prev != &content {
+            if content.supersedes(prev) {
                 *prev = content;
                 true
             } else {
@@ -28,12 +31,17 @@ impl RemoteClipboard {
         Ok(replaced)
     }
 
-    pub async fn current(&self) -> Result<String> {
+    /// The clip last handed to the remote, the initial one at first.
+    pub async fn sent(&self) -> Content {
+        self.sender.lock().await.borrow().clone()
+    }
+
+    pub async fn current(&self) -> Result<Content> {
         let content = self.receiver.lock().await.borrow().clone();
         Ok(content)
     }
 
-    pub async fn get_new(&self) -> Result<String> {
+    pub async fn get_new(&self) -> Result<Content> {
         let mut receiver = self.receiver.lock().await;
         receiver.changed().await?;
         let content = receiver.borrow_and_update().clone();
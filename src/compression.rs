@@ -0,0 +1,62 @@
+use std::io::{Read, Write};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use prost::Message;
+use tonic::codec::CompressionEncoding;
+
+use crate::error::{Error, Result};
+use crate::Content;
+
+/// The only codec tonic 0.10 ships, zstd needs a newer tonic.
+pub const GZIP: &str = "gzip";
+
+/// The codecs this build can use, in order of preference.
+pub const SUPPORTED: [&str; 1] = [GZIP];
+
+/// Clips smaller than this many encoded bytes are sent as they are, gzip
+/// would barely shrink them.
+pub const DEFAULT_THRESHOLD: u64 = 1024;
+
+/// The tonic encoding for a codec name.
+pub fn encoding(name: &str) -> Option<CompressionEncoding> {
+    match name {
+        GZIP => Some(CompressionEncoding::Gzip),
+        _ => None,
+    }
+}
+
+/// What goes in the polling stream in place of a clip worth compressing,
+/// tonic 0.10 can only gzip a whole stream.
+pub fn pack(content: &Content) -> Result<Content> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&content.unstamped().encode_to_vec())
+        .map_err(|e| Error::io("Gzip clip", e))?;
+    Ok(Content {
+        stamp: content.stamp.clone(),
+        gzipped: encoder.finish().map_err(|e| Error::io("Gzip clip", e))?,
+        ..Content::default()
+    })
+}
+
+/// The clip a packed one holds, refusing one over `max_size` once unpacked.
+pub fn unpack(content: &Content, max_size: u64) -> Result<Content> {
+    let mut encoded = Vec::new();
+    GzDecoder::new(content.gzipped.as_slice())
+        .take(max_size.saturating_add(1))
+        .read_to_end(&mut encoded)
+        .map_err(|e| Error::Corrupt(format!("Invalid gzipped clip: {e}")))?;
+    if encoded.len() as u64 > max_size {
+        return Err(Error::PayloadTooLarge(format!(
+            "Gzipped clip is over the {max_size} byte limit"
+        )));
+    }
+    let unpacked = Content::decode(encoded.as_slice())
+        .map_err(|e| Error::Corrupt(format!("Invalid gzipped clip: {e}")))?;
+    Ok(Content {
+        stamp: content.stamp.clone(),
+        ..unpacked
+    })
+}
@@ -0,0 +1,685 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tonic::transport::{ClientTlsConfig, ServerTlsConfig};
+use tracing::info;
+
+use crate::clipboard::DEFAULT_POLL_INTERVAL;
+use crate::compression;
+use crate::crypto::Cipher;
+use crate::direction::Direction;
+use crate::discovery::{self, Discovery};
+use crate::error::{Error, Result};
+use crate::filter::{self, Action, Filter, Rule};
+use crate::handshake::{Features, DEFAULT_MAX_PAYLOAD};
+use crate::history::{History, DEFAULT_MAX_BYTES, DEFAULT_MAX_ENTRIES};
+use crate::{auth, tls};
+
+const REDACTED: &str = "<redacted>";
+
+/// The settings of a synclip node.
+///
+/// Every layer overrides the previous one: built-in defaults, the TOML
+/// config file, `SYNCLIP_*` environment variables and finally CLI flags.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub client: ClientConfig,
+    pub peer: PeerConfig,
+    pub auth: AuthConfig,
+    pub tls: TlsConfig,
+    pub encryption: EncryptionConfig,
+    pub sync: SyncConfig,
+    pub history: HistoryConfig,
+    pub discovery: DiscoveryConfig,
+    pub filter: FilterConfig,
+    pub daemon: DaemonConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// `SYNCLIP_LISTEN`
+    pub listen: SocketAddr,
+    /// The most a peer may do by IP address, like "10.0.0.12" = "receive"
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub peer_directions: BTreeMap<String, Direction>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: ([0, 0, 0, 0], 5505).into(),
+            peer_directions: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    /// `SYNCLIP_ADDRESS`, like http://[remote]:[port]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// `SYNCLIP_DISCOVER`, find the server over mDNS when no address is given
+    pub discover: bool,
+}
+
+/// Peer mode listens on `server.listen` and connects to every peer.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PeerConfig {
+    /// `SYNCLIP_PEERS`, comma separated addresses like http://[remote]:[port]
+    pub peers: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// `SYNCLIP_TOKEN`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// `SYNCLIP_TOKEN_FILE`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_file: Option<PathBuf>,
+}
+
+impl AuthConfig {
+    pub fn token(&self) -> Result<Option<String>> {
+        auth::load_token(self.token.clone(), self.token_file.as_deref())
+    }
+}
+
+/// The certificate and key are the server's in server mode and the
+/// client's own identity in client mode.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// `SYNCLIP_TLS_CERT`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert: Option<PathBuf>,
+    /// `SYNCLIP_TLS_KEY`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<PathBuf>,
+    /// `SYNCLIP_TLS_CLIENT_CA`, server only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ca: Option<PathBuf>,
+    /// `SYNCLIP_REQUIRE_CLIENT_CERT`, server only
+    pub require_client_cert: bool,
+    /// `SYNCLIP_TLS_CA`, client only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca: Option<PathBuf>,
+    /// `SYNCLIP_TLS_DOMAIN`, client only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+impl TlsConfig {
+    pub fn server_config(&self) -> Result<Option<ServerTlsConfig>> {
+        let (Some(cert), Some(key)) = (&self.cert, &self.key) else {
+            return Ok(None);
+        };
+        tls::server_tls_config(
+            cert,
+            key,
+            self.client_ca.as_deref(),
+            self.require_client_cert,
+        )
+        .map(Some)
+    }
+
+    /// TLS is used for https addresses or once anything TLS is configured.
+    pub fn client_config(&self, address: &str) -> Result<Option<ClientTlsConfig>> {
+        let use_tls = address.starts_with("https://") || self.ca.is_some() || self.cert.is_some();
+        if !use_tls {
+            return Ok(None);
+        }
+        let identity = self.cert.as_deref().zip(self.key.as_deref());
+        tls::client_tls_config(self.ca.as_deref(), identity, self.domain.as_deref()).map(Some)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncryptionConfig {
+    /// `SYNCLIP_PASSPHRASE`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<String>,
+    /// `SYNCLIP_PASSPHRASE_FILE`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passphrase_file: Option<PathBuf>,
+    /// `SYNCLIP_ENCRYPTION_GROUP`, names the peers sharing the passphrase and
+    /// salts its key, required with a passphrase
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+impl EncryptionConfig {
+    /// Derive the end-to-end key, if a passphrase is configured.
+    pub fn cipher(&self) -> Result<Option<Cipher>> {
+        let passphrase = match (&self.passphrase, &self.passphrase_file) {
+            (Some(_), Some(_)) => {
+                return Err(Error::Config(
+                    "Give either a passphrase or a passphrase file, not both".into(),
+                ))
+            }
+            (Some(passphrase), None) => passphrase.clone(),
+            (None, Some(path)) => fs::read_to_string(path)
+                .map_err(|e| Error::io(format!("Read passphrase file {:?}", path), e))?
+                .trim_end_matches(['\r', '\n'])
+                .to_owned(),
+            (None, None) => return Ok(None),
+        };
+        let group = self.group.as_deref().ok_or_else(|| {
+            Error::Config("Set encryption.group along with the passphrase".into())
+        })?;
+        info!("Derive end-to-end encryption key for group {group}");
+        Cipher::from_passphrase(&passphrase, group).map(Some)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncConfig {
+    /// `SYNCLIP_POLL_INTERVAL`, milliseconds between two reads of the local clipboard
+    pub poll_interval: u64,
+    /// `SYNCLIP_MAX_CLIP_SIZE`, the largest clip sent or accepted in bytes, larger ones stay where they were copied
+    pub max_clip_size: u64,
+    /// `SYNCLIP_COMPRESSION`, the codecs offered, used only when both sides offer one, "none" or empty turns it off
+    pub compression: Vec<String>,
+    /// `SYNCLIP_COMPRESSION_THRESHOLD`, clips smaller than this many bytes are sent uncompressed
+    pub compression_threshold: u64,
+    /// `SYNCLIP_DIRECTION`, send local clips, receive remote ones or both
+    pub direction: Direction,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            max_clip_size: DEFAULT_MAX_PAYLOAD,
+            compression: compression::SUPPORTED.map(str::to_owned).to_vec(),
+            compression_threshold: compression::DEFAULT_THRESHOLD,
+            direction: Direction::Both,
+        }
+    }
+}
+
+impl SyncConfig {
+    /// What this node offers in the handshake.
+    pub fn features(&self) -> Features {
+        Features {
+            max_payload: self.max_clip_size,
+            compression: self.compression.clone(),
+            compression_threshold: self.compression_threshold,
+            direction: self.direction,
+            ..Features::default()
+        }
+    }
+}
+
+/// Codec names separated by commas, "none" for no compression.
+pub fn parse_codecs(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|codec| !codec.is_empty() && !codec.eq_ignore_ascii_case("none"))
+        .map(str::to_ascii_lowercase)
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// The number of clips kept
+    pub max_entries: usize,
+    /// The total size of the clips kept, in bytes
+    pub max_bytes: usize,
+    /// `SYNCLIP_HISTORY_PATH`, the file the history survives restarts in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_bytes: DEFAULT_MAX_BYTES,
+            path: None,
+        }
+    }
+}
+
+impl HistoryConfig {
+    pub fn open(&self) -> Result<History> {
+        match &self.path {
+            Some(path) => History::persistent(self.max_entries, self.max_bytes, path),
+            None => Ok(History::new(self.max_entries, self.max_bytes)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    /// `SYNCLIP_FILTER_BUILTIN`, look for private keys, AWS keys, JWTs and card numbers
+    pub builtin: bool,
+    /// What to do with clips the built-in rules match
+    pub builtin_action: Action,
+    /// `SYNCLIP_FILTER_HINTS`, what to do with clips a password manager marked as secret
+    pub hints: Action,
+    /// Extra patterns, tried after the built-in ones
+    pub rules: Vec<RuleConfig>,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self {
+            builtin: true,
+            builtin_action: Action::Block,
+            hints: Action::Block,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl FilterConfig {
+    pub fn filter(&self) -> Result<Filter> {
+        let mut rules = match self.builtin {
+            true => filter::builtin(self.builtin_action),
+            false => Vec::new(),
+        };
+        for rule in &self.rules {
+            rules.push(Rule::new(&rule.name, &rule.pattern, rule.action)?);
+        }
+        Ok(Filter::new(rules, self.hints))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    pub name: String,
+    /// A regular expression, see https://docs.rs/regex
+    pub pattern: String,
+    pub action: Action,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscoveryConfig {
+    /// `SYNCLIP_ADVERTISE`, announce servers and peers over mDNS, off unless
+    /// asked for as anyone on the LAN learns where to connect
+    pub advertise: bool,
+    /// `SYNCLIP_NODE_NAME`, the name to advertise, defaults to the host name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Stay on the loopback interface, for nodes sharing a host
+    pub loopback: bool,
+}
+
+impl DiscoveryConfig {
+    pub fn open(&self) -> Result<Discovery> {
+        if self.loopback {
+            Discovery::loopback()
+        } else {
+            Discovery::new()
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(discovery::hostname)
+    }
+}
+
+/// Where `synclip daemon` keeps its files, all under
+/// `$XDG_RUNTIME_DIR/synclip` unless set.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// `SYNCLIP_CONTROL_SOCKET`, the Unix socket `synclip status` and friends talk to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socket: Option<PathBuf>,
+    /// `SYNCLIP_PID_FILE`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid_file: Option<PathBuf>,
+    /// `SYNCLIP_LOG_FILE`, where a detached daemon logs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_file: Option<PathBuf>,
+}
+
+impl DaemonConfig {
+    pub fn socket(&self) -> Result<PathBuf> {
+        self.path_or(&self.socket, "control.sock")
+    }
+
+    pub fn pid_file(&self) -> Result<PathBuf> {
+        self.path_or(&self.pid_file, "synclip.pid")
+    }
+
+    pub fn log_file(&self) -> Result<PathBuf> {
+        self.path_or(&self.log_file, "synclip.log")
+    }
+
+    fn path_or(&self, path: &Option<PathBuf>, default: &str) -> Result<PathBuf> {
+        match path {
+            Some(path) => Ok(path.clone()),
+            None => Ok(runtime_dir()?.join(default)),
+        }
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/synclip`, or a directory of the user's own under the
+/// temporary directory.
+fn runtime_dir() -> Result<PathBuf> {
+    match dirs::runtime_dir() {
+        Some(dir) => Ok(dir.join("synclip")),
+        #[cfg(unix)]
+        None => {
+            private_dir(std::env::temp_dir().join(format!("synclip-{}", nix::unistd::getuid())))
+        }
+        #[cfg(not(unix))]
+        None => Ok(std::env::temp_dir().join("synclip")),
+    }
+}
+
+/// `dir` created readable by this user only, refused when another user
+/// made it first or it is open to others: anyone can create it in the
+/// temporary directory.
+#[cfg(unix)]
+fn private_dir(dir: PathBuf) -> Result<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    match fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Err(e) if e.kind() != ErrorKind::AlreadyExists => {
+            return Err(Error::io(format!("Create {:?}", dir), e));
+        }
+        _ => {}
+    }
+    let metadata =
+        fs::symlink_metadata(&dir).map_err(|e| Error::io(format!("Read {:?}", dir), e))?;
+    if !metadata.is_dir() || metadata.uid() != nix::unistd::getuid().as_raw() {
+        return Err(Error::Config(format!(
+            "{:?} is not a directory of this user's own",
+            dir
+        )));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        return Err(Error::Config(format!(
+            "{:?} is open to other users, expected mode 0700",
+            dir
+        )));
+    }
+    Ok(dir)
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/synclip/config.toml` or the platform equivalent.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("synclip").join("config.toml"))
+    }
+
+    /// Read the config file, an explicit `path` must exist while the
+    /// default one is optional.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                Self::parse(&content).map_err(|e| e.context(format!("Parse config {:?}", path)))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(e) => Err(Error::io(format!("Read config {:?}", path), e)),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        toml::from_str(content).map_err(|e| Error::Config(e.to_string()))
+    }
+
+    /// What this node offers in the handshake, with what a server lets each
+    /// of its peers do.
+    pub fn features(&self) -> Features {
+        Features {
+            peer_directions: self.server.peer_directions.clone(),
+            ..self.sync.features()
+        }
+    }
+
+    /// Override settings from the `SYNCLIP_*` environment variables.
+    pub fn apply_env(&mut self) -> Result<()> {
+        self.apply_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Override settings from variables looked up by name. A secret replaces
+    /// its file and the other way round, like the command line flags.
+    pub fn apply_vars(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let path = |name: &str| var(name).map(PathBuf::from);
+        if let Some(listen) = var("SYNCLIP_LISTEN") {
+            self.server.listen = listen
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid SYNCLIP_LISTEN: {listen}: {e}")))?;
+        }
+        if let Some(address) = var("SYNCLIP_ADDRESS") {
+            self.client.address = Some(address);
+        }
+        if let Some(discover) = var("SYNCLIP_DISCOVER") {
+            self.client.discover = parse_bool(&discover)
+                .ok_or_else(|| Error::Config(format!("Invalid SYNCLIP_DISCOVER: {discover}")))?;
+        }
+        if let Some(advertise) = var("SYNCLIP_ADVERTISE") {
+            self.discovery.advertise = parse_bool(&advertise)
+                .ok_or_else(|| Error::Config(format!("Invalid SYNCLIP_ADVERTISE: {advertise}")))?;
+        }
+        if let Some(name) = var("SYNCLIP_NODE_NAME") {
+            self.discovery.name = Some(name);
+        }
+        if let Some(peers) = var("SYNCLIP_PEERS") {
+            self.peer.peers = peers
+                .split(',')
+                .map(str::trim)
+                .filter(|peer| !peer.is_empty())
+                .map(str::to_owned)
+                .collect();
+        }
+        if let Some(token) = var("SYNCLIP_TOKEN") {
+            self.auth.token = Some(token);
+            self.auth.token_file = None;
+        }
+        if let Some(token_file) = path("SYNCLIP_TOKEN_FILE") {
+            self.auth.token_file = Some(token_file);
+            self.auth.token = None;
+        }
+        if let Some(cert) = path("SYNCLIP_TLS_CERT") {
+            self.tls.cert = Some(cert);
+        }
+        if let Some(key) = path("SYNCLIP_TLS_KEY") {
+            self.tls.key = Some(key);
+        }
+        if let Some(client_ca) = path("SYNCLIP_TLS_CLIENT_CA") {
+            self.tls.client_ca = Some(client_ca);
+        }
+        if let Some(require) = var("SYNCLIP_REQUIRE_CLIENT_CERT") {
+            self.tls.require_client_cert = parse_bool(&require).ok_or_else(|| {
+                Error::Config(format!("Invalid SYNCLIP_REQUIRE_CLIENT_CERT: {require}"))
+            })?;
+        }
+        if let Some(ca) = path("SYNCLIP_TLS_CA") {
+            self.tls.ca = Some(ca);
+        }
+        if let Some(domain) = var("SYNCLIP_TLS_DOMAIN") {
+            self.tls.domain = Some(domain);
+        }
+        if let Some(passphrase) = var("SYNCLIP_PASSPHRASE") {
+            self.encryption.passphrase = Some(passphrase);
+            self.encryption.passphrase_file = None;
+        }
+        if let Some(passphrase_file) = path("SYNCLIP_PASSPHRASE_FILE") {
+            self.encryption.passphrase_file = Some(passphrase_file);
+            self.encryption.passphrase = None;
+        }
+        if let Some(group) = var("SYNCLIP_ENCRYPTION_GROUP") {
+            self.encryption.group = Some(group);
+        }
+        if let Some(poll_interval) = var("SYNCLIP_POLL_INTERVAL") {
+            self.sync.poll_interval = poll_interval.parse().map_err(|e| {
+                Error::Config(format!(
+                    "Invalid SYNCLIP_POLL_INTERVAL: {poll_interval}: {e}"
+                ))
+            })?;
+        }
+        if let Some(max_clip_size) = var("SYNCLIP_MAX_CLIP_SIZE") {
+            self.sync.max_clip_size = max_clip_size.parse().map_err(|e| {
+                Error::Config(format!(
+                    "Invalid SYNCLIP_MAX_CLIP_SIZE: {max_clip_size}: {e}"
+                ))
+            })?;
+        }
+        if let Some(codecs) = var("SYNCLIP_COMPRESSION") {
+            self.sync.compression = parse_codecs(&codecs);
+        }
+        if let Some(threshold) = var("SYNCLIP_COMPRESSION_THRESHOLD") {
+            self.sync.compression_threshold = threshold.parse().map_err(|e| {
+                Error::Config(format!(
+                    "Invalid SYNCLIP_COMPRESSION_THRESHOLD: {threshold}: {e}"
+                ))
+            })?;
+        }
+        if let Some(socket) = path("SYNCLIP_CONTROL_SOCKET") {
+            self.daemon.socket = Some(socket);
+        }
+        if let Some(pid_file) = path("SYNCLIP_PID_FILE") {
+            self.daemon.pid_file = Some(pid_file);
+        }
+        if let Some(log_file) = path("SYNCLIP_LOG_FILE") {
+            self.daemon.log_file = Some(log_file);
+        }
+        if let Some(direction) = var("SYNCLIP_DIRECTION") {
+            self.sync.direction = direction
+                .parse()
+                .map_err(|e: Error| e.context("Invalid SYNCLIP_DIRECTION"))?;
+        }
+        if let Some(history_path) = path("SYNCLIP_HISTORY_PATH") {
+            self.history.path = Some(history_path);
+        }
+        if let Some(builtin) = var("SYNCLIP_FILTER_BUILTIN") {
+            self.filter.builtin = parse_bool(&builtin).ok_or_else(|| {
+                Error::Config(format!("Invalid SYNCLIP_FILTER_BUILTIN: {builtin}"))
+            })?;
+        }
+        if let Some(hints) = var("SYNCLIP_FILTER_HINTS") {
+            self.filter.hints = hints
+                .parse()
+                .map_err(|e: Error| e.context("Invalid SYNCLIP_FILTER_HINTS"))?;
+        }
+        Ok(())
+    }
+
+    /// Catch mistakes before anything is started.
+    pub fn validate(&self) -> Result<()> {
+        if self.tls.cert.is_some() != self.tls.key.is_some() {
+            return Err(Error::Config(
+                "tls.cert and tls.key must be given together".into(),
+            ));
+        }
+        if self.tls.require_client_cert && self.tls.client_ca.is_none() {
+            return Err(Error::Config(
+                "tls.require_client_cert needs tls.client_ca".into(),
+            ));
+        }
+        if self.auth.token.is_some() && self.auth.token_file.is_some() {
+            return Err(Error::Config(
+                "Give either auth.token or auth.token_file, not both".into(),
+            ));
+        }
+        if self.encryption.passphrase.is_some() && self.encryption.passphrase_file.is_some() {
+            return Err(Error::Config(
+                "Give either encryption.passphrase or encryption.passphrase_file, not both".into(),
+            ));
+        }
+        let passphrase =
+            self.encryption.passphrase.is_some() || self.encryption.passphrase_file.is_some();
+        let group = matches!(self.encryption.group.as_deref(), Some(group) if !group.is_empty());
+        if passphrase && !group {
+            return Err(Error::Config(
+                "Set encryption.group along with the passphrase, every peer the same".into(),
+            ));
+        }
+        if self.sync.poll_interval == 0 {
+            return Err(Error::Config("sync.poll_interval must be positive".into()));
+        }
+        if self.sync.max_clip_size == 0 {
+            return Err(Error::Config("sync.max_clip_size must be positive".into()));
+        }
+        if let Some(peer) = self
+            .server
+            .peer_directions
+            .keys()
+            .find(|peer| peer.parse::<IpAddr>().is_err())
+        {
+            return Err(Error::Config(format!(
+                "server.peer_directions: {peer} is not an IP address"
+            )));
+        }
+        self.filter.filter()?;
+        if let Some(codec) = self
+            .sync
+            .compression
+            .iter()
+            .find(|codec| compression::encoding(codec).is_none())
+        {
+            return Err(Error::Config(format!(
+                "sync.compression: {codec} is not supported, this build supports {}",
+                compression::SUPPORTED.join(", ")
+            )));
+        }
+        let files = [
+            &self.auth.token_file,
+            &self.tls.cert,
+            &self.tls.key,
+            &self.tls.client_ca,
+            &self.tls.ca,
+            &self.encryption.passphrase_file,
+        ];
+        for file in files.into_iter().flatten() {
+            if !file.is_file() {
+                return Err(Error::Config(format!("File not found: {:?}", file)));
+            }
+        }
+        Ok(())
+    }
+
+    /// The config with its secrets hidden, fit for printing.
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        if config.auth.token.is_some() {
+            config.auth.token = Some(REDACTED.into());
+        }
+        if config.encryption.passphrase.is_some() {
+            config.encryption.passphrase = Some(REDACTED.into());
+        }
+        config
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).map_err(|e| Error::Config(e.to_string()))
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt::{Display, Formatter};
+
+use crate::{Content, Representation, Stamp};
+
+pub const TEXT_PLAIN: &str = "text/plain";
+pub const TEXT_HTML: &str = "text/html";
+pub const TEXT_RTF: &str = "text/rtf";
+pub const TEXT_URI_LIST: &str = "text/uri-list";
+pub const IMAGE_PNG: &str = "image/png";
+
+/// The most characters of its first line a clip's summary keeps.
+pub const SUMMARY_CHARS: usize = 80;
+
+impl Content {
+    pub fn from_text(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Add a representation, replacing any previous one of the same MIME type.
+    ///
+    /// Plain text goes to the `text` field so text-only peers still see it.
+    pub fn with(mut self, mime_type: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        let mime_type = mime_type.into();
+        let data = data.into();
+        if mime_type == TEXT_PLAIN {
+            self.text = String::from_utf8_lossy(&data).into_owned();
+            return self;
+        }
+        self.representations
+            .retain(|representation| representation.mime_type != mime_type);
+        self.representations
+            .push(Representation { mime_type, data });
+        self
+    }
+
+    /// The data stored for a MIME type, plain text included.
+    pub fn get(&self, mime_type: &str) -> Option<&[u8]> {
+        if mime_type == TEXT_PLAIN {
+            return (!self.text.is_empty()).then_some(self.text.as_bytes());
+        }
+        self.representations
+            .iter()
+            .find(|representation| representation.mime_type == mime_type)
+            .map(|representation| representation.data.as_slice())
+    }
+
+    /// Every MIME type this clip is available as, plain text first.
+    pub fn mime_types(&self) -> impl Iterator<Item = &str> {
+        (!self.text.is_empty())
+            .then_some(TEXT_PLAIN)
+            .into_iter()
+            .chain(self.representations.iter().map(|r| r.mime_type.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.representations.is_empty() && self.sealed.is_none()
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.sealed.is_some()
+    }
+
+    pub fn with_stamp(mut self, stamp: Stamp) -> Self {
+        self.stamp = Some(stamp);
+        self
+    }
+
+    /// The same clip without where it came from.
+    pub fn unstamped(&self) -> Self {
+        Self {
+            stamp: None,
+            ..self.clone()
+        }
+    }
+
+    /// Whether both hold the same clip, wherever they came from.
+    pub fn same_clip(&self, other: &Content) -> bool {
+        self.text == other.text
+            && self.representations == other.representations
+            && self.sealed == other.sealed
+    }
+
+    /// Whether a clipboard this clip was written to may read back as `read`:
+    /// the same text with some of its representations, as a clipboard need
+    /// not keep or read back every one of them.
+    pub fn reads_back_as(&self, read: &Content) -> bool {
+        self.text == read.text
+            && self.sealed == read.sealed
+            && read
+                .representations
+                .iter()
+                .all(|representation| self.representations.contains(representation))
+    }
+
+    /// Whether this clip should replace `other`: a stamped clip when it is
+    /// newer, an unstamped one when it differs.
+    pub fn supersedes(&self, other: &Content) -> bool {
+        match &self.stamp {
+            Some(stamp) => stamp.is_newer(other.stamp.as_ref()),
+            None => self != other,
+        }
+    }
+
+    /// The first line of the text, cut at [`SUMMARY_CHARS`], and the MIME
+    /// types and sizes: enough to tell clips apart without holding them.
+    pub fn summary(&self) -> String {
+        if self.sealed.is_some() || self.text.is_empty() {
+            return self.to_string();
+        }
+        let line = self.text.lines().next().unwrap_or_default();
+        let mut first: String = line.chars().take(SUMMARY_CHARS).collect();
+        if first.len() < self.text.len() {
+            first.push('…');
+        }
+        format!("{first:?} {self}")
+    }
+
+    /// The payload size in bytes, ignoring protobuf framing.
+    pub fn size(&self) -> usize {
+        self.text.len()
+            + self
+                .representations
+                .iter()
+                .map(|representation| representation.data.len())
+                .sum::<usize>()
+            + self
+                .sealed
+                .as_ref()
+                .map(|sealed| sealed.ciphertext.len())
+                .unwrap_or_default()
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Self::from_text(text)
+    }
+}
+
+impl From<&str> for Content {
+    fn from(text: &str) -> Self {
+        Self::from_text(text)
+    }
+}
+
+/// A log friendly outline, the MIME type and size of every representation
+/// and none of the data: logs end up in files, clips are private.
+impl Display for Content {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(sealed) = &self.sealed {
+            return write!(f, "<sealed {} bytes>", sealed.ciphertext.len());
+        }
+        if self.is_empty() {
+            return write!(f, "<empty>");
+        }
+        for (i, mime_type) in self.mime_types().enumerate() {
+            let size = self.get(mime_type).map_or(0, <[u8]>::len);
+            let separator = if i == 0 { "" } else { " +" };
+            write!(f, "{separator}{mime_type}({size} bytes)")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+mod control_rpc;
+
+use std::fs::{self, Permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use nix::sys::stat::{umask, Mode};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::UnixListenerStream;
+use tokio_util::sync::CancellationToken;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::service_fn;
+use tracing::info;
+
+use crate::clipboard::backend::ClipboardBackend;
+use crate::clipboard::{Clipboard, VirtualClipboard};
+use crate::control::control_rpc::ControlRpc;
+use crate::error::{Error, Result};
+use crate::{control_client, control_server};
+
+pub type ControlClient = control_client::ControlClient<Channel>;
+
+/// Serves the control API of a running node on a local Unix socket, only
+/// the user running the node may connect.
+#[derive(Clone)]
+pub struct ControlServer {
+    socket: PathBuf,
+    handle: Arc<Mutex<Option<JoinHandle<Result<()>>>>>,
+}
+
+impl ControlServer {
+    /// `mode` is what the node runs as, reported in its status.
+    pub async fn new<T: VirtualClipboard + 'static, B: ClipboardBackend>(
+        socket: impl AsRef<Path>,
+        clipboard: Clipboard<T, B>,
+        mode: &'static str,
+        cancel_token: CancellationToken,
+    ) -> Result<Self> {
+        let socket = socket.as_ref().to_path_buf();
+        if let Some(dir) = socket.parent() {
+            fs::create_dir_all(dir).map_err(|e| Error::io(format!("Create {:?}", dir), e))?;
+        }
+        if socket.exists() {
+            if UnixStream::connect(&socket).await.is_ok() {
+                return Err(Error::Config(format!(
+                    "A synclip daemon already listens on {:?}",
+                    socket
+                )));
+            }
+            // Left behind by a node that did not shut down cleanly.
+            fs::remove_file(&socket)
+                .map_err(|e| Error::io(format!("Remove stale {:?}", socket), e))?;
+        }
+        // Created owner-only, an explicit --socket may lie outside the
+        // private runtime dir.
+        let previous = umask(Mode::from_bits_truncate(0o077));
+        let bound = UnixListener::bind(&socket);
+        umask(previous);
+        let listener = bound.map_err(|e| Error::io(format!("Bind {:?}", socket), e))?;
+        fs::set_permissions(&socket, Permissions::from_mode(0o600))
+            .map_err(|e| Error::io(format!("Restrict {:?}", socket), e))?;
+        let service = control_server::ControlServer::new(ControlRpc::new(
+            clipboard,
+            mode,
+            cancel_token.clone(),
+        ));
+
+        let path = socket.clone();
+        let handle = tokio::spawn(async move {
+            info!("Control socket: {:?}", path);
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(
+                    UnixListenerStream::new(listener),
+                    cancel_token.cancelled(),
+                )
+                .await?;
+            fs::remove_file(&path).ok();
+            info!("End [Control]");
+            Ok(())
+        });
+
+        Ok(Self {
+            socket,
+            handle: Arc::new(Mutex::new(Some(handle))),
+        })
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    /// Wait for the socket to close, once the node is cancelled.
+    pub async fn shutdown(self) -> Result<()> {
+        let handle = self.handle.lock().unwrap().take();
+        if let Some(handle) = handle {
+            match handle.await {
+                Ok(result) => result?,
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                Err(_) => return Err(Error::Shutdown),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Connect to the node whose control socket is at `socket`.
+pub async fn connect(socket: impl AsRef<Path>) -> Result<ControlClient> {
+    let socket = socket.as_ref().to_path_buf();
+    let path = socket.clone();
+    // Never dialed, the connector goes to the socket instead.
+    let channel = Endpoint::from_static("http://localhost")
+        .connect_with_connector(service_fn(move |_: Uri| UnixStream::connect(path.clone())))
+        .await
+        .map_err(|e| Error::from(e).context(format!("No synclip daemon on {:?}", socket)))?;
+    Ok(ControlClient::new(channel))
+}
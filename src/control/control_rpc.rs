@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+use crate::clipboard::backend::ClipboardBackend;
+use crate::clipboard::{Clipboard, VirtualClipboard};
+use crate::error::Error;
+use crate::history::History;
+use crate::proto::control_server::Control;
+use crate::util::now_millis;
+use crate::{
+    Empty, HistoryEntries, HistoryEntry, HistoryEntryId, HistoryQuery, NodeStatus, PauseRequest,
+    Peers, Replaced,
+};
+
+pub struct ControlRpc<T: VirtualClipboard, B: ClipboardBackend> {
+    clipboard: Clipboard<T, B>,
+    mode: &'static str,
+    started_at: u64,
+    cancel_token: CancellationToken,
+}
+
+impl<T: VirtualClipboard + 'static, B: ClipboardBackend> ControlRpc<T, B> {
+    pub fn new(
+        clipboard: Clipboard<T, B>,
+        mode: &'static str,
+        cancel_token: CancellationToken,
+    ) -> Self {
+        Self {
+            clipboard,
+            mode,
+            started_at: now_millis(),
+            cancel_token,
+        }
+    }
+
+    fn status(&self) -> NodeStatus {
+        let remote = self.clipboard.remote();
+        let paused_until = self.clipboard.paused_until();
+        NodeStatus {
+            node_id: remote.id().to_owned(),
+            mode: self.mode.to_owned(),
+            pid: std::process::id(),
+            started_at: self.started_at,
+            paused: paused_until.is_some(),
+            direction: self.clipboard.direction().to_string(),
+            peers: remote.peers().len() as u32,
+            paused_until: paused_until.flatten().unwrap_or_default(),
+            skip_next: self.clipboard.skips_next(),
+            transient_errors: self.clipboard.metrics().transient_errors(),
+        }
+    }
+
+    fn history(&self) -> Result<&History, Error> {
+        self.clipboard
+            .remote()
+            .history()
+            .ok_or_else(|| Error::NotFound(format!("A {} keeps no history", self.mode)))
+    }
+}
+
+#[tonic::async_trait]
+impl<T: VirtualClipboard + 'static, B: ClipboardBackend> Control for ControlRpc<T, B> {
+    async fn status(&self, _: Request<Empty>) -> Result<Response<NodeStatus>, Status> {
+        Ok(Response::new(self.status()))
+    }
+
+    async fn pause(&self, request: Request<PauseRequest>) -> Result<Response<NodeStatus>, Status> {
+        match request.into_inner().seconds {
+            0 => self.clipboard.pause(),
+            seconds => self.clipboard.pause_for(Duration::from_secs(seconds)),
+        }
+        Ok(Response::new(self.status()))
+    }
+
+    async fn resume(&self, _: Request<Empty>) -> Result<Response<NodeStatus>, Status> {
+        self.clipboard.resume();
+        Ok(Response::new(self.status()))
+    }
+
+    async fn skip_next(&self, _: Request<Empty>) -> Result<Response<NodeStatus>, Status> {
+        self.clipboard.skip_next();
+        Ok(Response::new(self.status()))
+    }
+
+    async fn stop(&self, _: Request<Empty>) -> Result<Response<Empty>, Status> {
+        info!("Stop requested [Control]");
+        self.cancel_token.cancel();
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn list_peers(&self, _: Request<Empty>) -> Result<Response<Peers>, Status> {
+        let peers = self.clipboard.remote().peers();
+        Ok(Response::new(Peers { peers }))
+    }
+
+    async fn list_history(
+        &self,
+        request: Request<HistoryQuery>,
+    ) -> Result<Response<HistoryEntries>, Status> {
+        let entries = self.history()?.list(request.into_inner().limit as usize);
+        Ok(Response::new(HistoryEntries { entries }))
+    }
+
+    async fn get_history_entry(
+        &self,
+        request: Request<HistoryEntryId>,
+    ) -> Result<Response<HistoryEntry>, Status> {
+        let id = request.into_inner().id;
+        let entry = self
+            .history()?
+            .get(id)
+            .ok_or_else(|| Error::NotFound(format!("No history entry {id}")))?;
+        Ok(Response::new(entry))
+    }
+
+    async fn apply_history_entry(
+        &self,
+        request: Request<HistoryEntryId>,
+    ) -> Result<Response<Replaced>, Status> {
+        let replaced = self
+            .clipboard
+            .apply_history(request.into_inner().id)
+            .await?;
+        Ok(Response::new(Replaced { replaced }))
+    }
+}
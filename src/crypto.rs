@@ -0,0 +1,127 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use prost::Message;
+
+use crate::error::{Error, Result};
+use crate::{Content, Sealed, Stamp};
+
+pub const SEALED_VERSION: u32 = 2;
+
+/// Every peer derives the same key from the same passphrase and group, so
+/// the salt is the group rather than random.
+const SALT_PREFIX: &str = "synclip-e2e-v2:";
+const KEY_LEN: usize = 32;
+const KEY_ID_LEN: usize = 8;
+
+/// Encrypts clips end-to-end with a key derived from a shared passphrase.
+#[derive(Clone)]
+pub struct Cipher {
+    aead: ChaCha20Poly1305,
+    key_id: Vec<u8>,
+}
+
+impl Cipher {
+    /// Derive the key with Argon2id, this takes a noticeable moment on purpose.
+    ///
+    /// `group` names the peers sharing the passphrase, like "home-desks". It
+    /// salts the key, a table precomputed for one group is of no use against
+    /// another.
+    pub fn from_passphrase(passphrase: &str, group: &str) -> Result<Self> {
+        if passphrase.is_empty() {
+            return Err(Error::Config("The passphrase is empty".into()));
+        }
+        if group.is_empty() {
+            return Err(Error::Config("The encryption group is empty".into()));
+        }
+        let salt = format!("{SALT_PREFIX}{group}");
+        let mut output = [0u8; KEY_LEN + KEY_ID_LEN];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt.as_bytes(), &mut output)
+            .map_err(|e| Error::Crypto(format!("Derive key: {}", e)))?;
+        let (key, key_id) = output.split_at(KEY_LEN);
+        Ok(Self {
+            aead: ChaCha20Poly1305::new(Key::from_slice(key)),
+            key_id: key_id.to_vec(),
+        })
+    }
+
+    /// Encrypt a plaintext clip with a fresh nonce. Its stamp stays outside,
+    /// bound to the ciphertext: the clip only opens under the same stamp.
+    pub fn seal(&self, content: &Content) -> Result<Content> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = self.aad(SEALED_VERSION, content.stamp.as_ref());
+        let payload = Payload {
+            msg: &content.unstamped().encode_to_vec(),
+            aad: &aad,
+        };
+        let ciphertext = self
+            .aead
+            .encrypt(&nonce, payload)
+            .map_err(|e| Error::Crypto(format!("Encrypt clip: {}", e)))?;
+        Ok(Content {
+            sealed: Some(Sealed {
+                version: SEALED_VERSION,
+                key_id: self.key_id.clone(),
+                nonce: nonce.to_vec(),
+                ciphertext,
+            }),
+            stamp: content.stamp.clone(),
+            ..Default::default()
+        })
+    }
+
+    /// Decrypt a clip sealed by a peer sharing the passphrase, under the
+    /// stamp it was sealed with.
+    pub fn open(&self, content: &Content) -> Result<Content> {
+        let sealed = content.sealed.as_ref().ok_or_else(|| {
+            Error::Crypto(
+                "Received an unencrypted clip, is the passphrase set on every peer?".into(),
+            )
+        })?;
+        if sealed.version != SEALED_VERSION {
+            return Err(Error::Crypto(format!(
+                "Unsupported encryption version: {}",
+                sealed.version
+            )));
+        }
+        if sealed.key_id != self.key_id {
+            return Err(Error::Crypto("Clip was encrypted with another passphrase, check that every peer uses the same one".into()));
+        }
+        if sealed.nonce.len() != 12 {
+            return Err(Error::Crypto(format!(
+                "Invalid nonce length: {}",
+                sealed.nonce.len()
+            )));
+        }
+        let aad = self.aad(sealed.version, content.stamp.as_ref());
+        let payload = Payload {
+            msg: &sealed.ciphertext,
+            aad: &aad,
+        };
+        let plaintext = self
+            .aead
+            .decrypt(Nonce::from_slice(&sealed.nonce), payload)
+            .map_err(|_| {
+                Error::Crypto(
+                    "Clip failed authentication, it was tampered with, replayed under another stamp or the passphrase differs"
+                        .into(),
+                )
+            })?;
+        Content::decode(plaintext.as_slice())
+            .map_err(|e| Error::Crypto(format!("Decode decrypted clip: {e}")))
+    }
+
+    /// The version, key and stamp the ciphertext is bound to, so a relay
+    /// cannot pass an old clip off as a new copy.
+    fn aad(&self, version: u32, stamp: Option<&Stamp>) -> Vec<u8> {
+        let mut aad = version.to_be_bytes().to_vec();
+        aad.extend_from_slice(&self.key_id);
+        if let Some(stamp) = stamp {
+            aad.extend_from_slice(&stamp.timestamp.to_be_bytes());
+            aad.extend_from_slice(&stamp.seq.to_be_bytes());
+            aad.extend_from_slice(stamp.origin.as_bytes());
+        }
+        aad
+    }
+}
@@ -0,0 +1,148 @@
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::sys::signal::kill;
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::{dup2, fork, pipe, setsid, ForkResult, Pid};
+use sd_notify::NotifyState;
+use tracing::{info, warn};
+
+/// Holds the pid of the running daemon, removed when dropped.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Write the pid of this process to `path`, refused while the process
+    /// an existing file names is alive.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        Self::check(&path)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Create {:?}", dir))?;
+        }
+        fs::write(&path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Write pid file {:?}", path))?;
+        info!("pid file: {:?}", path);
+        Ok(Self { path })
+    }
+
+    /// Refuse to start while the process the file at `path` names is alive.
+    pub fn check(path: &Path) -> Result<()> {
+        if let Some(pid) = read_pid(path) {
+            if pid != std::process::id() as i32 && is_alive(pid) {
+                return Err(eyre!("synclip already runs with pid {pid}, see {:?}", path));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Remove pid file {:?} error: {}", self.path, e);
+        }
+    }
+}
+
+/// Whether a process runs with `pid`, one of another user's included.
+fn is_alive(pid: i32) -> bool {
+    !matches!(kill(Pid::from_raw(pid), None), Err(Errno::ESRCH))
+}
+
+/// The pid in a pid file, `None` if there is none.
+pub fn read_pid(path: &Path) -> Option<i32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Where a detached daemon tells its parent it is ready, see [`detach`].
+static READY: Mutex<Option<File>> = Mutex::new(None);
+
+/// Fork into the background: the child leaves the terminal while the parent
+/// waits for [`notify_ready`], prints the pid and exits. A child failing to
+/// start prints its error on the terminal, the parent exits with its status.
+///
+/// Must run before any other thread is started, the tokio runtime included.
+pub fn detach() -> Result<()> {
+    let (read, write) = pipe().with_context(|| "Create the readiness pipe")?;
+    // Not for the processes the daemon runs, like wl-paste, to hold open.
+    fcntl(write.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+    // SAFETY: single threaded until here, see above.
+    match unsafe { fork() }.with_context(|| "Fork the daemon")? {
+        ForkResult::Parent { child } => {
+            drop(write);
+            std::process::exit(wait_ready(child, File::from(read)));
+        }
+        ForkResult::Child => {}
+    }
+    drop(read);
+    setsid()?;
+    let null = File::open("/dev/null")?;
+    dup2(null.as_raw_fd(), 0)?;
+    *READY.lock().unwrap() = Some(File::from(write));
+    Ok(())
+}
+
+/// What the parent exits with, 0 once the child is ready and the child's
+/// status when it exits before.
+fn wait_ready(child: Pid, mut ready: File) -> i32 {
+    if matches!(ready.read(&mut [0]), Ok(1)) {
+        println!("synclip daemon started with pid {child}");
+        return 0;
+    }
+    match waitpid(child, None) {
+        Ok(WaitStatus::Exited(_, code)) => code,
+        _ => 1,
+    }
+}
+
+/// Let go of the terminal a detached daemon kept for its startup errors.
+fn release_terminal() -> std::io::Result<()> {
+    let null = File::options().write(true).open("/dev/null")?;
+    for fd in 1..=2 {
+        dup2(null.as_raw_fd(), fd)?;
+    }
+    Ok(())
+}
+
+/// Whether systemd started this process and waits to hear it is ready, as
+/// for `Type=notify` units.
+pub fn under_systemd() -> bool {
+    std::env::var_os("NOTIFY_SOCKET").is_some()
+}
+
+/// Tell systemd, or the parent [`detach`] left waiting, the node is up.
+pub fn notify_ready() {
+    notify(&[NotifyState::Ready]);
+    if let Some(mut ready) = READY.lock().unwrap().take() {
+        if let Err(e) = ready.write_all(&[1]) {
+            warn!("Notify the parent process error: {}", e);
+        }
+        if let Err(e) = release_terminal() {
+            warn!("Release the terminal error: {}", e);
+        }
+    }
+}
+
+/// Tell systemd the node is shutting down.
+pub fn notify_stopping() {
+    notify(&[NotifyState::Stopping]);
+}
+
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        warn!("Notify systemd error: {}", e);
+    }
+}
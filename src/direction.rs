@@ -0,0 +1,78 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// Which way clips flow between a node and the others.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Send local clips and apply remote ones.
+    #[default]
+    #[serde(alias = "bidirectional")]
+    Both,
+    /// Only publish local clips, like a build server.
+    #[serde(alias = "send-only")]
+    Send,
+    /// Only apply remote clips, like a kiosk.
+    #[serde(alias = "receive-only")]
+    Receive,
+}
+
+impl Direction {
+    pub fn sends(self) -> bool {
+        self != Direction::Receive
+    }
+
+    pub fn receives(self) -> bool {
+        self != Direction::Send
+    }
+
+    /// What is left of `self` once `other` is respected too, `None` if the
+    /// two exclude each other.
+    pub fn narrow(self, other: Direction) -> Option<Direction> {
+        match (self, other) {
+            (Direction::Both, other) => Some(other),
+            (ours, Direction::Both) => Some(ours),
+            (ours, theirs) if ours == theirs => Some(ours),
+            _ => None,
+        }
+    }
+
+    /// The direction in a greeting, empty for nodes that predate it.
+    pub fn from_greeting(value: &str) -> Result<Self> {
+        match value {
+            "" => Ok(Direction::Both),
+            value => value
+                .parse()
+                .map_err(|e: Error| Error::Protocol(e.to_string())),
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "both" | "bidirectional" => Ok(Direction::Both),
+            "send" | "send-only" => Ok(Direction::Send),
+            "receive" | "receive-only" => Ok(Direction::Receive),
+            _ => Err(Error::Config(format!(
+                "Unknown sync direction {value}, use both, send or receive"
+            ))),
+        }
+    }
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Direction::Both => write!(f, "both"),
+            Direction::Send => write!(f, "send"),
+            Direction::Receive => write!(f, "receive"),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::time::Duration;
+
+use mdns_sd::{IfKind, ServiceDaemon, ServiceEvent, ServiceInfo};
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+use crate::error::{Error, Result};
+use crate::PROTOCOL_VERSION;
+
+/// The DNS-SD service type synclip nodes advertise.
+pub const SERVICE_TYPE: &str = "_synclip._tcp.local.";
+
+const NODE_KEY: &str = "node";
+const PROTOCOL_KEY: &str = "proto";
+const TLS_KEY: &str = "tls";
+
+/// A synclip node found on the network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Discovered {
+    pub node: String,
+    pub addresses: Vec<IpAddr>,
+    pub port: u16,
+    pub protocol: u32,
+    pub tls: bool,
+}
+
+impl Discovered {
+    /// The address to connect to, IPv4 preferred.
+    pub fn url(&self) -> Option<String> {
+        let ip = self
+            .addresses
+            .iter()
+            .find(|ip| ip.is_ipv4())
+            .or_else(|| self.addresses.first())?;
+        let scheme = if self.tls { "https" } else { "http" };
+        Some(match ip {
+            IpAddr::V4(ip) => format!("{scheme}://{ip}:{}", self.port),
+            IpAddr::V6(ip) => format!("{scheme}://[{ip}]:{}", self.port),
+        })
+    }
+
+    /// Whether this node speaks the same protocol as us.
+    pub fn is_compatible(&self) -> bool {
+        self.protocol == PROTOCOL_VERSION
+    }
+
+    fn from_info(info: &ServiceInfo) -> Option<Self> {
+        let node = info.get_property_val_str(NODE_KEY)?.to_owned();
+        let protocol = info.get_property_val_str(PROTOCOL_KEY)?.parse().ok()?;
+        let tls = info.get_property_val_str(TLS_KEY) == Some("1");
+        let mut addresses: Vec<IpAddr> = info.get_addresses().iter().copied().collect();
+        addresses.sort();
+        Some(Self {
+            node,
+            addresses,
+            port: info.get_port(),
+            protocol,
+            tls,
+        })
+    }
+}
+
+impl Display for Discovered {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} (protocol {})",
+            self.node,
+            self.url().unwrap_or_default(),
+            self.protocol
+        )
+    }
+}
+
+/// This host's name, the default node name.
+pub fn hostname() -> String {
+    gethostname::gethostname().to_string_lossy().into_owned()
+}
+
+/// Advertises and browses synclip nodes over mDNS.
+pub struct Discovery {
+    daemon: ServiceDaemon,
+    loopback: bool,
+    advertised: Vec<String>,
+}
+
+impl Discovery {
+    /// Use every LAN interface.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            daemon: ServiceDaemon::new()?,
+            loopback: false,
+            advertised: Vec::new(),
+        })
+    }
+
+    /// Use the loopback interface only, for nodes on the same host.
+    pub fn loopback() -> Result<Self> {
+        let daemon = ServiceDaemon::new()?;
+        daemon.disable_interface(IfKind::All)?;
+        daemon.enable_interface(IfKind::LoopbackV4)?;
+        Ok(Self {
+            daemon,
+            loopback: true,
+            advertised: Vec::new(),
+        })
+    }
+
+    /// Announce a node listening on `port` until shutdown.
+    pub fn advertise(&mut self, node: &str, port: u16, tls: bool) -> Result<()> {
+        // Instance names are labels, keep them short and dot free.
+        let instance: String = node.replace('.', "-").chars().take(48).collect();
+        let host = format!("{instance}.local.");
+        let protocol = PROTOCOL_VERSION.to_string();
+        let properties = [
+            (NODE_KEY, node),
+            (PROTOCOL_KEY, protocol.as_str()),
+            (TLS_KEY, if tls { "1" } else { "0" }),
+        ];
+        let info = if self.loopback {
+            ServiceInfo::new(
+                SERVICE_TYPE,
+                &instance,
+                &host,
+                "127.0.0.1",
+                port,
+                &properties[..],
+            )?
+        } else {
+            ServiceInfo::new(SERVICE_TYPE, &instance, &host, "", port, &properties[..])?
+                .enable_addr_auto()
+        };
+        info!(
+            "Advertise [{node}] on port {port} as {}",
+            info.get_fullname()
+        );
+        self.advertised.push(info.get_fullname().to_owned());
+        self.daemon.register(info)?;
+        Ok(())
+    }
+
+    /// Every node that answers within `timeout`.
+    pub async fn browse(&self, timeout: Duration) -> Result<Vec<Discovered>> {
+        let receiver = self.daemon.browse(SERVICE_TYPE)?;
+        let deadline = Instant::now() + timeout;
+        let mut found = HashMap::new();
+        while let Ok(Ok(event)) = tokio::time::timeout_at(deadline, receiver.recv_async()).await {
+            match event {
+                ServiceEvent::ServiceResolved(info) => match Discovered::from_info(&info) {
+                    Some(node) => {
+                        debug!("Discovered {node}");
+                        found.insert(info.get_fullname().to_owned(), node);
+                    }
+                    None => warn!("Ignore malformed service {}", info.get_fullname()),
+                },
+                ServiceEvent::ServiceRemoved(_, fullname) => {
+                    found.remove(&fullname);
+                }
+                _ => {}
+            }
+        }
+        self.daemon.stop_browse(SERVICE_TYPE)?;
+        let mut found: Vec<_> = found.into_values().collect();
+        found.sort_by(|a, b| a.node.cmp(&b.node));
+        Ok(found)
+    }
+
+    /// The first compatible node that answers within `timeout`.
+    pub async fn find(&self, timeout: Duration) -> Result<Discovered> {
+        let receiver = self.daemon.browse(SERVICE_TYPE)?;
+        let deadline = Instant::now() + timeout;
+        let mut found = None;
+        while let Ok(Ok(event)) = tokio::time::timeout_at(deadline, receiver.recv_async()).await {
+            let ServiceEvent::ServiceResolved(info) = event else {
+                continue;
+            };
+            match Discovered::from_info(&info) {
+                Some(node) if node.is_compatible() && node.url().is_some() => {
+                    found = Some(node);
+                    break;
+                }
+                Some(node) => info!("Skip incompatible {node}"),
+                None => warn!("Ignore malformed service {}", info.get_fullname()),
+            }
+        }
+        self.daemon.stop_browse(SERVICE_TYPE)?;
+        found.ok_or_else(|| {
+            Error::NotFound(format!(
+                "No synclip node found on the network within {timeout:?}"
+            ))
+        })
+    }
+
+    /// Withdraw the advertisements and stop the mDNS daemon.
+    pub fn shutdown(self) -> Result<()> {
+        for fullname in &self.advertised {
+            // Waits for the goodbye packet so browsers drop us right away.
+            let status = self.daemon.unregister(fullname)?;
+            let _ = status.recv_timeout(Duration::from_secs(1));
+        }
+        self.daemon.shutdown()?;
+        Ok(())
+    }
+}
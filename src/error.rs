@@ -0,0 +1,307 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use color_eyre::Report;
+use tokio::sync::watch;
+use tonic::codegen::http::uri::InvalidUri;
+use tonic::{Code, Status};
+
+use crate::clipboard::backend::Transient;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// What a backend failed with, whatever the clipboard it drives.
+pub type BackendError = Box<dyn std::error::Error + Send + Sync>;
+
+/// What can go wrong syncing, for callers to match on.
+///
+/// Servers answer with the matching gRPC [`Status`] and clients turn the
+/// status back into the same variant, see the `From` impls.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The local clipboard cannot be read or written.
+    Backend {
+        /// What was being done, like "Open the clipboard backend".
+        context: Option<String>,
+        source: BackendError,
+    },
+    /// The other node cannot be reached or the connection broke.
+    Transport {
+        /// What was being done, like "Connect to http://clip:5505".
+        context: Option<String>,
+        source: TransportError,
+    },
+    /// The shared token is invalid or was refused.
+    Auth(String),
+    /// A clip over the size limit.
+    PayloadTooLarge(String),
+    /// The other node speaks another protocol than this one.
+    Protocol(String),
+    /// A clip arrived damaged, like a chunked transfer cut short. Only that
+    /// clip is lost.
+    Corrupt(String),
+    /// The other node does not allow it, like a receive-only peer sending.
+    Forbidden(String),
+    /// What was asked for does not exist, like an expired history entry.
+    NotFound(String),
+    /// A clip cannot be encrypted or decrypted, like one sealed with another
+    /// passphrase.
+    Crypto(String),
+    /// The node is set up wrong, like a builder without a role.
+    Config(String),
+    /// A file cannot be read or written, like a certificate or the history.
+    Io { context: String, source: io::Error },
+    /// mDNS fails to advertise or browse.
+    Discovery(mdns_sd::Error),
+    /// The node shut down.
+    Shutdown,
+    /// Any other status from the other node.
+    Remote(Box<Status>),
+}
+
+/// Why the other node cannot be reached, see [`Error::Transport`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TransportError {
+    /// Connecting failed or the endpoint is set up wrong, like its TLS.
+    Connect(tonic::transport::Error),
+    /// The address is not a valid URI.
+    Address(InvalidUri),
+    /// A call broke off, like when the connection was lost.
+    Status(Box<Status>),
+    /// The other node ended the stream.
+    Closed,
+}
+
+impl Error {
+    /// The local clipboard failed with what its backend reported.
+    pub fn backend(report: Report) -> Self {
+        // Boxed as is, a report hides the type of the error it holds.
+        let source: BackendError = match report.downcast::<Transient>() {
+            Ok(transient) => Box::new(transient),
+            Err(report) => report.into(),
+        };
+        Error::Backend {
+            context: None,
+            source,
+        }
+    }
+
+    /// Reading or writing a file failed while doing `context`.
+    pub fn io(context: impl Display, source: io::Error) -> Self {
+        Error::Io {
+            context: context.to_string(),
+            source,
+        }
+    }
+
+    /// Why the local clipboard could not be read, if retrying may help.
+    pub fn transient(&self) -> Option<Transient> {
+        let Error::Backend { source, .. } = self else {
+            return None;
+        };
+        let mut error: Option<&(dyn std::error::Error + 'static)> = Some(source.as_ref());
+        while let Some(e) = error {
+            if let Some(transient) = e.downcast_ref::<Transient>() {
+                return Some(*transient);
+            }
+            error = e.source();
+        }
+        None
+    }
+
+    /// The same error, its message prefixed with what was being done.
+    pub fn context(self, context: impl Display) -> Self {
+        let prefix = |inner: Option<String>| match inner {
+            Some(inner) => Some(format!("{context}: {inner}")),
+            None => Some(context.to_string()),
+        };
+        match self {
+            Error::Backend {
+                context: inner,
+                source,
+            } => Error::Backend {
+                context: prefix(inner),
+                source,
+            },
+            Error::Transport {
+                context: inner,
+                source,
+            } => Error::Transport {
+                context: prefix(inner),
+                source,
+            },
+            Error::Io {
+                context: inner,
+                source,
+            } => Error::Io {
+                context: format!("{context}: {inner}"),
+                source,
+            },
+            Error::Auth(message) => Error::Auth(format!("{context}: {message}")),
+            Error::PayloadTooLarge(message) => {
+                Error::PayloadTooLarge(format!("{context}: {message}"))
+            }
+            Error::Protocol(message) => Error::Protocol(format!("{context}: {message}")),
+            Error::Corrupt(message) => Error::Corrupt(format!("{context}: {message}")),
+            Error::Forbidden(message) => Error::Forbidden(format!("{context}: {message}")),
+            Error::NotFound(message) => Error::NotFound(format!("{context}: {message}")),
+            Error::Crypto(message) => Error::Crypto(format!("{context}: {message}")),
+            Error::Config(message) => Error::Config(format!("{context}: {message}")),
+            Error::Discovery(e) => Error::Discovery(e),
+            Error::Shutdown => Error::Shutdown,
+            Error::Remote(status) => Error::Remote(Box::new(Status::new(
+                status.code(),
+                format!("{context}: {}", status.message()),
+            ))),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Backend { context, source } => {
+                write!(f, "Clipboard backend unavailable")?;
+                write_chain(f, context.as_deref(), source.as_ref())
+            }
+            Error::Transport { context, source } => {
+                write!(f, "Connection failed")?;
+                write_chain(f, context.as_deref(), source)
+            }
+            Error::Auth(message) => write!(f, "Authentication failed: {message}"),
+            Error::PayloadTooLarge(message)
+            | Error::Protocol(message)
+            | Error::Corrupt(message)
+            | Error::Forbidden(message)
+            | Error::NotFound(message)
+            | Error::Crypto(message)
+            | Error::Config(message) => write!(f, "{message}"),
+            Error::Io { context, source } => write!(f, "{context}: {source}"),
+            Error::Discovery(e) => write!(f, "Discovery failed: {e}"),
+            Error::Shutdown => write!(f, "The node shut down"),
+            Error::Remote(status) => write!(f, "{:?}: {}", status.code(), status.message()),
+        }
+    }
+}
+
+/// `: context: error: its source: ...`, like a report printed with `{:#}`.
+fn write_chain(
+    f: &mut Formatter<'_>,
+    context: Option<&str>,
+    error: &(dyn std::error::Error + 'static),
+) -> std::fmt::Result {
+    if let Some(context) = context {
+        write!(f, ": {context}")?;
+    }
+    let mut error = Some(error);
+    while let Some(e) = error {
+        write!(f, ": {e}")?;
+        error = e.source();
+    }
+    Ok(())
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Backend { source, .. } => Some(source.as_ref()),
+            Error::Transport { source, .. } => Some(source),
+            Error::Io { source, .. } => Some(source),
+            Error::Discovery(e) => Some(e),
+            Error::Remote(status) => Some(status.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl Display for TransportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportError::Connect(_) => write!(f, "Cannot connect"),
+            TransportError::Address(_) => write!(f, "Invalid address"),
+            TransportError::Status(status) => {
+                write!(f, "{:?}: {}", status.code(), status.message())
+            }
+            TransportError::Closed => write!(f, "The stream was closed"),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TransportError::Connect(e) => Some(e),
+            TransportError::Address(e) => Some(e),
+            TransportError::Status(_) | TransportError::Closed => None,
+        }
+    }
+}
+
+impl From<Error> for Status {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Backend { .. } | Error::Io { .. } | Error::Discovery(_) => {
+                Status::internal(error.to_string())
+            }
+            Error::Transport { .. } | Error::Shutdown => Status::unavailable(error.to_string()),
+            Error::Auth(message) => Status::unauthenticated(message),
+            Error::PayloadTooLarge(message) => Status::resource_exhausted(message),
+            Error::Protocol(message) | Error::Crypto(message) => {
+                Status::failed_precondition(message)
+            }
+            Error::Corrupt(message) => Status::data_loss(message),
+            Error::Forbidden(message) => Status::permission_denied(message),
+            Error::NotFound(message) => Status::not_found(message),
+            Error::Config(message) => Status::invalid_argument(message),
+            Error::Remote(status) => *status,
+        }
+    }
+}
+
+impl From<Status> for Error {
+    fn from(status: Status) -> Self {
+        let message = status.message().to_owned();
+        match status.code() {
+            Code::Unauthenticated => Error::Auth(message),
+            Code::PermissionDenied => Error::Forbidden(message),
+            Code::ResourceExhausted => Error::PayloadTooLarge(message),
+            Code::FailedPrecondition | Code::Unimplemented => Error::Protocol(message),
+            Code::NotFound => Error::NotFound(message),
+            Code::DataLoss => Error::Corrupt(message),
+            // A broken connection shows up as unknown.
+            Code::Unavailable | Code::Cancelled | Code::DeadlineExceeded | Code::Unknown => {
+                TransportError::Status(Box::new(status)).into()
+            }
+            _ => Error::Remote(Box::new(status)),
+        }
+    }
+}
+
+impl From<TransportError> for Error {
+    fn from(source: TransportError) -> Self {
+        Error::Transport {
+            context: None,
+            source,
+        }
+    }
+}
+
+impl From<tonic::transport::Error> for Error {
+    fn from(error: tonic::transport::Error) -> Self {
+        TransportError::Connect(error).into()
+    }
+}
+
+impl From<mdns_sd::Error> for Error {
+    fn from(error: mdns_sd::Error) -> Self {
+        Error::Discovery(error)
+    }
+}
+
+impl From<watch::error::RecvError> for Error {
+    fn from(_: watch::error::RecvError) -> Self {
+        Error::Shutdown
+    }
+}
@@ -0,0 +1,288 @@
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::error::{Error, Result};
+use crate::Content;
+
+/// Set to "secret" by KeePassXC and KDE applications on the clipboard.
+pub const PASSWORD_MANAGER_HINT: &str = "x-kde-passwordManagerHint";
+/// Offered by password managers that follow the nspasteboard.org conventions.
+pub const CONCEALED_TYPE: &str = "org.nspasteboard.ConcealedType";
+pub const TRANSIENT_TYPE: &str = "org.nspasteboard.TransientType";
+
+/// Clipboard targets that mark a clip as not meant to be kept or shared,
+/// backends that see them add them to the clip as empty representations.
+pub const HINTS: [&str; 3] = [PASSWORD_MANAGER_HINT, CONCEALED_TYPE, TRANSIENT_TYPE];
+
+/// What replaces a redacted match.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// What to do with a clip a rule matches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Keep the clip on this machine.
+    Block,
+    /// Send the clip with the matches replaced by [`REDACTED`], clips a
+    /// password manager marked stay local as a whole.
+    Redact,
+    /// Send the clip unchanged and only note the match in the local log,
+    /// to try a rule out.
+    Log,
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "block" => Ok(Action::Block),
+            "redact" => Ok(Action::Redact),
+            "log" => Ok(Action::Log),
+            _ => Err(Error::Config(format!(
+                "Unknown filter action {value}, use block, redact or log"
+            ))),
+        }
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Block => write!(f, "block"),
+            Action::Redact => write!(f, "redact"),
+            Action::Log => write!(f, "log"),
+        }
+    }
+}
+
+/// A pattern for sensitive text.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    name: String,
+    regex: Regex,
+    action: Action,
+    /// Confirms a match the pattern alone cannot, like a card checksum.
+    check: Option<fn(&str) -> bool>,
+}
+
+impl Rule {
+    pub fn new(name: impl Into<String>, pattern: &str, action: Action) -> Result<Self> {
+        let name = name.into();
+        let regex = Regex::new(pattern)
+            .map_err(|e| Error::Config(format!("Invalid pattern for rule {name}: {e}")))?;
+        Ok(Self {
+            name,
+            regex,
+            action,
+            check: None,
+        })
+    }
+
+    fn checked(mut self, check: fn(&str) -> bool) -> Self {
+        self.check = Some(check);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match self.check {
+            Some(check) => self.regex.find_iter(text).any(|m| check(m.as_str())),
+            None => self.regex.is_match(text),
+        }
+    }
+
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.regex.replace_all(text, |captures: &Captures| {
+            let found = &captures[0];
+            match self.check {
+                Some(check) if !check(found) => found.to_owned(),
+                _ => REDACTED.to_owned(),
+            }
+        })
+    }
+}
+
+/// Private keys, AWS keys, JSON Web Tokens and payment card numbers.
+pub fn builtin(action: Action) -> Vec<Rule> {
+    let rules = [
+        (
+            "private-key",
+            r"-----BEGIN [A-Z0-9 ]*PRIVATE KEY( BLOCK)?-----[\s\S]*?-----END [A-Z0-9 ]*PRIVATE KEY( BLOCK)?-----",
+        ),
+        ("aws-access-key", r"\b(AKIA|ASIA)[0-9A-Z]{16}\b"),
+        (
+            "aws-secret-key",
+            r"(?i)aws_secret_access_key\s*[:=]\s*[A-Za-z0-9/+=]{40}",
+        ),
+        (
+            "jwt",
+            r"\beyJ[A-Za-z0-9_-]+\.eyJ[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+",
+        ),
+    ];
+    let mut rules: Vec<Rule> = rules
+        .into_iter()
+        .map(|(name, pattern)| Rule::new(name, pattern, action).expect("valid builtin rule"))
+        .collect();
+    rules.push(
+        Rule::new("credit-card", r"\b\d(?:[ -]?\d){12,18}\b", action)
+            .expect("valid builtin rule")
+            .checked(luhn),
+    );
+    rules
+}
+
+/// The checksum of payment card numbers.
+fn luhn(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match i % 2 {
+            0 => digit,
+            _ if digit * 2 > 9 => digit * 2 - 9,
+            _ => digit * 2,
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+/// Stops sensitive clips from leaving the machine, applied to every local
+/// clip before it is sent.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    rules: Arc<Vec<Rule>>,
+    hints: Action,
+}
+
+impl Filter {
+    /// `hints` is what to do with clips a password manager marked.
+    pub fn new(rules: Vec<Rule>, hints: Action) -> Self {
+        Self {
+            rules: Arc::new(rules),
+            hints,
+        }
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// The clip to send, `None` if it must stay local.
+    pub fn apply(&self, content: Content) -> Option<Content> {
+        if content.is_sealed() {
+            return Some(content);
+        }
+        let mut content = strip_hints(content, self.hints)?;
+        let matched: Vec<&Rule> = self
+            .rules
+            .iter()
+            .filter(|rule| texts(&content).any(|text| rule.is_match(text)))
+            .collect();
+        if let Some(rule) = matched.iter().find(|rule| rule.action == Action::Block) {
+            warn!(
+                "Keep local [Filter] {}: {} bytes",
+                rule.name,
+                content.size()
+            );
+            return None;
+        }
+        for rule in matched {
+            match rule.action {
+                Action::Redact => {
+                    info!("Redact [Filter] {}", rule.name);
+                    redact(&mut content, rule);
+                }
+                Action::Log => info!("Match [Filter] {}, sent as is", rule.name),
+                Action::Block => unreachable!("blocked above"),
+            }
+        }
+        Some(content)
+    }
+}
+
+impl Default for Filter {
+    /// The built-in rules, blocking, and password manager hints honoured.
+    fn default() -> Self {
+        Self::new(builtin(Action::Block), Action::Block)
+    }
+}
+
+/// Whether a password manager marked the clip.
+pub fn is_hinted(content: &Content) -> bool {
+    content
+        .representations
+        .iter()
+        .any(|representation| match representation.mime_type.as_str() {
+            PASSWORD_MANAGER_HINT => representation.data.trim_ascii() == b"secret",
+            mime_type => HINTS.contains(&mime_type),
+        })
+}
+
+/// The clip without hint representations, handled as `action` says if
+/// there were any.
+fn strip_hints(mut content: Content, action: Action) -> Option<Content> {
+    let hinted = is_hinted(&content);
+    content
+        .representations
+        .retain(|representation| !HINTS.contains(&representation.mime_type.as_str()));
+    if !hinted {
+        return Some(content);
+    }
+    match action {
+        Action::Block => {
+            warn!("Keep local [Filter] password manager hint");
+            None
+        }
+        // The whole clip is the secret, a placeholder sent instead would
+        // only overwrite the other clipboards.
+        Action::Redact => {
+            warn!("Keep local [Filter] password manager hint, nothing left to send");
+            None
+        }
+        Action::Log => {
+            info!("Match [Filter] password manager hint, sent as is");
+            Some(content)
+        }
+    }
+}
+
+/// The text representations of a clip, binary ones are not searched.
+fn texts(content: &Content) -> impl Iterator<Item = &str> {
+    std::iter::once(content.text.as_str()).chain(
+        content
+            .representations
+            .iter()
+            .filter(|representation| representation.mime_type.starts_with("text/"))
+            .filter_map(|representation| std::str::from_utf8(&representation.data).ok()),
+    )
+}
+
+fn redact(content: &mut Content, rule: &Rule) {
+    content.text = rule.redact(&content.text).into_owned();
+    for representation in &mut content.representations {
+        if !representation.mime_type.starts_with("text/") {
+            continue;
+        }
+        if let Ok(text) = std::str::from_utf8(&representation.data) {
+            representation.data = rule.redact(text).into_owned().into_bytes();
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tonic::codec::CompressionEncoding;
+
+use crate::compression::{self, DEFAULT_THRESHOLD, GZIP};
+use crate::content::{IMAGE_PNG, TEXT_HTML, TEXT_PLAIN, TEXT_RTF, TEXT_URI_LIST};
+use crate::direction::Direction;
+use crate::error::{Error, Result};
+use crate::{Content, Greeting, PROTOCOL_VERSION};
+
+/// The largest clip a node accepts unless configured otherwise, clips over
+/// [`crate::chunk::CHUNK_SIZE`] travel in chunks.
+pub const DEFAULT_MAX_PAYLOAD: u64 = 32 * 1024 * 1024;
+
+/// What a node can do, each side of a connection narrows it down to what
+/// both can do.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Features {
+    pub content_types: Vec<String>,
+    pub compression: Vec<String>,
+    pub max_payload: u64,
+    /// Clips smaller than this are sent uncompressed. Not exchanged, each
+    /// side decides for what it sends.
+    pub compression_threshold: u64,
+    /// Whether this node sends, receives or both.
+    pub direction: Direction,
+    /// Servers only, the most a peer may do by IP address. Not exchanged,
+    /// peers learn theirs from the server's reply.
+    pub peer_directions: BTreeMap<String, Direction>,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            content_types: [TEXT_PLAIN, TEXT_HTML, TEXT_RTF, TEXT_URI_LIST, IMAGE_PNG]
+                .map(str::to_owned)
+                .to_vec(),
+            compression: vec![GZIP.to_owned()],
+            max_payload: DEFAULT_MAX_PAYLOAD,
+            compression_threshold: DEFAULT_THRESHOLD,
+            direction: Direction::Both,
+            peer_directions: BTreeMap::new(),
+        }
+    }
+}
+
+impl Features {
+    pub fn hello(&self, node_id: &str) -> Greeting {
+        Greeting {
+            protocol_version: PROTOCOL_VERSION,
+            node_id: node_id.to_owned(),
+            content_types: self.content_types.clone(),
+            compression: self.compression.clone(),
+            max_payload: self.max_payload,
+            direction: self.direction.to_string(),
+        }
+    }
+
+    /// What both this node and the one that said `hello` can do, the
+    /// direction is left to [`Features::restrict`].
+    pub fn narrow(&self, hello: &Greeting) -> Self {
+        let common = |ours: &[String], theirs: &[String]| {
+            ours.iter()
+                .filter(|item| theirs.contains(item))
+                .cloned()
+                .collect()
+        };
+        Self {
+            content_types: common(&self.content_types, &hello.content_types),
+            compression: common(&self.compression, &hello.compression),
+            max_payload: match hello.max_payload {
+                0 => self.max_payload,
+                theirs => self.max_payload.min(theirs),
+            },
+            compression_threshold: self.compression_threshold,
+            direction: self.direction,
+            peer_directions: self.peer_directions.clone(),
+        }
+    }
+
+    /// Narrow the direction to what the other side allows, refused if
+    /// nothing is left.
+    pub fn restrict(mut self, allowed: Direction) -> Result<Self> {
+        self.direction = self.direction.narrow(allowed).ok_or_else(|| {
+            Error::Forbidden(format!(
+                "Direction {} is not allowed, only {allowed}",
+                self.direction
+            ))
+        })?;
+        Ok(self)
+    }
+
+    /// The most a peer connecting from `address` may do, both unless
+    /// `peer_directions` lists its IP address. Never looked up by the id a
+    /// peer sends, any client can claim any id.
+    pub fn direction_for(&self, address: Option<SocketAddr>) -> Direction {
+        address
+            .and_then(|address| self.peer_directions.get(&address.ip().to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The codec clips are compressed with, the first one this build knows.
+    pub fn codec(&self) -> Option<&str> {
+        self.compression
+            .iter()
+            .map(String::as_str)
+            .find(|name| compression::encoding(name).is_some())
+    }
+
+    /// How to send a clip of `size` encoded bytes, `None` for uncompressed.
+    pub fn compress(&self, size: u64) -> Option<CompressionEncoding> {
+        if size < self.compression_threshold {
+            return None;
+        }
+        self.codec().and_then(compression::encoding)
+    }
+
+    /// Refuse a clip over the size limit, the one copying it keeps it.
+    pub fn check_size(&self, size: u64) -> Result<()> {
+        if size <= self.max_payload {
+            return Ok(());
+        }
+        Err(Error::PayloadTooLarge(format!(
+            "Clip of {size} bytes is over the {} byte limit",
+            self.max_payload
+        )))
+    }
+
+    /// The clip without the representations the other side cannot hold,
+    /// sealed clips are opaque and pass as they are.
+    pub fn filter(&self, mut content: Content) -> Content {
+        let accepts = |mime_type: &str| self.content_types.iter().any(|t| t == mime_type);
+        if !accepts(TEXT_PLAIN) {
+            content.text.clear();
+        }
+        content
+            .representations
+            .retain(|representation| accepts(&representation.mime_type));
+        content
+    }
+}
+
+/// Refuse a node built from another protocol before it fails in obscure ways.
+pub fn check_version(hello: &Greeting) -> Result<()> {
+    if hello.protocol_version == PROTOCOL_VERSION {
+        return Ok(());
+    }
+    Err(Error::Protocol(format!(
+        "Node {} speaks synclip protocol {} but this one speaks {}, upgrade both to the same release",
+        hello.node_id, hello.protocol_version, PROTOCOL_VERSION
+    )))
+}
+
+/// A peer as it said hello: its id with the address it said it from, so a
+/// hello under another peer's id is no way to change what that peer gets.
+type PeerKey = (String, Option<SocketAddr>);
+
+/// What a peer agreed on, with the hello it was agreed in and whether a
+/// stream of clips holds it.
+struct Agreed {
+    hello: u64,
+    features: Features,
+    held: bool,
+}
+
+/// The features negotiated with every peer that said hello, by peer id and
+/// address, until the stream of clips it opened after ends.
+#[derive(Clone, Default)]
+pub struct Negotiated {
+    peers: Arc<Mutex<HashMap<PeerKey, Agreed>>>,
+    hellos: Arc<AtomicU64>,
+}
+
+impl Negotiated {
+    /// Remember what a peer agreed on, forgetting the hellos it never opened
+    /// a stream after from the same host.
+    pub fn insert(&self, id: String, address: Option<SocketAddr>, features: Features) {
+        let hello = self.hellos.fetch_add(1, Ordering::Relaxed);
+        let host = address.map(|address| address.ip());
+        let mut peers = self.peers.lock().unwrap();
+        peers.retain(|(other, at), agreed| {
+            agreed.held || *other != id || at.map(|address| address.ip()) != host
+        });
+        let agreed = Agreed {
+            hello,
+            features,
+            held: false,
+        };
+        peers.insert((id, address), agreed);
+    }
+
+    pub fn get(&self, id: &str, address: Option<SocketAddr>) -> Option<Features> {
+        self.peers
+            .lock()
+            .unwrap()
+            .get(&(id.to_owned(), address))
+            .map(|agreed| agreed.features.clone())
+    }
+
+    /// Keep what a peer agreed on until the guard is dropped, then forget
+    /// it unless the peer said hello again meanwhile.
+    pub fn hold(&self, id: &str, address: Option<SocketAddr>) -> NegotiatedGuard {
+        let key = (id.to_owned(), address);
+        let hello = self.peers.lock().unwrap().get_mut(&key).map(|agreed| {
+            agreed.held = true;
+            agreed.hello
+        });
+        NegotiatedGuard {
+            negotiated: self.clone(),
+            key,
+            hello,
+        }
+    }
+}
+
+pub struct NegotiatedGuard {
+    negotiated: Negotiated,
+    key: PeerKey,
+    hello: Option<u64>,
+}
+
+impl Drop for NegotiatedGuard {
+    fn drop(&mut self) {
+        let Some(hello) = self.hello else {
+            return;
+        };
+        let mut peers = self.negotiated.peers.lock().unwrap();
+        if peers
+            .get(&self.key)
+            .is_some_and(|agreed| agreed.hello == hello)
+        {
+            peers.remove(&self.key);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use prost::Message;
+use tracing::{info, warn};
+
+use crate::error::{Error, Result};
+use crate::util::now_millis;
+use crate::{Content, HistoryEntries, HistoryEntry};
+
+pub const DEFAULT_MAX_ENTRIES: usize = 100;
+pub const DEFAULT_MAX_BYTES: usize = 16 * 1024 * 1024;
+
+/// The clips a node has seen, newest last, bounded by count and bytes.
+///
+/// A persistent history is rewritten to its file on every new clip.
+#[derive(Clone)]
+pub struct History {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    entries: VecDeque<HistoryEntry>,
+    bytes: usize,
+    next_id: u64,
+    max_entries: usize,
+    max_bytes: usize,
+    path: Option<PathBuf>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ENTRIES, DEFAULT_MAX_BYTES)
+    }
+}
+
+impl History {
+    /// An in-memory history.
+    pub fn new(max_entries: usize, max_bytes: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                entries: VecDeque::new(),
+                bytes: 0,
+                next_id: 1,
+                max_entries,
+                max_bytes,
+                path: None,
+            })),
+        }
+    }
+
+    /// A history saved to `path`, starting with what the file already holds.
+    pub fn persistent(
+        max_entries: usize,
+        max_bytes: usize,
+        path: impl Into<PathBuf>,
+    ) -> Result<Self> {
+        let path = path.into();
+        let history = Self::new(max_entries, max_bytes);
+        {
+            let mut inner = history.inner.lock().unwrap();
+            for entry in load(&path)? {
+                inner.next_id = inner.next_id.max(entry.id + 1);
+                inner.bytes += entry.size as usize;
+                inner.entries.push_back(entry);
+            }
+            inner.evict();
+            info!(
+                "Loaded {} history entries from {:?}",
+                inner.entries.len(),
+                path
+            );
+            inner.path = Some(path);
+        }
+        Ok(history)
+    }
+
+    /// Remember a clip, returns its id or `None` if it was not kept: empty,
+    /// the same as the newest entry, or larger than the whole history.
+    pub fn record(&self, origin: Option<&str>, content: &Content) -> Option<u64> {
+        if content.is_empty() {
+            return None;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        if inner
+            .entries
+            .back()
+            .is_some_and(|entry| entry.content.as_ref() == Some(content))
+        {
+            return None;
+        }
+        let size = content.encoded_len();
+        if size > inner.max_bytes {
+            warn!("Skip history for a clip of {size} bytes");
+            return None;
+        }
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.bytes += size;
+        inner.entries.push_back(HistoryEntry {
+            id,
+            created_at: now_millis(),
+            origin: origin.unwrap_or_default().to_owned(),
+            content: Some(content.clone()),
+            size: size as u64,
+            summary: content.summary(),
+        });
+        inner.evict();
+        if let Err(e) = inner.save() {
+            warn!("Save history error: {:?}", e);
+        }
+        Some(id)
+    }
+
+    /// The newest `limit` entries without their content, newest first, all
+    /// of them if `limit` is 0.
+    pub fn list(&self, limit: usize) -> Vec<HistoryEntry> {
+        let inner = self.inner.lock().unwrap();
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        inner
+            .entries
+            .iter()
+            .rev()
+            .take(limit)
+            .map(|entry| HistoryEntry {
+                content: None,
+                ..entry.clone()
+            })
+            .collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<HistoryEntry> {
+        let inner = self.inner.lock().unwrap();
+        inner.entries.iter().find(|entry| entry.id == id).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The total encoded size of the kept clips.
+    pub fn bytes(&self) -> usize {
+        self.inner.lock().unwrap().bytes
+    }
+}
+
+impl Inner {
+    fn evict(&mut self) {
+        while self.entries.len() > self.max_entries || self.bytes > self.max_bytes {
+            let Some(entry) = self.entries.pop_front() else {
+                break;
+            };
+            self.bytes -= entry.size as usize;
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let write = || {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let entries = HistoryEntries {
+                entries: self.entries.iter().cloned().collect(),
+            };
+            // Written aside then renamed so a crash never leaves half a file.
+            let partial = path.with_extension("partial");
+            create_private(&partial)?.write_all(&entries.encode_to_vec())?;
+            fs::rename(&partial, path)
+        };
+        write().map_err(|e| Error::io(format!("Write history {:?}", path), e))
+    }
+}
+
+/// A file only this user can read, replacing any left at `path`: clips are
+/// as private as the clipboard they came from.
+fn create_private(path: &Path) -> io::Result<File> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = File::options();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+fn load(path: &Path) -> Result<Vec<HistoryEntry>> {
+    match fs::read(path) {
+        Ok(bytes) => {
+            let entries = HistoryEntries::decode(bytes.as_slice()).map_err(|e| {
+                Error::io(
+                    format!("Decode history {:?}", path),
+                    io::Error::new(ErrorKind::InvalidData, e),
+                )
+            })?;
+            Ok(entries.entries)
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(Error::io(format!("Read history {:?}", path), e)),
+    }
+}
@@ -1,9 +1,35 @@
+pub mod auth;
+pub mod chunk;
 pub mod client;
 pub mod clipboard;
+pub mod compression;
+pub mod config;
+pub mod content;
+#[cfg(unix)]
+pub mod control;
+pub mod crypto;
+#[cfg(unix)]
+pub mod daemon;
+pub mod direction;
+pub mod discovery;
+pub mod error;
+pub mod filter;
+pub mod handshake;
+pub mod history;
+pub mod mesh;
+pub mod metrics;
+pub mod node;
 pub mod server;
+pub mod stamp;
+pub mod tls;
+pub mod util;
 
 mod proto {
     tonic::include_proto!("synclip");
 }
 
+pub use error::{Error, Result};
 pub use proto::*;
+
+/// Bumped on every incompatible change to the wire protocol.
+pub const PROTOCOL_VERSION: u32 = 2;
@@ -1,12 +1,36 @@
-use clap::Parser;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+use color_eyre::eyre::{eyre, Context};
 use color_eyre::Result;
 use tokio_util::sync::CancellationToken;
-use tracing::info;
+use tracing::{info, warn};
 
 use synclip::clipboard::local_clipboard::LocalClipboard;
-use synclip::clipboard::Clipboard;
-use synclip::{client, server};
+use synclip::clipboard::{Clipboard, VirtualClipboard};
+use synclip::config::{parse_codecs, Config};
+#[cfg(unix)]
+use synclip::control::{self, ControlServer};
+use synclip::crypto::Cipher;
+#[cfg(unix)]
+use synclip::daemon::{self, PidFile};
+use synclip::direction::Direction;
+use synclip::discovery::Discovery;
+use synclip::filter::Filter;
+use synclip::mesh::{MeshNode, PeerAddress};
+use synclip::node::prepare_initial;
+use synclip::util::now_millis;
+use synclip::{
+    client, server, Empty, HistoryEntry, HistoryEntryId, HistoryQuery, NodeStatus, PauseRequest,
+};
+
+/// How long `client --discover` looks for a server.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// Settings are layered: defaults, the config file, `SYNCLIP_*` environment
+/// variables, then the flags below.
 #[derive(Parser)]
 #[command(
 author,
@@ -14,61 +38,980 @@ version,
 about,
 long_about = None
 )]
-pub enum Cli {
+pub struct Cli {
+    /// The TOML config file [default: $XDG_CONFIG_HOME/synclip/config.toml]
+    #[arg(long, global = true, env = "SYNCLIP_CONFIG")]
+    config: Option<PathBuf>,
+    /// The control socket of the daemon [env: SYNCLIP_CONTROL_SOCKET] [default: $XDG_RUNTIME_DIR/synclip/control.sock]
+    #[arg(long, global = true)]
+    socket: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(flatten)]
+    Node(NodeCommand),
+    /// Run a server, client or peer in the background, or under systemd with Type=notify
+    Daemon {
+        /// Stay attached to the terminal, implied under systemd
+        #[arg(long)]
+        foreground: bool,
+        /// The file holding the pid of the daemon [env: SYNCLIP_PID_FILE] [default: $XDG_RUNTIME_DIR/synclip/synclip.pid]
+        #[arg(long)]
+        pid_file: Option<PathBuf>,
+        /// Where to log once detached [env: SYNCLIP_LOG_FILE] [default: $XDG_RUNTIME_DIR/synclip/synclip.log]
+        #[arg(long)]
+        log_file: Option<PathBuf>,
+        #[command(subcommand)]
+        node: NodeCommand,
+    },
+    #[command(flatten)]
+    Control(ControlCommand),
+    /// List the synclip nodes advertised on the LAN
+    Discover {
+        /// Seconds to wait for answers
+        #[arg(long, default_value_t = 3)]
+        timeout: u64,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Browse and restore the clips a server, or the running daemon, has seen
+    History {
+        /// The server to ask (lke http://[remote]:[port]) [env: SYNCLIP_ADDRESS]
+        #[arg(long)]
+        address: Option<String>,
+        /// Ask the running daemon over its control socket, whatever it runs as
+        #[arg(long, conflicts_with = "address")]
+        daemon: bool,
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+}
+
+/// The ways to run a node.
+#[derive(Subcommand)]
+pub enum NodeCommand {
     /// Run as a server
     Server {
-        /// The address to connect to
-        port: u16,
+        /// The port to listen on, on every interface
+        #[arg(conflicts_with = "listen")]
+        port: Option<u16>,
+        /// The address to listen on [env: SYNCLIP_LISTEN] [default: 0.0.0.0:5505]
+        #[arg(long)]
+        listen: Option<SocketAddr>,
+        /// The PEM certificate to serve TLS with [env: SYNCLIP_TLS_CERT]
+        #[arg(long)]
+        tls_cert: Option<PathBuf>,
+        /// The PEM private key of the TLS certificate [env: SYNCLIP_TLS_KEY]
+        #[arg(long)]
+        tls_key: Option<PathBuf>,
+        /// The PEM CA certificate client certificates are verified against [env: SYNCLIP_TLS_CLIENT_CA]
+        #[arg(long)]
+        tls_client_ca: Option<PathBuf>,
+        /// Reject clients without a certificate signed by the client CA [env: SYNCLIP_REQUIRE_CLIENT_CERT]
+        #[arg(long)]
+        require_client_cert: bool,
+        /// Announce this server on the LAN over mDNS [env: SYNCLIP_ADVERTISE]
+        #[arg(long)]
+        advertise: bool,
+        #[command(flatten)]
+        common: CommonArgs,
     },
     /// Run as a client
     Client {
-        /// The address to connect to (lke http://[remote]:[port])
-        address: String,
+        /// The address to connect to (lke http://[remote]:[port], https:// for TLS) [env: SYNCLIP_ADDRESS]
+        #[arg(conflicts_with = "discover")]
+        address: Option<String>,
+        /// Find the server on the LAN over mDNS instead [env: SYNCLIP_DISCOVER]
+        #[arg(long)]
+        discover: bool,
+        /// The PEM CA certificate the server is verified against, instead of the system roots [env: SYNCLIP_TLS_CA]
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// The PEM certificate to authenticate to the server with [env: SYNCLIP_TLS_CERT]
+        #[arg(long)]
+        tls_cert: Option<PathBuf>,
+        /// The PEM private key of the client certificate [env: SYNCLIP_TLS_KEY]
+        #[arg(long)]
+        tls_key: Option<PathBuf>,
+        /// The domain name to verify the server certificate for, defaults to the address host [env: SYNCLIP_TLS_DOMAIN]
+        #[arg(long)]
+        tls_domain: Option<String>,
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// Run as a mesh peer, serving and connecting to other peers at once
+    Peer {
+        /// The address to listen on [env: SYNCLIP_LISTEN] [default: 0.0.0.0:5505]
+        #[arg(long)]
+        listen: Option<SocketAddr>,
+        /// Another peer to connect to (lke http://[remote]:[port]), repeat for several [env: SYNCLIP_PEERS]
+        #[arg(long = "peer")]
+        peers: Vec<String>,
+        /// The PEM certificate to serve TLS and authenticate to other peers with [env: SYNCLIP_TLS_CERT]
+        #[arg(long)]
+        tls_cert: Option<PathBuf>,
+        /// The PEM private key of the certificate [env: SYNCLIP_TLS_KEY]
+        #[arg(long)]
+        tls_key: Option<PathBuf>,
+        /// The PEM CA certificate other peers are verified against [env: SYNCLIP_TLS_CA]
+        #[arg(long)]
+        tls_ca: Option<PathBuf>,
+        /// Announce this peer on the LAN over mDNS [env: SYNCLIP_ADVERTISE]
+        #[arg(long)]
+        advertise: bool,
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+}
+
+/// Talk to the running daemon over its control socket.
+#[derive(Subcommand)]
+pub enum ControlCommand {
+    /// Show what the daemon is doing
+    Status,
+    /// Stop syncing until resumed, clips copied meanwhile are never sent
+    Pause {
+        /// Resume by itself after this long, like 90s, 5m or 1h
+        #[arg(long = "for", value_parser = parse_duration)]
+        duration: Option<Duration>,
+    },
+    /// Sync again after a pause
+    Resume,
+    /// Keep the next clip copied local, without pausing
+    Skip,
+    /// Shut the daemon down
+    Stop,
+    /// List the nodes the daemon is connected to
+    Peers,
+}
+
+#[derive(Subcommand)]
+pub enum HistoryCommand {
+    /// List the newest clips
+    List {
+        /// The number of clips to list, 0 for all of them
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
+    /// Print one clip
+    Show { id: u64 },
+    /// Make an older clip the current one on every peer
+    Apply { id: u64 },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Validate the effective configuration and print it with secrets hidden
+    Check,
+}
+
+/// Flags shared by the server and the client.
+#[derive(Args)]
+pub struct CommonArgs {
+    /// The shared token every peer must present [env: SYNCLIP_TOKEN]
+    #[arg(long)]
+    token: Option<String>,
+    /// A file holding the shared token [env: SYNCLIP_TOKEN_FILE]
+    #[arg(long, conflicts_with = "token")]
+    token_file: Option<PathBuf>,
+    /// The passphrase clips are encrypted end-to-end with, every peer needs the same one [env: SYNCLIP_PASSPHRASE]
+    #[arg(long)]
+    passphrase: Option<String>,
+    /// A file holding the end-to-end passphrase [env: SYNCLIP_PASSPHRASE_FILE]
+    #[arg(long, conflicts_with = "passphrase")]
+    passphrase_file: Option<PathBuf>,
+    /// Names the peers sharing the passphrase and salts its key, every peer needs the same one [env: SYNCLIP_ENCRYPTION_GROUP]
+    #[arg(long)]
+    encryption_group: Option<String>,
+    /// Milliseconds between two reads of the local clipboard, clipboards that report their changes are read on change [env: SYNCLIP_POLL_INTERVAL] [default: 500]
+    #[arg(long)]
+    poll_interval: Option<u64>,
+    /// The largest clip sent or accepted in bytes, larger ones stay where they were copied [env: SYNCLIP_MAX_CLIP_SIZE] [default: 33554432]
+    #[arg(long)]
+    max_clip_size: Option<u64>,
+    /// The codecs offered, comma separated, used only when both sides offer one, "none" turns compression off [env: SYNCLIP_COMPRESSION] [default: gzip]
+    #[arg(long)]
+    compression: Option<String>,
+    /// Clips smaller than this many bytes are sent uncompressed [env: SYNCLIP_COMPRESSION_THRESHOLD] [default: 1024]
+    #[arg(long)]
+    compression_threshold: Option<u64>,
+    /// Whether to send local clips, receive remote ones or both: both, send or receive [env: SYNCLIP_DIRECTION] [default: both]
+    #[arg(long)]
+    direction: Option<Direction>,
+}
+
+impl CommonArgs {
+    /// A flag replaces its counterpart, a file flag also drops the inline value.
+    fn apply(self, config: &mut Config) {
+        if let Some(token) = self.token {
+            config.auth.token = Some(token);
+            config.auth.token_file = None;
+        }
+        if let Some(token_file) = self.token_file {
+            config.auth.token_file = Some(token_file);
+            config.auth.token = None;
+        }
+        if let Some(passphrase) = self.passphrase {
+            config.encryption.passphrase = Some(passphrase);
+            config.encryption.passphrase_file = None;
+        }
+        if let Some(passphrase_file) = self.passphrase_file {
+            config.encryption.passphrase_file = Some(passphrase_file);
+            config.encryption.passphrase = None;
+        }
+        if let Some(group) = self.encryption_group {
+            config.encryption.group = Some(group);
+        }
+        if let Some(poll_interval) = self.poll_interval {
+            config.sync.poll_interval = poll_interval;
+        }
+        if let Some(max_clip_size) = self.max_clip_size {
+            config.sync.max_clip_size = max_clip_size;
+        }
+        if let Some(codecs) = self.compression {
+            config.sync.compression = parse_codecs(&codecs);
+        }
+        if let Some(threshold) = self.compression_threshold {
+            config.sync.compression_threshold = threshold;
+        }
+        if let Some(direction) = self.direction {
+            config.sync.direction = direction;
+        }
+    }
+}
+
+enum Mode {
+    Sync(Node),
+    Daemon {
+        node: Node,
+        foreground: bool,
+    },
+    Control(ControlCommand),
+    Discover(Duration),
+    Check,
+    History {
+        command: HistoryCommand,
+        daemon: bool,
     },
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+#[derive(Clone, Copy)]
+enum Node {
+    Server,
+    Client,
+    Peer,
+}
+
+impl Node {
+    fn name(self) -> &'static str {
+        match self {
+            Node::Server => "server",
+            Node::Client => "client",
+            Node::Peer => "peer",
+        }
+    }
+}
+
+/// The effective configuration, CLI flags applied last.
+fn load_config(
+    path: Option<&Path>,
+    socket: Option<PathBuf>,
+    command: Command,
+) -> Result<(Config, Mode)> {
+    let mut config = Config::load(path)?;
+    config.apply_env()?;
+    if socket.is_some() {
+        config.daemon.socket = socket;
+    }
+    let mode = match command {
+        Command::Node(command) => Mode::Sync(apply_node(command, &mut config)),
+        Command::Daemon {
+            foreground,
+            pid_file,
+            log_file,
+            node,
+        } => {
+            if pid_file.is_some() {
+                config.daemon.pid_file = pid_file;
+            }
+            if log_file.is_some() {
+                config.daemon.log_file = log_file;
+            }
+            Mode::Daemon {
+                node: apply_node(node, &mut config),
+                foreground,
+            }
+        }
+        Command::Control(command) => Mode::Control(command),
+        Command::Discover { timeout } => Mode::Discover(Duration::from_secs(timeout)),
+        Command::Config {
+            command: ConfigCommand::Check,
+        } => Mode::Check,
+        Command::History {
+            address,
+            daemon,
+            command,
+        } => {
+            if address.is_some() {
+                config.client.address = address;
+            }
+            Mode::History { command, daemon }
+        }
+    };
+    Ok((config, mode))
+}
+
+fn apply_node(command: NodeCommand, config: &mut Config) -> Node {
+    match command {
+        NodeCommand::Server {
+            port,
+            listen,
+            tls_cert,
+            tls_key,
+            tls_client_ca,
+            require_client_cert,
+            advertise,
+            common,
+        } => {
+            config.discovery.advertise |= advertise;
+            if let Some(port) = port {
+                config.server.listen = ([0, 0, 0, 0], port).into();
+            }
+            if let Some(listen) = listen {
+                config.server.listen = listen;
+            }
+            if tls_cert.is_some() {
+                config.tls.cert = tls_cert;
+            }
+            if tls_key.is_some() {
+                config.tls.key = tls_key;
+            }
+            if tls_client_ca.is_some() {
+                config.tls.client_ca = tls_client_ca;
+            }
+            config.tls.require_client_cert |= require_client_cert;
+            common.apply(config);
+            Node::Server
+        }
+        NodeCommand::Client {
+            address,
+            discover,
+            tls_ca,
+            tls_cert,
+            tls_key,
+            tls_domain,
+            common,
+        } => {
+            if address.is_some() {
+                config.client.address = address;
+                config.client.discover = false;
+            }
+            config.client.discover |= discover;
+            if tls_ca.is_some() {
+                config.tls.ca = tls_ca;
+            }
+            if tls_cert.is_some() {
+                config.tls.cert = tls_cert;
+            }
+            if tls_key.is_some() {
+                config.tls.key = tls_key;
+            }
+            if tls_domain.is_some() {
+                config.tls.domain = tls_domain;
+            }
+            common.apply(config);
+            Node::Client
+        }
+        NodeCommand::Peer {
+            listen,
+            peers,
+            tls_cert,
+            tls_key,
+            tls_ca,
+            advertise,
+            common,
+        } => {
+            config.discovery.advertise |= advertise;
+            if let Some(listen) = listen {
+                config.server.listen = listen;
+            }
+            if !peers.is_empty() {
+                config.peer.peers = peers;
+            }
+            if tls_cert.is_some() {
+                config.tls.cert = tls_cert;
+            }
+            if tls_key.is_some() {
+                config.tls.key = tls_key;
+            }
+            if tls_ca.is_some() {
+                config.tls.ca = tls_ca;
+            }
+            common.apply(config);
+            Node::Peer
+        }
+    }
+}
+
+fn main() -> Result<()> {
     color_eyre::install()?;
-    tracing_subscriber::fmt::init();
-    info!("pid: {}", std::process::id());
 
     let cli = Cli::parse();
+    let (config, mode) = load_config(cli.config.as_deref(), cli.socket, cli.command)?;
+    config.validate()?;
+    // A daemon forks before any thread is started, the runtime's included.
+    start_logging(&config, &mode)?;
+    tokio::runtime::Runtime::new()?.block_on(run(config, mode))
+}
+
+async fn run(config: Config, mode: Mode) -> Result<()> {
+    match mode {
+        Mode::Sync(node) => run_node(config, node, false).await,
+        Mode::Daemon { node, .. } => run_node(config, node, true).await,
+        Mode::Control(command) => run_control(config, command).await,
+        Mode::Discover(timeout) => {
+            let discovery = config.discovery.open()?;
+            let found = discovery.browse(timeout).await?;
+            if found.is_empty() {
+                eprintln!("No synclip node found");
+            }
+            for node in found {
+                let note = if node.is_compatible() {
+                    ""
+                } else {
+                    "\tincompatible"
+                };
+                println!(
+                    "{}\t{}\t{}{}",
+                    node.node,
+                    node.url().unwrap_or_default(),
+                    node.protocol,
+                    note
+                );
+            }
+            Ok(discovery.shutdown()?)
+        }
+        Mode::Check => {
+            print!("{}", config.redacted().to_toml()?);
+            Ok(())
+        }
+        Mode::History { command, daemon } => run_history(config, command, daemon).await,
+    }
+}
+
+async fn run_node(config: Config, node: Node, daemon: bool) -> Result<()> {
+    match node {
+        Node::Server => run_server(config, daemon).await,
+        Node::Client => run_client(config, daemon).await,
+        Node::Peer => run_peer(config, daemon).await,
+    }
+}
+
+async fn run_server(config: Config, daemon: bool) -> Result<()> {
+    info!("pid: {}", std::process::id());
+    let local_clipboard = LocalClipboard::new()?;
+    let initial = local_clipboard.initial().await?;
+    let cancel_token = CancellationToken::new();
+    let cipher = config.encryption.cipher()?;
+    let filter = config.filter.filter()?;
+    let initial = prepare_initial(initial, &filter, cipher.as_ref())?;
+
+    let server = server::SynclipServer::new(
+        config.server.listen,
+        initial,
+        config.tls.server_config()?,
+        config.auth.token()?,
+        config.history.open()?,
+        config.features(),
+        cancel_token.clone(),
+    )
+    .await?;
+    let discovery = advertise(&config);
+    let clipboard = Clipboard::new(
+        local_clipboard,
+        server,
+        config.sync.poll_interval,
+        cancel_token.clone(),
+    );
+    let clipboard = configure(clipboard, &config, cipher, filter);
+    sync(clipboard, &config, Node::Server, daemon, cancel_token).await?;
+    if let Some(discovery) = discovery {
+        discovery.shutdown()?;
+    }
+    Ok(())
+}
+
+async fn run_client(config: Config, daemon: bool) -> Result<()> {
+    info!("pid: {}", std::process::id());
+    let address = find_server(&config).await?;
+    let local_clipboard = LocalClipboard::new()?;
+    let initial = local_clipboard.initial().await?;
+    let cancel_token = CancellationToken::new();
+    let cipher = config.encryption.cipher()?;
+    let filter = config.filter.filter()?;
+    let initial = prepare_initial(initial, &filter, cipher.as_ref())?;
 
+    let tls = config.tls.client_config(&address)?;
+    let client = client::SynclipClient::new(
+        address,
+        initial,
+        tls,
+        config.auth.token()?,
+        config.history.open()?,
+        config.features(),
+        cancel_token.clone(),
+    )
+    .await?;
+    let clipboard = Clipboard::new(
+        local_clipboard,
+        client,
+        config.sync.poll_interval,
+        cancel_token.clone(),
+    );
+    let clipboard = configure(clipboard, &config, cipher, filter);
+    sync(clipboard, &config, Node::Client, daemon, cancel_token).await
+}
+
+async fn run_peer(config: Config, daemon: bool) -> Result<()> {
+    info!("pid: {}", std::process::id());
     let local_clipboard = LocalClipboard::new()?;
-    let initial = local_clipboard.get().await?;
+    let initial = local_clipboard.initial().await?;
     let cancel_token = CancellationToken::new();
+    let cipher = config.encryption.cipher()?;
+    let filter = config.filter.filter()?;
+    let initial = prepare_initial(initial, &filter, cipher.as_ref())?;
+
+    let peers = config
+        .peer
+        .peers
+        .iter()
+        .map(|address| {
+            Ok(PeerAddress::new(
+                address,
+                config.tls.client_config(address)?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let node = MeshNode::new(
+        config.server.listen,
+        config.tls.server_config()?,
+        peers,
+        initial,
+        config.auth.token()?,
+        config.history.open()?,
+        config.features(),
+        cancel_token.clone(),
+    )
+    .await?;
+    let discovery = advertise(&config);
+    let clipboard = Clipboard::new(
+        local_clipboard,
+        node,
+        config.sync.poll_interval,
+        cancel_token.clone(),
+    );
+    let clipboard = configure(clipboard, &config, cipher, filter);
+    sync(clipboard, &config, Node::Peer, daemon, cancel_token).await?;
+    if let Some(discovery) = discovery {
+        discovery.shutdown()?;
+    }
+    Ok(())
+}
+
+fn configure<T: VirtualClipboard + 'static>(
+    mut clipboard: Clipboard<T>,
+    config: &Config,
+    cipher: Option<Cipher>,
+    filter: Filter,
+) -> Clipboard<T> {
+    if let Some(cipher) = cipher {
+        clipboard = clipboard.with_cipher(cipher);
+    }
+    clipboard
+        .with_filter(filter)
+        .with_direction(config.sync.direction)
+}
+
+/// Sync until Ctrl-C, SIGTERM or, for a daemon, `synclip stop`.
+async fn sync<T: VirtualClipboard + 'static>(
+    mut clipboard: Clipboard<T>,
+    config: &Config,
+    node: Node,
+    daemon: bool,
+    cancel_token: CancellationToken,
+) -> Result<()> {
+    let daemon = match daemon {
+        true => Some(Daemon::start(config, &clipboard, node, &cancel_token).await?),
+        false => None,
+    };
+    let handle = clipboard.start();
+    if daemon.is_some() {
+        Daemon::ready();
+    }
+    tokio::select! {
+        _ = cancel_token.cancelled() => {}
+        _ = tokio::signal::ctrl_c() => {
+            cancel_token.cancel();
+        }
+        _ = terminated() => {
+            cancel_token.cancel();
+        }
+    }
+    if daemon.is_some() {
+        Daemon::stopping();
+    }
+    clipboard.shutdown().await?;
+    info!("wait for {} shutdown", node.name());
+    handle.await?;
+    if let Some(daemon) = daemon {
+        daemon.shutdown().await?;
+    }
+    Ok(())
+}
+
+/// What a daemon holds on to while it runs, its pid file goes with it.
+#[cfg(unix)]
+struct Daemon {
+    control: ControlServer,
+    _pid_file: PidFile,
+}
+
+#[cfg(unix)]
+impl Daemon {
+    async fn start<T: VirtualClipboard + 'static>(
+        config: &Config,
+        clipboard: &Clipboard<T>,
+        node: Node,
+        cancel_token: &CancellationToken,
+    ) -> Result<Self> {
+        let pid_file = PidFile::create(config.daemon.pid_file()?)?;
+        let control = ControlServer::new(
+            config.daemon.socket()?,
+            clipboard.clone(),
+            node.name(),
+            cancel_token.clone(),
+        )
+        .await?;
+        Ok(Self {
+            control,
+            _pid_file: pid_file,
+        })
+    }
+
+    fn ready() {
+        daemon::notify_ready();
+    }
+
+    fn stopping() {
+        daemon::notify_stopping();
+    }
+
+    async fn shutdown(self) -> Result<()> {
+        Ok(self.control.shutdown().await?)
+    }
+}
+
+#[cfg(not(unix))]
+struct Daemon;
+
+#[cfg(not(unix))]
+impl Daemon {
+    async fn start<T: VirtualClipboard + 'static>(
+        _: &Config,
+        _: &Clipboard<T>,
+        _: Node,
+        _: &CancellationToken,
+    ) -> Result<Self> {
+        Err(eyre!("synclip daemon needs Unix domain sockets"))
+    }
+
+    fn ready() {}
+
+    fn stopping() {}
+
+    async fn shutdown(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Resolves on SIGTERM, as sent by `kill` and systemd.
+#[cfg(unix)]
+async fn terminated() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            terminate.recv().await;
+        }
+        Err(e) => {
+            warn!("Listen for SIGTERM error: {:?}", e);
+            std::future::pending().await
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn terminated() {
+    std::future::pending().await
+}
 
-    match cli {
-        Cli::Server { port } => {
-            let server = server::SynclipServer::new(port, initial, cancel_token.clone()).await?;
-            let mut clipboard = Clipboard::new(local_clipboard, server, 500, cancel_token.clone());
-            let handle = clipboard.start();
-            tokio::select! {
-                _ = cancel_token.cancelled() => {}
-                _ = tokio::signal::ctrl_c() => {
-                    cancel_token.cancel();
-                }
-            }
-            clipboard.shutdown().await?;
-            info!("wait for server shutdown");
-            handle.join().unwrap();
-        }
-        Cli::Client { address } => {
-            let client = client::SynclipClient::new(address, initial, cancel_token.clone()).await?;
-            let mut clipboard = Clipboard::new(local_clipboard, client, 500, cancel_token.clone());
-            let handle = clipboard.start();
-            tokio::select! {
-                _ = cancel_token.cancelled() => {}
-                _ = tokio::signal::ctrl_c() => {
-                    cancel_token.cancel();
-                }
-            }
-            clipboard.shutdown().await?;
-            info!("wait for client shutdown");
-            handle.join().unwrap();
+/// Log to the terminal, or to the log file for a daemon that detaches from
+/// it. Under systemd the journal takes the terminal's place.
+fn start_logging(config: &Config, mode: &Mode) -> Result<()> {
+    #[cfg(unix)]
+    if let Mode::Daemon {
+        foreground: false, ..
+    } = mode
+    {
+        if !daemon::under_systemd() {
+            // Before forking, so the error reaches the terminal.
+            PidFile::check(&config.daemon.pid_file()?)?;
+            let log_file = config.daemon.log_file()?;
+            if let Some(dir) = log_file.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let file = std::fs::File::options()
+                .create(true)
+                .append(true)
+                .open(&log_file)
+                .with_context(|| format!("Open log file {:?}", log_file))?;
+            daemon::detach()?;
+            tracing_subscriber::fmt()
+                .with_ansi(false)
+                .with_writer(std::sync::Mutex::new(file))
+                .init();
+            return Ok(());
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (config, mode);
+    tracing_subscriber::fmt::init();
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn run_control(config: Config, command: ControlCommand) -> Result<()> {
+    let mut control = control::connect(config.daemon.socket()?).await?;
+    match command {
+        ControlCommand::Status => print_status(control.status(Empty {}).await?.into_inner()),
+        ControlCommand::Pause { duration } => {
+            let seconds = duration.map_or(0, |duration| duration.as_secs().max(1));
+            print_status(control.pause(PauseRequest { seconds }).await?.into_inner())
+        }
+        ControlCommand::Resume => print_status(control.resume(Empty {}).await?.into_inner()),
+        ControlCommand::Skip => print_status(control.skip_next(Empty {}).await?.into_inner()),
+        ControlCommand::Stop => {
+            let pid = control.status(Empty {}).await?.into_inner().pid;
+            control.stop(Empty {}).await?;
+            println!("synclip daemon {pid} stopping");
+        }
+        ControlCommand::Peers => {
+            let peers = control.list_peers(Empty {}).await?.into_inner().peers;
+            let now = now_millis();
+            for peer in peers {
+                let age = now.saturating_sub(peer.last_seen) / 1000;
+                let compression = match peer.compression.as_str() {
+                    "" => "uncompressed",
+                    codec => codec,
+                };
+                println!(
+                    "{}\t{}\t{}\t{}\t{}s ago",
+                    peer.id, peer.address, peer.direction, compression, age
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn run_control(_: Config, _: ControlCommand) -> Result<()> {
+    Err(eyre!("synclip daemon needs Unix domain sockets"))
+}
+
+#[cfg(unix)]
+fn print_status(status: NodeStatus) {
+    let now = now_millis();
+    let uptime = now.saturating_sub(status.started_at) / 1000;
+    let state = match (status.paused, status.paused_until) {
+        (false, _) => "syncing".to_owned(),
+        (true, 0) => "paused".to_owned(),
+        (true, until) => format!("paused for {}s more", until.saturating_sub(now) / 1000),
+    };
+    println!("node: {}", status.node_id);
+    println!("mode: {}", status.mode);
+    println!("pid: {}", status.pid);
+    println!("uptime: {uptime}s");
+    println!("state: {state}");
+    println!("direction: {}", status.direction);
+    println!("peers: {}", status.peers);
+    if status.skip_next {
+        println!("skip: next copy");
+    }
+    if status.transient_errors > 0 {
+        println!("retried reads: {}", status.transient_errors);
+    }
+}
+
+/// A duration in seconds, or with an s, m or h suffix.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => value.split_at(at),
+        None => (value, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("{value:?} is not a duration like 90s, 5m or 1h"))?;
+    let seconds = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        _ => return Err(format!("Unknown unit {unit:?}, use s, m or h")),
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Where `synclip history` reads from.
+enum HistorySource {
+    Server(client::GrpcClient),
+    #[cfg(unix)]
+    Daemon(control::ControlClient),
+}
+
+impl HistorySource {
+    async fn open(config: &Config, daemon: bool) -> Result<Self> {
+        if daemon {
+            #[cfg(unix)]
+            return Ok(Self::Daemon(
+                control::connect(config.daemon.socket()?).await?,
+            ));
+            #[cfg(not(unix))]
+            return Err(eyre!("synclip daemon needs Unix domain sockets"));
         }
+        let address = find_server(config).await?;
+        let tls = config.tls.client_config(&address)?;
+        let token = config.auth.token()?;
+        Ok(Self::Server(
+            client::connect(&address, tls, token.as_deref()).await?,
+        ))
+    }
+
+    async fn list(&mut self, limit: u32) -> Result<Vec<HistoryEntry>> {
+        let query = HistoryQuery { limit };
+        let entries = match self {
+            Self::Server(client) => client.list_history(query).await?,
+            #[cfg(unix)]
+            Self::Daemon(control) => control.list_history(query).await?,
+        };
+        Ok(entries.into_inner().entries)
+    }
+
+    async fn get(&mut self, id: u64) -> Result<HistoryEntry> {
+        let id = HistoryEntryId { id };
+        let entry = match self {
+            Self::Server(client) => client.get_history_entry(id).await?,
+            #[cfg(unix)]
+            Self::Daemon(control) => control.get_history_entry(id).await?,
+        };
+        Ok(entry.into_inner())
+    }
+
+    async fn apply(&mut self, id: u64) -> Result<bool> {
+        let id = HistoryEntryId { id };
+        let replaced = match self {
+            Self::Server(client) => client.apply_history_entry(id).await?,
+            #[cfg(unix)]
+            Self::Daemon(control) => control.apply_history_entry(id).await?,
+        };
+        Ok(replaced.into_inner().replaced)
     }
+}
 
+async fn run_history(config: Config, command: HistoryCommand, daemon: bool) -> Result<()> {
+    let mut history = HistorySource::open(&config, daemon).await?;
+    match command {
+        HistoryCommand::List { limit } => {
+            let entries = history.list(limit).await?;
+            // What an empty origin stands for, the node's own clipboard.
+            let own = if daemon { "local" } else { "server" };
+            let now = now_millis();
+            for entry in entries {
+                let origin = if entry.origin.is_empty() {
+                    own
+                } else {
+                    entry.origin.as_str()
+                };
+                let age = now.saturating_sub(entry.created_at) / 1000;
+                println!("{}\t{}s ago\t{}\t{}", entry.id, age, origin, entry.summary);
+            }
+        }
+        HistoryCommand::Show { id } => {
+            let entry = history.get(id).await?;
+            let content = entry.content.unwrap_or_default();
+            let content = match config.encryption.cipher()? {
+                Some(cipher) if content.is_sealed() => cipher.open(&content)?,
+                _ => content,
+            };
+            println!("{}", content.text);
+            for representation in &content.representations {
+                eprintln!(
+                    "+{} ({} bytes)",
+                    representation.mime_type,
+                    representation.data.len()
+                );
+            }
+        }
+        HistoryCommand::Apply { id } => {
+            let replaced = history.apply(id).await?;
+            info!("Applied history entry {id}: [{replaced}]");
+        }
+    }
     Ok(())
 }
+
+/// Announce the node if asked to, a LAN without multicast is no reason to stop.
+fn advertise(config: &Config) -> Option<Discovery> {
+    if !config.discovery.advertise {
+        return None;
+    }
+    let tls = config.tls.cert.is_some();
+    let result = config.discovery.open().and_then(|mut discovery| {
+        discovery.advertise(&config.discovery.name(), config.server.listen.port(), tls)?;
+        Ok(discovery)
+    });
+    match result {
+        Ok(discovery) => Some(discovery),
+        Err(e) => {
+            warn!("Advertise error: {:?}", e);
+            None
+        }
+    }
+}
+
+/// The server from the config, or the first one found on the LAN.
+async fn find_server(config: &Config) -> Result<String> {
+    if config.client.address.is_some() || !config.client.discover {
+        return server_address(config);
+    }
+    let discovery = config.discovery.open()?;
+    let found = discovery.find(DISCOVERY_TIMEOUT).await;
+    discovery.shutdown()?;
+    let found = found?;
+    info!("Discovered server: {found}");
+    found
+        .url()
+        .ok_or_else(|| eyre!("{} advertised no address", found.node))
+}
+
+fn server_address(config: &Config) -> Result<String> {
+    config
+        .client
+        .address
+        .clone()
+        .ok_or_else(|| eyre!("No server address, pass one, set client.address or use --discover"))
+}
@@ -0,0 +1,271 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tonic::transport::{ClientTlsConfig, ServerTlsConfig};
+use tracing::{debug, info, warn};
+
+use crate::client::SynclipClient;
+use crate::clipboard::remote_clipboard::RemoteClipboard;
+use crate::clipboard::VirtualClipboard;
+use crate::error::Result;
+use crate::handshake::Features;
+use crate::history::History;
+use crate::server::SynclipServer;
+use crate::stamp::Stamper;
+use crate::{Content, Peer};
+
+/// Another mesh node to connect to.
+#[derive(Clone, Debug)]
+pub struct PeerAddress {
+    /// Like http://[remote]:[port]
+    pub address: String,
+    pub tls: Option<ClientTlsConfig>,
+}
+
+impl PeerAddress {
+    pub fn new(address: impl Into<String>, tls: Option<ClientTlsConfig>) -> Self {
+        Self {
+            address: address.into(),
+            tls,
+        }
+    }
+}
+
+/// A node that serves like [`SynclipServer`] and connects to its peers like
+/// [`SynclipClient`], so clips spread as long as any path between two
+/// nodes is up.
+///
+/// Every clip is stamped with the node it was copied on, see [`Stamper`].
+/// A node only takes a clip whose stamp is newer than the one it holds and
+/// relays it to its other links, so a clip crosses every link at most once
+/// in each direction and concurrent copies settle on the same winner
+/// everywhere.
+#[derive(Clone)]
+pub struct MeshNode {
+    id: String,
+    remote: RemoteClipboard,
+    server: SynclipServer,
+    clients: Vec<SynclipClient>,
+    stamper: Stamper,
+    /// The clip this node holds, stamped once anything was copied.
+    current: Arc<Mutex<Content>>,
+    handles: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl MeshNode {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new(
+        listen: SocketAddr,
+        tls: Option<ServerTlsConfig>,
+        peers: Vec<PeerAddress>,
+        initial: Content,
+        token: Option<String>,
+        history: History,
+        features: Features,
+        cancel_token: CancellationToken,
+    ) -> Result<Self> {
+        let id = uuid::Uuid::new_v4().to_string();
+        info!("Mesh node id: {id}");
+        let server = SynclipServer::new(
+            listen,
+            initial.clone(),
+            tls,
+            token.clone(),
+            history.clone(),
+            features.clone(),
+            cancel_token.clone(),
+        )
+        .await?;
+        let mut clients = Vec::with_capacity(peers.len());
+        for peer in peers {
+            info!("Mesh peer: {}", peer.address);
+            let client = SynclipClient::new(
+                peer.address,
+                initial.clone(),
+                peer.tls,
+                token.clone(),
+                history.clone(),
+                features.clone(),
+                cancel_token.clone(),
+            )
+            .await?;
+            clients.push(client);
+        }
+
+        let (sender_1, receiver_1) = watch::channel(initial.clone());
+        let (sender_2, receiver_2) = watch::channel(initial.clone());
+        let sender_2 = Arc::new(sender_2);
+        let stamper = Stamper::new(id.as_str());
+        let current = Arc::new(Mutex::new(initial));
+        let links: Vec<RemoteClipboard> = std::iter::once(server.remote().clone())
+            .chain(clients.iter().map(|client| client.remote().clone()))
+            .collect();
+
+        let mut handles = Vec::with_capacity(links.len() + 1);
+        handles.push(tokio::spawn(Self::forward_local(
+            stamper.clone(),
+            receiver_1,
+            links.clone(),
+            current.clone(),
+        )));
+        for index in 0..links.len() {
+            handles.push(tokio::spawn(Self::relay(
+                index,
+                links.clone(),
+                sender_2.clone(),
+                stamper.clone(),
+                current.clone(),
+            )));
+        }
+
+        Ok(Self {
+            id,
+            remote: RemoteClipboard::new(sender_1, receiver_2),
+            server,
+            clients,
+            stamper,
+            current,
+            handles: Arc::new(Mutex::new(handles)),
+        })
+    }
+
+    /// The origin this node stamps its clips with.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The clip this node holds, unstamped before the first copy.
+    pub fn current(&self) -> Content {
+        self.current.lock().unwrap().clone()
+    }
+
+    pub fn server(&self) -> &SynclipServer {
+        &self.server
+    }
+
+    pub fn clients(&self) -> &[SynclipClient] {
+        &self.clients
+    }
+
+    /// Send clips copied on this node to every link, stamping them if the
+    /// clipboard engine did not.
+    async fn forward_local(
+        stamper: Stamper,
+        mut receiver: watch::Receiver<Content>,
+        links: Vec<RemoteClipboard>,
+        current: Arc<Mutex<Content>>,
+    ) {
+        while receiver.changed().await.is_ok() {
+            let content = receiver.borrow_and_update().clone();
+            let stamped = {
+                let mut current = current.lock().unwrap();
+                let content = match content.stamp {
+                    Some(_) => content,
+                    // A relayed clip applied locally comes back here.
+                    None if current.same_clip(&content) => continue,
+                    None => stamper.stamp(content),
+                };
+                if !content.supersedes(&current) {
+                    continue;
+                }
+                *current = content.clone();
+                content
+            };
+            for link in &links {
+                if let Err(e) = link.set(stamped.clone()).await {
+                    warn!("Set [Mesh] error: {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Take newer clips from one link, apply them locally and pass them on
+    /// to the other links.
+    async fn relay(
+        index: usize,
+        links: Vec<RemoteClipboard>,
+        local: Arc<watch::Sender<Content>>,
+        stamper: Stamper,
+        current: Arc<Mutex<Content>>,
+    ) {
+        loop {
+            let content = match links[index].get_new().await {
+                Ok(content) => content,
+                Err(e) => {
+                    debug!("End relay [Mesh] from link {index}: {:?}", e);
+                    break;
+                }
+            };
+            let Some(stamp) = &content.stamp else {
+                continue;
+            };
+            if stamp.is_too_far_ahead() {
+                warn!("Ignore [Mesh] {stamp}, too far ahead of the local clock");
+                continue;
+            }
+            {
+                let mut current = current.lock().unwrap();
+                if !stamp.is_newer(current.stamp.as_ref()) {
+                    continue;
+                }
+                stamper.observe(stamp);
+                *current = content.clone();
+            }
+            info!("Relay [Mesh] {stamp}: {content}");
+            local.send_replace(content.clone());
+            for (other, link) in links.iter().enumerate() {
+                if other == index {
+                    continue;
+                }
+                if let Err(e) = link.set(content.clone()).await {
+                    warn!("Set [Mesh] error: {:?}", e);
+                }
+            }
+        }
+    }
+
+    pub async fn shutdown(self) -> Result<()> {
+        info!("Shutdown [Mesh]");
+        for handle in self.handles.lock().unwrap().drain(..) {
+            handle.abort();
+        }
+        for client in self.clients {
+            client.shutdown().await?;
+        }
+        self.server.shutdown().await
+    }
+}
+
+impl VirtualClipboard for MeshNode {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn stamper(&self) -> Stamper {
+        self.stamper.clone()
+    }
+
+    fn remote(&self) -> &RemoteClipboard {
+        &self.remote
+    }
+
+    /// Both the peers connected here and the ones this node connected to.
+    fn peers(&self) -> Vec<Peer> {
+        let mut peers = VirtualClipboard::peers(&self.server);
+        for client in &self.clients {
+            peers.extend(client.peers());
+        }
+        peers
+    }
+
+    fn history(&self) -> Option<&History> {
+        Some(self.server.history())
+    }
+
+    async fn shutdown(self) -> Result<()> {
+        self.shutdown().await
+    }
+}
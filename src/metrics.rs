@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::clipboard::backend::Transient;
+
+/// Counters of a running node, shared by the clones of its clipboard.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    empty: AtomicU64,
+    unsupported_format: AtomicU64,
+    owner_busy: AtomicU64,
+    fatal: AtomicU64,
+}
+
+impl Metrics {
+    /// A read of the local clipboard that is retried.
+    pub fn record_transient(&self, transient: Transient) {
+        self.counter(transient).fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A read of the local clipboard that stopped the node.
+    pub fn record_fatal(&self) {
+        self.fatal.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// How many reads failed for `transient`.
+    pub fn transient(&self, transient: Transient) -> u64 {
+        self.counter(transient).load(Ordering::Relaxed)
+    }
+
+    /// How many reads failed and were retried, whatever the reason.
+    pub fn transient_errors(&self) -> u64 {
+        [
+            Transient::Empty,
+            Transient::UnsupportedFormat,
+            Transient::OwnerBusy,
+        ]
+        .into_iter()
+        .map(|transient| self.transient(transient))
+        .sum()
+    }
+
+    pub fn fatal_errors(&self) -> u64 {
+        self.fatal.load(Ordering::Relaxed)
+    }
+
+    fn counter(&self, transient: Transient) -> &AtomicU64 {
+        match transient {
+            Transient::Empty => &self.empty,
+            Transient::UnsupportedFormat => &self.unsupported_format,
+            Transient::OwnerBusy => &self.owner_busy,
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tonic::transport::{ClientTlsConfig, ServerTlsConfig};
+use tracing::info;
+
+use crate::client::SynclipClient;
+use crate::clipboard::backend::{ClipboardBackend, SystemBackend};
+use crate::clipboard::local_clipboard::LocalClipboard;
+use crate::clipboard::{Clipboard, VirtualClipboard, DEFAULT_POLL_INTERVAL};
+use crate::crypto::Cipher;
+use crate::direction::Direction;
+use crate::error::{Error, Result};
+use crate::filter::Filter;
+use crate::handshake::Features;
+use crate::history::History;
+use crate::mesh::{MeshNode, PeerAddress};
+use crate::metrics::Metrics;
+use crate::server::SynclipServer;
+use crate::{Content, Peer};
+
+/// What a node runs as.
+#[derive(Clone, Debug)]
+pub enum Role {
+    /// Serve clients on `listen`.
+    Server {
+        listen: SocketAddr,
+        tls: Option<ServerTlsConfig>,
+    },
+    /// Sync through the server at `address`, like http://[server]:[port].
+    Client {
+        address: String,
+        tls: Option<ClientTlsConfig>,
+    },
+    /// Serve on `listen` and connect to `peers`, see [`MeshNode`].
+    Peer {
+        listen: SocketAddr,
+        tls: Option<ServerTlsConfig>,
+        peers: Vec<PeerAddress>,
+    },
+}
+
+impl Role {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Server { .. } => "server",
+            Role::Client { .. } => "client",
+            Role::Peer { .. } => "peer",
+        }
+    }
+}
+
+/// The clipboard engine of a node, whatever it syncs through.
+enum Engine<B: ClipboardBackend> {
+    Server(Clipboard<SynclipServer, B>),
+    Client(Clipboard<SynclipClient, B>),
+    Peer(Clipboard<MeshNode, B>),
+}
+
+/// Calls `$body` with the engine's clipboard bound to `$clipboard`.
+macro_rules! with_clipboard {
+    ($engine:expr, $clipboard:ident => $body:expr) => {
+        match $engine {
+            Engine::Server($clipboard) => $body,
+            Engine::Client($clipboard) => $body,
+            Engine::Peer($clipboard) => $body,
+        }
+    };
+}
+
+type BackendFactory<B> = Box<dyn FnOnce() -> color_eyre::Result<B> + Send>;
+
+/// Sets up a [`SynclipNode`], see [`SynclipNode::builder`].
+pub struct SynclipNodeBuilder<B: ClipboardBackend = SystemBackend> {
+    role: Option<Role>,
+    backend: BackendFactory<B>,
+    poll_interval: u64,
+    token: Option<String>,
+    history: History,
+    features: Features,
+    filter: Filter,
+    cipher: Option<Cipher>,
+    cancel_token: CancellationToken,
+}
+
+impl SynclipNodeBuilder<SystemBackend> {
+    fn new() -> Self {
+        Self {
+            role: None,
+            backend: Box::new(SystemBackend::new),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            token: None,
+            history: History::default(),
+            features: Features::default(),
+            filter: Filter::default(),
+            cipher: None,
+            cancel_token: CancellationToken::new(),
+        }
+    }
+}
+
+impl<B: ClipboardBackend> SynclipNodeBuilder<B> {
+    pub fn role(mut self, role: Role) -> Self {
+        self.role = Some(role);
+        self
+    }
+
+    /// Serve clients on `listen`, without TLS.
+    pub fn server(self, listen: SocketAddr) -> Self {
+        self.role(Role::Server { listen, tls: None })
+    }
+
+    /// Sync through the server at `address`, without TLS.
+    pub fn client(self, address: impl Into<String>) -> Self {
+        self.role(Role::Client {
+            address: address.into(),
+            tls: None,
+        })
+    }
+
+    /// Serve on `listen` and connect to `peers`, without TLS.
+    pub fn peer(self, listen: SocketAddr, peers: Vec<PeerAddress>) -> Self {
+        self.role(Role::Peer {
+            listen,
+            tls: None,
+            peers,
+        })
+    }
+
+    /// Sync this clipboard instead of the system one.
+    pub fn backend<C: ClipboardBackend>(self, backend: C) -> SynclipNodeBuilder<C> {
+        SynclipNodeBuilder {
+            role: self.role,
+            backend: Box::new(move || Ok(backend)),
+            poll_interval: self.poll_interval,
+            token: self.token,
+            history: self.history,
+            features: self.features,
+            filter: self.filter,
+            cipher: self.cipher,
+            cancel_token: self.cancel_token,
+        }
+    }
+
+    /// The milliseconds between two reads of the local clipboard.
+    pub fn poll_interval(mut self, poll_interval: u64) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// The bearer token every node of the deployment shares.
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn history(mut self, history: History) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// What this node offers in its handshake, replacing the direction set
+    /// before.
+    pub fn features(mut self, features: Features) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Only send local clips or only apply remote ones.
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.features.direction = direction;
+        self
+    }
+
+    /// Check every local clip before it leaves the machine, instead of
+    /// blocking what the built-in rules match.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Encrypt clips end-to-end.
+    pub fn cipher(mut self, cipher: Cipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Stop the node when `cancel_token` fires, as well as on shutdown.
+    pub fn cancel_token(mut self, cancel_token: CancellationToken) -> Self {
+        self.cancel_token = cancel_token;
+        self
+    }
+
+    /// Start syncing as tasks on the current tokio runtime.
+    pub async fn start(self) -> Result<SynclipNode<B>> {
+        let role = self.role.ok_or_else(|| {
+            Error::Config("No role, call server, client or peer on the builder".into())
+        })?;
+        if self.poll_interval == 0 {
+            return Err(Error::Config("The poll interval must be positive".into()));
+        }
+        let name = role.name();
+        let backend = (self.backend)()
+            .map_err(|e| Error::backend(e).context("Open the clipboard backend"))?;
+        let local = LocalClipboard::with_backend(backend);
+        let initial = local.initial().await?;
+        let initial = prepare_initial(initial, &self.filter, self.cipher.as_ref())?;
+        let cancel_token = self.cancel_token;
+        let setup = Setup {
+            cipher: self.cipher,
+            filter: self.filter,
+            direction: self.features.direction,
+        };
+
+        let engine = match role {
+            Role::Server { listen, tls } => {
+                let server = SynclipServer::new(
+                    listen,
+                    initial,
+                    tls,
+                    self.token,
+                    self.history,
+                    self.features,
+                    cancel_token.clone(),
+                )
+                .await?;
+                Engine::Server(setup.apply(Clipboard::new(
+                    local,
+                    server,
+                    self.poll_interval,
+                    cancel_token.clone(),
+                )))
+            }
+            Role::Client { address, tls } => {
+                let client = SynclipClient::new(
+                    address,
+                    initial,
+                    tls,
+                    self.token,
+                    self.history,
+                    self.features,
+                    cancel_token.clone(),
+                )
+                .await?;
+                Engine::Client(setup.apply(Clipboard::new(
+                    local,
+                    client,
+                    self.poll_interval,
+                    cancel_token.clone(),
+                )))
+            }
+            Role::Peer { listen, tls, peers } => {
+                let node = MeshNode::new(
+                    listen,
+                    tls,
+                    peers,
+                    initial,
+                    self.token,
+                    self.history,
+                    self.features,
+                    cancel_token.clone(),
+                )
+                .await?;
+                Engine::Peer(setup.apply(Clipboard::new(
+                    local,
+                    node,
+                    self.poll_interval,
+                    cancel_token.clone(),
+                )))
+            }
+        };
+        let handle = with_clipboard!(&engine, clipboard => clipboard.clone().start());
+        info!("Node started as {name}");
+        Ok(SynclipNode {
+            name,
+            engine,
+            handle,
+            cancel_token,
+        })
+    }
+}
+
+/// A running node, embedded in the caller's tokio runtime.
+///
+/// ```no_run
+/// # async fn run() -> synclip::Result<()> {
+/// let node = synclip::node::SynclipNode::builder()
+///     .client("http://clip.example:5505")
+///     .start()
+///     .await?;
+/// // ...
+/// node.shutdown().await
+/// # }
+/// ```
+pub struct SynclipNode<B: ClipboardBackend = SystemBackend> {
+    name: &'static str,
+    engine: Engine<B>,
+    handle: JoinHandle<()>,
+    cancel_token: CancellationToken,
+}
+
+impl SynclipNode {
+    pub fn builder() -> SynclipNodeBuilder {
+        SynclipNodeBuilder::new()
+    }
+}
+
+impl<B: ClipboardBackend> SynclipNode<B> {
+    /// server, client or peer.
+    pub fn role(&self) -> &'static str {
+        self.name
+    }
+
+    /// The node id clips copied here are stamped with.
+    pub fn id(&self) -> String {
+        with_clipboard!(&self.engine, clipboard => clipboard.remote().id().to_owned())
+    }
+
+    /// The nodes this one is connected to.
+    pub fn peers(&self) -> Vec<Peer> {
+        with_clipboard!(&self.engine, clipboard => VirtualClipboard::peers(clipboard.remote()))
+    }
+
+    /// Stop syncing both ways until resumed.
+    pub fn pause(&self) {
+        with_clipboard!(&self.engine, clipboard => clipboard.pause())
+    }
+
+    /// Pause for `duration` only.
+    pub fn pause_for(&self, duration: Duration) {
+        with_clipboard!(&self.engine, clipboard => clipboard.pause_for(duration))
+    }
+
+    pub fn resume(&self) {
+        with_clipboard!(&self.engine, clipboard => clipboard.resume())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        with_clipboard!(&self.engine, clipboard => clipboard.is_paused())
+    }
+
+    /// How syncing went so far.
+    pub fn metrics(&self) -> &Metrics {
+        with_clipboard!(&self.engine, clipboard => clipboard.metrics())
+    }
+
+    /// Keep the next clip copied here local.
+    pub fn skip_next(&self) {
+        with_clipboard!(&self.engine, clipboard => clipboard.skip_next())
+    }
+
+    /// Resolves once the node stopped, shut down or with the error its
+    /// clipboard failed on.
+    pub async fn stopped(&self) -> std::result::Result<(), &Error> {
+        self.cancel_token.cancelled().await;
+        match with_clipboard!(&self.engine, clipboard => clipboard.failure()) {
+            Some(failure) => Err(failure),
+            None => Ok(()),
+        }
+    }
+
+    /// Stop syncing and wait for every task of the node to end, the error
+    /// the node failed on first.
+    pub async fn shutdown(self) -> Result<()> {
+        info!("Shutdown [Node]");
+        self.cancel_token.cancel();
+        if let Err(e) = self.handle.await {
+            if e.is_panic() {
+                std::panic::resume_unwind(e.into_panic());
+            }
+        }
+        with_clipboard!(self.engine, clipboard => clipboard.shutdown().await)
+    }
+}
+
+/// What the builder was given besides the remote.
+struct Setup {
+    cipher: Option<Cipher>,
+    filter: Filter,
+    direction: Direction,
+}
+
+impl Setup {
+    fn apply<T: VirtualClipboard + 'static, B: ClipboardBackend>(
+        self,
+        clipboard: Clipboard<T, B>,
+    ) -> Clipboard<T, B> {
+        let clipboard = match self.cipher {
+            Some(cipher) => clipboard.with_cipher(cipher),
+            None => clipboard,
+        };
+        clipboard
+            .with_filter(self.filter)
+            .with_direction(self.direction)
+    }
+}
+
+/// The first clip offered to the remote must not leak sensitive text or the
+/// plaintext either, it is filtered and sealed like any other.
+pub fn prepare_initial(
+    initial: Content,
+    filter: &Filter,
+    cipher: Option<&Cipher>,
+) -> Result<Content> {
+    let initial = filter.apply(initial).unwrap_or_default();
+    match cipher {
+        Some(cipher) => cipher.seal(&initial),
+        None => Ok(initial),
+    }
+}
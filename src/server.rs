@@ -1,36 +1,84 @@
+pub mod peer;
 mod synclip_rpc;
 
+use crate::auth::AuthInterceptor;
 use crate::clipboard::remote_clipboard::RemoteClipboard;
 use crate::clipboard::VirtualClipboard;
+use crate::compression;
+use crate::error::{Error, Result};
+use crate::handshake::{Features, Negotiated};
+use crate::history::History;
+use crate::server::peer::{describe, PeerRegistry, PeerState};
 use crate::server::synclip_rpc::SynclipRpc;
-use crate::synclip_server;
-use color_eyre::Result;
+use crate::stamp::Stamper;
+use crate::{synclip_server, Content, Peer};
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use tokio::sync::watch;
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
+use tonic::codegen::InterceptedService;
+use tonic::transport::ServerTlsConfig;
 use tracing::info;
 
 #[derive(Clone)]
 pub struct SynclipServer {
+    id: String,
+    stamper: Stamper,
     remote: RemoteClipboard,
+    peers: PeerRegistry,
+    negotiated: Negotiated,
+    features: Features,
+    history: History,
     handle: Arc<Mutex<Option<JoinHandle<Result<()>>>>>,
 }
 
 impl SynclipServer {
-    pub async fn new(port: u16, initial: String, cancel_token: CancellationToken) -> Result<Self> {
+    pub async fn new(
+        listen: SocketAddr,
+        initial: Content,
+        tls: Option<ServerTlsConfig>,
+        token: Option<String>,
+        history: History,
+        features: Features,
+        cancel_token: CancellationToken,
+    ) -> Result<Self> {
+        let id = uuid::Uuid::new_v4().to_string();
+        info!("Server id: {id}");
+        let stamper = Stamper::new(id.as_str());
         let (sender_1, receiver_1) = watch::channel(initial.clone());
         let (sender_2, receiver_2) = watch::channel(initial);
-        let rpc = SynclipRpc::new(sender_2, receiver_1);
+        let rpc = SynclipRpc::new(
+            sender_2,
+            receiver_1,
+            history.clone(),
+            stamper.clone(),
+            features.clone(),
+            cancel_token.clone(),
+        );
+        let peers = rpc.peers();
+        let negotiated = rpc.negotiated();
 
-        let addr = format!("0.0.0.0:{}", port).parse()?;
         let mut server = tonic::transport::Server::default();
-        let router = server.add_service(synclip_server::SynclipServer::new(rpc));
+        if let Some(tls) = tls {
+            server = server.tls_config(tls)?;
+        }
+        let interceptor =
+            AuthInterceptor::new(token.as_deref()).map_err(|e| Error::Auth(format!("{e:#}")))?;
+        let mut service = synclip_server::SynclipServer::new(rpc);
+        // Only what a peer asks for gets compressed, see `Features::compress`.
+        if let Some(encoding) = features.codec().and_then(compression::encoding) {
+            info!("Compression: {}", features.codec().unwrap_or_default());
+            service = service
+                .accept_compressed(encoding)
+                .send_compressed(encoding);
+        }
+        let router = server.add_service(InterceptedService::new(service, interceptor));
 
         let handle = tokio::spawn(async move {
             router
-                .serve_with_shutdown(addr, async move {
-                    info!("Listening on: {}", addr);
+                .serve_with_shutdown(listen, async move {
+                    info!("Listening on: {}", listen);
                     cancel_token.cancelled().await;
                     info!("Received shutdown signal");
                 })
@@ -40,26 +88,68 @@ impl SynclipServer {
         });
 
         let server = Self {
+            id,
+            stamper,
             remote: RemoteClipboard::new(sender_1, receiver_2),
+            peers,
+            negotiated,
+            features,
+            history,
             handle: Arc::new(Mutex::new(Some(handle))),
         };
 
         Ok(server)
     }
 
-    pub fn shutdown(self) -> Result<()> {
+    /// The clients currently subscribed to this server.
+    pub fn peers(&self) -> Vec<PeerState> {
+        self.peers.list()
+    }
+
+    /// What this server and a peer agreed on, if the peer said hello from
+    /// `address`.
+    pub fn negotiated(&self, peer_id: &str, address: Option<SocketAddr>) -> Option<Features> {
+        self.negotiated.get(peer_id, address)
+    }
+
+    /// Every clip that went through this server.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub async fn shutdown(self) -> Result<()> {
         info!("Shutdown [Server]");
-        self.handle.lock().unwrap().take().unwrap().abort();
+        // Taken by the first of the clones to shut down.
+        let handle = self.handle.lock().unwrap().take();
+        if let Some(handle) = handle {
+            handle.abort();
+        }
         Ok(())
     }
 }
 
 impl VirtualClipboard for SynclipServer {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn stamper(&self) -> Stamper {
+        self.stamper.clone()
+    }
+
     fn remote(&self) -> &RemoteClipboard {
         &self.remote
     }
 
-    fn shutdown(self) -> Result<()> {
-        self.shutdown()
+    fn peers(&self) -> Vec<Peer> {
+        describe(&self.peers, &self.negotiated, &self.features)
+    }
+
+    fn history(&self) -> Option<&History> {
+        Some(&self.history)
+    }
+
+    async fn shutdown(self) -> Result<()> {
+        self.shutdown().await
     }
 }
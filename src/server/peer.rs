@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use tonic::Request;
+use tracing::info;
+
+use crate::handshake::{Features, Negotiated};
+use crate::util::{content_hash, now_millis, PEER_ID_HEADER};
+use crate::{Content, Peer};
+
+/// What the server knows about one connected client.
+#[derive(Clone, Debug)]
+pub struct PeerState {
+    pub id: String,
+    pub address: Option<SocketAddr>,
+    pub connected_at: u64,
+    pub last_seen: u64,
+    pub last_content_hash: u64,
+    connection: u64,
+}
+
+impl From<&PeerState> for Peer {
+    fn from(state: &PeerState) -> Self {
+        Peer {
+            id: state.id.clone(),
+            address: state
+                .address
+                .map(|address| address.to_string())
+                .unwrap_or_default(),
+            connected_at: state.connected_at,
+            last_seen: state.last_seen,
+            last_content_hash: state.last_content_hash,
+            compression: String::new(),
+            direction: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct PeerRegistry {
+    peers: Arc<Mutex<HashMap<String, PeerState>>>,
+    connections: Arc<Mutex<u64>>,
+}
+
+impl PeerRegistry {
+    /// Register a subscribed peer, it stays listed until the guard is dropped.
+    pub fn connect(&self, id: String, address: Option<SocketAddr>) -> PeerGuard {
+        let connection = {
+            let mut connections = self.connections.lock().unwrap();
+            *connections += 1;
+            *connections
+        };
+        let now = now_millis();
+        info!("Peer [{id}] connected from {:?}", address);
+        self.peers.lock().unwrap().insert(
+            id.clone(),
+            PeerState {
+                id: id.clone(),
+                address,
+                connected_at: now,
+                last_seen: now,
+                last_content_hash: 0,
+                connection,
+            },
+        );
+        PeerGuard {
+            registry: self.clone(),
+            id,
+            connection,
+        }
+    }
+
+    /// Record activity from a peer, optionally with the content it now holds.
+    pub fn touch(&self, id: &str, content: Option<&Content>) {
+        if let Some(peer) = self.peers.lock().unwrap().get_mut(id) {
+            peer.last_seen = now_millis();
+            if let Some(content) = content {
+                peer.last_content_hash = content_hash(content);
+            }
+        }
+    }
+
+    pub fn list(&self) -> Vec<PeerState> {
+        let mut peers = self
+            .peers
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        peers.sort_by_key(|peer| peer.connected_at);
+        peers
+    }
+
+    fn disconnect(&self, id: &str, connection: u64) {
+        let mut peers = self.peers.lock().unwrap();
+        if peers
+            .get(id)
+            .is_some_and(|peer| peer.connection == connection)
+        {
+            peers.remove(id);
+            info!("Peer [{id}] disconnected");
+        }
+    }
+}
+
+pub struct PeerGuard {
+    registry: PeerRegistry,
+    id: String,
+    connection: u64,
+}
+
+impl Drop for PeerGuard {
+    fn drop(&mut self) {
+        self.registry.disconnect(&self.id, self.connection);
+    }
+}
+
+/// The connected peers as listed, with what was agreed with each in the
+/// handshake, `features` being the server's own.
+pub fn describe(peers: &PeerRegistry, negotiated: &Negotiated, features: &Features) -> Vec<Peer> {
+    peers
+        .list()
+        .iter()
+        .map(|state| {
+            let agreed = negotiated.get(&state.id, state.address);
+            Peer {
+                compression: agreed
+                    .as_ref()
+                    .and_then(|features| features.codec().map(str::to_owned))
+                    .unwrap_or_default(),
+                direction: agreed
+                    .map(|features| features.direction)
+                    .unwrap_or_else(|| features.direction_for(state.address))
+                    .to_string(),
+                ..state.into()
+            }
+        })
+        .collect()
+}
+
+/// The id a request was sent by, falling back to its address for clients
+/// that do not send one.
+pub fn peer_id<T>(request: &Request<T>) -> String {
+    request
+        .metadata()
+        .get(PEER_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .map(str::to_owned)
+        .or_else(|| request.remote_addr().map(|address| address.to_string()))
+        .unwrap_or_default()
+}
@@ -1,53 +1,411 @@
+use std::future::Future;
+use std::net::SocketAddr;
 use std::pin::Pin;
+use std::task::{Context, Poll};
 use tokio::sync::watch;
 use tokio_stream::wrappers::WatchStream;
 use tokio_stream::StreamExt;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
 
+use prost::Message;
 use tonic::codegen::tokio_stream::Stream;
-use tonic::{Request, Response, Status};
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{info, warn};
 
+use crate::chunk::{self, Assembler, Progress};
+use crate::compression;
+use crate::direction::Direction;
+use crate::error::Error;
+use crate::handshake::{check_version, Features, Negotiated, NegotiatedGuard};
+use crate::history::History;
 use crate::proto::synclip_server::Synclip;
-use crate::proto::{Content, Empty};
-use crate::Replaced;
+use crate::proto::{Content, Download, Empty};
+use crate::server::peer::{describe, peer_id, PeerGuard, PeerRegistry};
+use crate::stamp::Stamper;
+use crate::util::content_hash;
+use crate::{
+    Chunk, Greeting, HistoryEntries, HistoryEntry, HistoryEntryId, HistoryQuery, Peers, Replaced,
+};
 
 pub type ContentResult = Result<Content, Status>;
 type ContentStream = Pin<Box<dyn Stream<Item = ContentResult> + Send>>;
+type ChunkStream = Pin<Box<dyn Stream<Item = Result<Chunk, Status>> + Send>>;
+
+/// The latest clip together with the peer it came from, `None` being the
+/// server's own clipboard.
+#[derive(Clone)]
+struct Clip {
+    origin: Option<String>,
+    content: Content,
+}
+
+/// What of `clip` goes to peer `id`, filtered for it. Neither what the peer
+/// set nor what was copied on it goes back.
+fn outgoing(clip: Clip, id: &str, features: &Features) -> Option<Content> {
+    let copied_there = clip
+        .content
+        .stamp
+        .as_ref()
+        .is_some_and(|stamp| stamp.origin == id);
+    if clip.origin.as_deref() == Some(id) || copied_there {
+        return None;
+    }
+    Some(features.filter(clip.content))
+}
 
 pub struct SynclipRpc {
-    sender: watch::Sender<String>,
-    receiver: watch::Receiver<String>,
+    sender: watch::Sender<Content>,
+    clips: watch::Sender<Clip>,
+    peers: PeerRegistry,
+    history: History,
+    stamper: Stamper,
+    features: Features,
+    negotiated: Negotiated,
+    cancel_token: CancellationToken,
+}
+
+/// A peer's clip stream, it keeps the peer listed and what it negotiated
+/// while it lives and ends when the server shuts down so the peer notices.
+struct PeerStream {
+    clips: ContentStream,
+    cancelled: Pin<Box<WaitForCancellationFutureOwned>>,
+    _guard: PeerGuard,
+    _negotiated: NegotiatedGuard,
+}
+
+impl Stream for PeerStream {
+    type Item = ContentResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.cancelled.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(None);
+        }
+        self.clips.as_mut().poll_next(cx)
+    }
 }
 
 impl SynclipRpc {
-    pub fn new(sender: watch::Sender<String>, receiver: watch::Receiver<String>) -> Self {
-        Self { sender, receiver }
+    /// `sender` receives every clip set by a peer, `receiver` carries the
+    /// server's own clipboard which is fanned out to every peer. Every clip
+    /// goes to `history`.
+    pub fn new(
+        sender: watch::Sender<Content>,
+        receiver: watch::Receiver<Content>,
+        history: History,
+        stamper: Stamper,
+        features: Features,
+        cancel_token: CancellationToken,
+    ) -> Self {
+        let initial = Clip {
+            origin: None,
+            content: receiver.borrow().clone(),
+        };
+        history.record(None, &initial.content);
+        let (clips, _) = watch::channel(initial);
+        tokio::spawn(Self::forward_local(
+            receiver,
+            clips.clone(),
+            history.clone(),
+        ));
+        Self {
+            sender,
+            clips,
+            peers: PeerRegistry::default(),
+            history,
+            stamper,
+            features,
+            negotiated: Negotiated::default(),
+            cancel_token,
+        }
+    }
+
+    pub fn peers(&self) -> PeerRegistry {
+        self.peers.clone()
+    }
+
+    pub fn negotiated(&self) -> Negotiated {
+        self.negotiated.clone()
+    }
+
+    /// What was agreed with a peer at `address`, this server's own features
+    /// if it skipped the handshake.
+    fn features_of(&self, id: &str, address: Option<SocketAddr>) -> Features {
+        self.negotiated
+            .get(id, address)
+            .unwrap_or_else(|| self.features.clone())
+    }
+
+    /// Which way clips may flow with a peer, what it said hello with but
+    /// never more than `peer_directions` allow its address: another peer's
+    /// hello is no way around them. `None` if nothing is left.
+    fn direction_of(&self, id: &str, address: Option<SocketAddr>) -> Option<Direction> {
+        let allowed = self.features.direction_for(address);
+        match self.negotiated.get(id, address) {
+            Some(features) => features.direction.narrow(allowed),
+            None => Some(allowed),
+        }
+    }
+
+    /// Refuse clips from a peer that may only receive.
+    fn check_sends(&self, id: &str, address: Option<SocketAddr>) -> Result<(), Error> {
+        if self.direction_of(id, address).is_some_and(Direction::sends) {
+            return Ok(());
+        }
+        warn!("Refused clip from receive-only [{id}]");
+        Err(Error::Forbidden(format!("Peer {id} is receive-only")))
+    }
+
+    /// Take a peer's clip if it is the newest, last writer wins.
+    fn replace(&self, id: &str, content: Content) -> bool {
+        if let Some(stamp) = content
+            .stamp
+            .as_ref()
+            .filter(|stamp| stamp.is_too_far_ahead())
+        {
+            warn!("Ignore [{id}] {stamp}, too far ahead of the local clock");
+            return false;
+        }
+        let replaced = self.clips.send_if_modified(|clip| {
+            if content.supersedes(&clip.content) {
+                *clip = Clip {
+                    origin: Some(id.to_owned()),
+                    content: content.clone(),
+                };
+                true
+            } else {
+                false
+            }
+        });
+        if replaced {
+            self.history.record(Some(id), &content);
+            self.sender.send_replace(content);
+        }
+        replaced
+    }
+
+    async fn forward_local(
+        mut receiver: watch::Receiver<Content>,
+        clips: watch::Sender<Clip>,
+        history: History,
+    ) {
+        while receiver.changed().await.is_ok() {
+            let content = receiver.borrow_and_update().clone();
+            // A peer's clip applied to the server clipboard comes back here,
+            // it must keep the peer as origin so it is not echoed.
+            let replaced = clips.send_if_modified(|clip| {
+                if content.supersedes(&clip.content) {
+                    *clip = Clip {
+                        origin: None,
+                        content: content.clone(),
+                    };
+                    true
+                } else {
+                    false
+                }
+            });
+            if replaced {
+                history.record(None, &content);
+            }
+        }
     }
 }
 
 #[tonic::async_trait]
 impl Synclip for SynclipRpc {
     type PollingClipboardStream = ContentStream;
+    type DownloadClipboardStream = ChunkStream;
+
+    async fn hello(&self, request: Request<Greeting>) -> Result<Response<Greeting>, Status> {
+        let id = peer_id(&request);
+        let address = request.remote_addr();
+        let allowed = self.features.direction_for(address);
+        let hello = request.into_inner();
+        check_version(&hello)?;
+        let features = Features {
+            direction: Direction::from_greeting(&hello.direction)?,
+            ..self.features.narrow(&hello)
+        }
+        .restrict(allowed)?;
+        info!(
+            "Hello from [{id}] as {}: {:?}, direction: {}",
+            hello.node_id, features.content_types, features.direction
+        );
+        let reply = Greeting {
+            direction: features.direction.to_string(),
+            ..self.features.hello(self.stamper.origin())
+        };
+        self.negotiated.insert(id, address, features);
+        Ok(Response::new(reply))
+    }
 
     async fn polling_clipboard(
         &self,
-        _request: Request<Empty>,
+        request: Request<Empty>,
     ) -> Result<Response<Self::PollingClipboardStream>, Status> {
-        let stream = Box::pin(
-            WatchStream::new(self.receiver.clone()).map(|content| Ok(Content { text: content })),
-        );
-        Ok(Response::new(stream))
+        let id = peer_id(&request);
+        let address = request.remote_addr();
+        let guard = self.peers.connect(id.clone(), address);
+        let negotiated = self.negotiated.hold(&id, address);
+        let peers = self.peers.clone();
+        let features = self.features_of(&id, address);
+        // Only a peer that said hello knows to unpack a gzipped clip.
+        let gzips = self.negotiated.get(&id, address).is_some();
+        // A send-only peer stays connected and listed, it just gets no clips.
+        let receives = self
+            .direction_of(&id, address)
+            .is_some_and(Direction::receives);
+        let clips = WatchStream::new(self.clips.subscribe()).filter_map(move |clip| {
+            if !receives {
+                return None;
+            }
+            let content = outgoing(clip, &id, &features)?;
+            peers.touch(&id, Some(&content));
+            let size = content.encoded_len() as u64;
+            if let Err(error) = features.check_size(size) {
+                warn!("Not sending to [{id}]: {error}");
+                return None;
+            }
+            // Too large for one message, the peer downloads it.
+            if chunk::is_large(&content) {
+                return Some(Ok(chunk::placeholder(&content)));
+            }
+            if gzips && features.compress(size).is_some() {
+                return Some(compression::pack(&content).map_err(Status::from));
+            }
+            Some(Ok(content))
+        });
+        let stream = PeerStream {
+            clips: Box::pin(clips),
+            cancelled: Box::pin(self.cancel_token.clone().cancelled_owned()),
+            _guard: guard,
+            _negotiated: negotiated,
+        };
+        Ok(Response::new(Box::pin(stream)))
     }
 
     async fn set_clipboard(&self, request: Request<Content>) -> Result<Response<Replaced>, Status> {
-        let content = request.into_inner().text;
-        let replaced = self.sender.send_if_modified(|prev| {
-            if prev != &content {
-                *prev = content;
+        let id = peer_id(&request);
+        self.check_sends(&id, request.remote_addr())?;
+        let content = request.into_inner();
+        self.peers.touch(&id, Some(&content));
+        self.features.check_size(content.encoded_len() as u64)?;
+        let replaced = self.replace(&id, content);
+        Ok(Response::new(Replaced { replaced }))
+    }
+
+    async fn upload_clipboard(
+        &self,
+        request: Request<Streaming<Chunk>>,
+    ) -> Result<Response<Replaced>, Status> {
+        let id = peer_id(&request);
+        self.check_sends(&id, request.remote_addr())?;
+        let mut chunks = request.into_inner();
+        let mut assembler = Assembler::new("Upload [Server]", self.features.max_payload);
+        while let Some(chunk) = chunks.message().await? {
+            assembler.push(chunk)?;
+        }
+        let content = assembler.finish()?;
+        self.peers.touch(&id, Some(&content));
+        let replaced = self.replace(&id, content);
+        Ok(Response::new(Replaced { replaced }))
+    }
+
+    async fn download_clipboard(
+        &self,
+        request: Request<Download>,
+    ) -> Result<Response<Self::DownloadClipboardStream>, Status> {
+        let id = peer_id(&request);
+        let features = self.features_of(&id, request.remote_addr());
+        let hash = request.into_inner().hash;
+        let clip = self.clips.borrow().clone();
+        let content = outgoing(clip, &id, &features)
+            .filter(|content| content_hash(content) == hash)
+            .ok_or_else(|| Error::NotFound("The announced clip was replaced".into()))?;
+        self.peers.touch(&id, Some(&content));
+        let size = content.encoded_len() as u64;
+        features.check_size(size)?;
+        info!(
+            "Send [{id}] {size} bytes, compression: {}",
+            features
+                .compress(size)
+                .and(features.codec())
+                .unwrap_or("none")
+        );
+        let chunks = chunk::split(&content);
+        let mut progress = Progress::new("Download [Server]", size);
+        let stream = tokio_stream::iter(chunks)
+            .map(move |chunk| {
+                progress.advance(chunk.data.len());
+                chunk
+            })
+            .map(Ok);
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn list_peers(&self, request: Request<Empty>) -> Result<Response<Peers>, Status> {
+        self.peers.touch(&peer_id(&request), None);
+        let peers = describe(&self.peers, &self.negotiated, &self.features);
+        Ok(Response::new(Peers { peers }))
+    }
+
+    async fn list_history(
+        &self,
+        request: Request<HistoryQuery>,
+    ) -> Result<Response<HistoryEntries>, Status> {
+        self.peers.touch(&peer_id(&request), None);
+        let entries = self.history.list(request.into_inner().limit as usize);
+        Ok(Response::new(HistoryEntries { entries }))
+    }
+
+    async fn get_history_entry(
+        &self,
+        request: Request<HistoryEntryId>,
+    ) -> Result<Response<HistoryEntry>, Status> {
+        self.peers.touch(&peer_id(&request), None);
+        let id = request.into_inner().id;
+        let entry = self
+            .history
+            .get(id)
+            .ok_or_else(|| Error::NotFound(format!("No history entry {id}")))?;
+        Ok(Response::new(entry))
+    }
+
+    async fn apply_history_entry(
+        &self,
+        request: Request<HistoryEntryId>,
+    ) -> Result<Response<Replaced>, Status> {
+        self.peers.touch(&peer_id(&request), None);
+        let id = request.into_inner().id;
+        let content = self
+            .history
+            .get(id)
+            .and_then(|entry| entry.content)
+            .ok_or_else(|| Error::NotFound(format!("No history entry {id}")))?;
+        // A sealed clip only opens under the stamp it was sealed with.
+        if content.is_sealed() {
+            return Err(Error::Crypto(format!(
+                "History entry {id} is encrypted, apply it on a peer with the passphrase"
+            ))
+            .into());
+        }
+        // Copied anew on the server so it wins over the current clip everywhere.
+        let content = self.stamper.stamp(content.unstamped());
+        let replaced = self.clips.send_if_modified(|clip| {
+            if content.supersedes(&clip.content) {
+                *clip = Clip {
+                    origin: None,
+                    content: content.clone(),
+                };
                 true
             } else {
                 false
             }
         });
+        if replaced {
+            self.history.record(None, &content);
+            // Unstamped, the server clipboard would take its own stamp for an echo.
+            self.sender.send_replace(content.unstamped());
+        }
         Ok(Response::new(Replaced { replaced }))
     }
 }
@@ -0,0 +1,91 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+
+use crate::util::now_millis;
+use crate::{Content, Stamp};
+
+/// How far ahead of the local clock a stamp may be, more than clocks
+/// disagree by.
+pub const MAX_CLOCK_SKEW: u64 = 60 * 60 * 1000;
+
+impl Stamp {
+    /// Last writer wins: the later timestamp, then the greater origin.
+    pub fn cmp_order(&self, other: &Stamp) -> Ordering {
+        (self.timestamp, &self.origin, self.seq).cmp(&(other.timestamp, &other.origin, other.seq))
+    }
+
+    /// Whether this stamp wins over `other`, any stamp wins over none.
+    pub fn is_newer(&self, other: Option<&Stamp>) -> bool {
+        match other {
+            Some(other) => self.cmp_order(other) == Ordering::Greater,
+            None => true,
+        }
+    }
+
+    /// Whether the stamp is further ahead of the local clock than
+    /// [`MAX_CLOCK_SKEW`], like one forged to win over every later copy.
+    pub fn is_too_far_ahead(&self) -> bool {
+        self.timestamp > now_millis().saturating_add(MAX_CLOCK_SKEW)
+    }
+}
+
+impl Display for Stamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}#{}@{}", self.origin, self.seq, self.timestamp)
+    }
+}
+
+/// Stamps the clips copied on one node.
+///
+/// Timestamps follow the wall clock but never fall behind a clip the node
+/// has observed, so a copy made after receiving a clip wins over it even
+/// when the clocks of the two nodes disagree.
+#[derive(Clone)]
+pub struct Stamper {
+    origin: String,
+    state: Arc<Mutex<StamperState>>,
+}
+
+#[derive(Default)]
+struct StamperState {
+    seq: u64,
+    timestamp: u64,
+}
+
+impl Stamper {
+    pub fn new(origin: impl Into<String>) -> Self {
+        Self {
+            origin: origin.into(),
+            state: Arc::default(),
+        }
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// The next stamp of this node.
+    pub fn next(&self) -> Stamp {
+        let mut state = self.state.lock().unwrap();
+        state.seq += 1;
+        state.timestamp = now_millis().max(state.timestamp.saturating_add(1));
+        Stamp {
+            origin: self.origin.clone(),
+            timestamp: state.timestamp,
+            seq: state.seq,
+        }
+    }
+
+    pub fn stamp(&self, content: Content) -> Content {
+        content.with_stamp(self.next())
+    }
+
+    /// Keep later stamps ahead of a clip from another node, no further
+    /// than [`MAX_CLOCK_SKEW`] ahead of the local clock.
+    pub fn observe(&self, stamp: &Stamp) {
+        let ahead = now_millis().saturating_add(MAX_CLOCK_SKEW);
+        let mut state = self.state.lock().unwrap();
+        state.timestamp = state.timestamp.max(stamp.timestamp.min(ahead));
+    }
+}
@@ -0,0 +1,60 @@
+use std::fs;
+use std::path::Path;
+
+use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
+
+use crate::error::{Error, Result};
+
+fn read_pem(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|e| Error::io(format!("Read PEM file {:?}", path), e))
+}
+
+/// Build the server side TLS config from PEM files.
+///
+/// With a `client_ca` clients may present a certificate signed by it, with
+/// `require_client_cert` they must.
+pub fn server_tls_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: Option<&Path>,
+    require_client_cert: bool,
+) -> Result<ServerTlsConfig> {
+    let identity = Identity::from_pem(read_pem(cert)?, read_pem(key)?);
+    let mut config = ServerTlsConfig::new().identity(identity);
+    match client_ca {
+        Some(client_ca) => {
+            config = config
+                .client_ca_root(Certificate::from_pem(read_pem(client_ca)?))
+                .client_auth_optional(!require_client_cert);
+        }
+        None if require_client_cert => {
+            return Err(Error::Config(
+                "Requiring a client certificate needs a client CA".into(),
+            ));
+        }
+        None => {}
+    }
+    Ok(config)
+}
+
+/// Build the client side TLS config from PEM files.
+///
+/// Without a `ca` the server is verified against the system roots, with an
+/// `identity` (certificate, key) the client authenticates itself.
+pub fn client_tls_config(
+    ca: Option<&Path>,
+    identity: Option<(&Path, &Path)>,
+    domain: Option<&str>,
+) -> Result<ClientTlsConfig> {
+    let mut config = ClientTlsConfig::new();
+    if let Some(ca) = ca {
+        config = config.ca_certificate(Certificate::from_pem(read_pem(ca)?));
+    }
+    if let Some((cert, key)) = identity {
+        config = config.identity(Identity::from_pem(read_pem(cert)?, read_pem(key)?));
+    }
+    if let Some(domain) = domain {
+        config = config.domain_name(domain);
+    }
+    Ok(config)
+}
@@ -0,0 +1,25 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use prost::Message;
+
+use crate::Content;
+
+/// The metadata key a client uses to identify itself on every call.
+pub const PEER_ID_HEADER: &str = "synclip-peer-id";
+
+/// Tells clips apart without keeping them, as listed for peers.
+pub fn content_hash(content: &Content) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.encode_to_vec().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Milliseconds since the Unix epoch, what every timestamp is kept in.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
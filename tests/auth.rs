@@ -0,0 +1,115 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{connect, free_port, wait_for};
+use synclip::auth::{bearer, AUTHORIZATION_HEADER};
+use synclip::client::SynclipClient;
+use synclip::clipboard::backend::{ClipboardBackend, MemoryBackend};
+use synclip::clipboard::local_clipboard::LocalClipboard;
+use synclip::clipboard::Clipboard;
+use synclip::handshake::Features;
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::{Content, Empty};
+use tokio_util::sync::CancellationToken;
+use tonic::{Code, Request};
+
+fn with_token(token: &str) -> Request<Empty> {
+    let mut request = Request::new(Empty {});
+    request
+        .metadata_mut()
+        .insert(AUTHORIZATION_HEADER, bearer(token).unwrap());
+    request
+}
+
+#[tokio::test]
+async fn calls_without_the_shared_token_are_rejected() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    let token = Some("s3cret".to_string());
+    SynclipServer::new(
+        common::listen(port),
+        "initial".into(),
+        None,
+        token,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut client = connect(port).await;
+
+    let status = client.list_peers(Empty {}).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    let status = client
+        .polling_clipboard(with_token("guess"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    let content = Request::new(Content::from("overwrite"));
+    let status = client.set_clipboard(content).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    assert!(client.list_peers(with_token("s3cret")).await.is_ok());
+    cancel_token.cancel();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_attaches_the_shared_token() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    let token = Some("s3cret".to_string());
+
+    let mut server_backend = MemoryBackend::new("initial");
+    let server = SynclipServer::new(
+        common::listen(port),
+        "initial".into(),
+        None,
+        token.clone(),
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut server_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(server_backend.clone()),
+        server,
+        20,
+        cancel_token.clone(),
+    );
+    let server_handle = server_clipboard.start();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut client_backend = MemoryBackend::new("initial");
+    let client = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        "initial".into(),
+        None,
+        token,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut client_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(client_backend.clone()),
+        client,
+        20,
+        cancel_token.clone(),
+    );
+    let client_handle = client_clipboard.start();
+
+    let copied = Content::from("authenticated");
+    client_backend.set(copied.clone()).unwrap();
+    wait_for(&mut server_backend, &copied).await;
+
+    cancel_token.cancel();
+    client_clipboard.shutdown().await.unwrap();
+    server_clipboard.shutdown().await.unwrap();
+    client_handle.await.unwrap();
+    server_handle.await.unwrap();
+}
@@ -0,0 +1,42 @@
+use synclip::clipboard::backend::{ClipboardBackend, FileBackend, MemoryBackend};
+use synclip::content::{TEXT_HTML, TEXT_PLAIN};
+use synclip::Content;
+
+#[test]
+fn memory_backend_notifies_watchers() {
+    let mut backend = MemoryBackend::default();
+    let watcher = backend.watch().unwrap();
+    assert!(!watcher.has_changed().unwrap());
+
+    let copied = Content::from("copied").with(TEXT_HTML, "<b>copied</b>");
+    backend.clone().set(copied.clone()).unwrap();
+    assert!(watcher.has_changed().unwrap());
+    assert_eq!(backend.get().unwrap(), copied);
+}
+
+#[test]
+fn file_backend_round_trips() {
+    let path = std::env::temp_dir().join(format!("synclip-{}.txt", std::process::id()));
+    let mut backend = FileBackend::new(&path);
+    assert!(backend.get().unwrap().is_empty());
+
+    let copied = Content::from("copied").with(TEXT_HTML, "<b>copied</b>");
+    backend.set(copied).unwrap();
+    assert_eq!(backend.get().unwrap(), Content::from("copied"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn content_keeps_plain_text_in_text_field() {
+    let content = Content::default()
+        .with(TEXT_PLAIN, "plain")
+        .with(TEXT_HTML, "<i>old</i>")
+        .with(TEXT_HTML, "<b>new</b>");
+    assert_eq!(content.text, "plain");
+    assert_eq!(content.get(TEXT_HTML), Some("<b>new</b>".as_bytes()));
+    assert_eq!(
+        content.mime_types().collect::<Vec<_>>(),
+        [TEXT_PLAIN, TEXT_HTML]
+    );
+    assert_eq!(content.size(), 15);
+}
@@ -0,0 +1,288 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{connect, free_port, wait_for};
+use prost::Message;
+use synclip::chunk::{self, Assembler, CHUNK_SIZE};
+use synclip::client::{ConnectionState, SynclipClient};
+use synclip::clipboard::backend::{ClipboardBackend, MemoryBackend};
+use synclip::clipboard::local_clipboard::LocalClipboard;
+use synclip::clipboard::Clipboard;
+use synclip::content::IMAGE_PNG;
+use synclip::handshake::{Features, DEFAULT_MAX_PAYLOAD};
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::util::PEER_ID_HEADER;
+use synclip::{Content, Download, Empty, Error};
+use tokio_util::sync::CancellationToken;
+use tonic::{Code, Request, Status};
+
+fn large(byte: u8, size: usize) -> Content {
+    Content::default().with(IMAGE_PNG, vec![byte; size])
+}
+
+fn limited(max_payload: u64) -> Features {
+    Features {
+        max_payload,
+        ..Features::default()
+    }
+}
+
+#[test]
+fn chunks_put_back_together_give_the_clip() {
+    let content = large(7, 3 * CHUNK_SIZE);
+    let chunks = chunk::split(&content);
+    assert_eq!(chunks.len(), content.encoded_len().div_ceil(CHUNK_SIZE));
+    assert!(chunks.iter().all(|chunk| chunk.data.len() <= CHUNK_SIZE));
+
+    let mut assembler = Assembler::new("Test", DEFAULT_MAX_PAYLOAD);
+    for chunk in chunks {
+        assembler.push(chunk).unwrap();
+    }
+    assert_eq!(assembler.finish().unwrap(), content);
+}
+
+#[test]
+fn assembler_refuses_clips_over_the_limit() {
+    let content = large(7, 2 * CHUNK_SIZE);
+    let mut assembler = Assembler::new("Test", CHUNK_SIZE as u64);
+
+    let chunk = chunk::split(&content).remove(0);
+    let error = assembler.push(chunk).unwrap_err();
+    assert!(matches!(error, Error::PayloadTooLarge(_)), "{error:?}");
+}
+
+#[test]
+fn assembler_notices_missing_chunks() {
+    let content = large(7, 2 * CHUNK_SIZE);
+    let mut assembler = Assembler::new("Test", DEFAULT_MAX_PAYLOAD);
+
+    assembler.push(chunk::split(&content).remove(0)).unwrap();
+    let error = assembler.finish().unwrap_err();
+    assert!(matches!(error, Error::Corrupt(_)), "{error:?}");
+
+    let error = Assembler::new("Test", DEFAULT_MAX_PAYLOAD)
+        .finish()
+        .unwrap_err();
+    assert!(matches!(error, Error::Corrupt(_)), "{error:?}");
+
+    let mut assembler = Assembler::new("Test", DEFAULT_MAX_PAYLOAD);
+    for chunk in chunk::split(&Content::default()) {
+        assembler.push(chunk).unwrap();
+    }
+    assert_eq!(assembler.finish().unwrap(), Content::default());
+}
+
+#[test]
+fn placeholder_only_carries_stamp_and_size() {
+    let content = large(7, 2 * CHUNK_SIZE);
+    assert!(chunk::is_large(&content));
+    assert!(!chunk::is_large(&Content::from("small")));
+
+    let placeholder = chunk::placeholder(&content);
+    assert_eq!(placeholder.chunked, content.encoded_len() as u64);
+    assert!(placeholder.representations.is_empty());
+}
+
+fn as_peer<T>(id: &str, message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert(PEER_ID_HEADER, id.parse().unwrap());
+    request
+}
+
+async fn start_server(
+    port: u16,
+    features: Features,
+    cancel_token: &CancellationToken,
+) -> SynclipServer {
+    SynclipServer::new(
+        common::listen(port),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        features,
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn server_rejects_clips_over_its_limit() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    start_server(port, limited(1024), &cancel_token).await;
+    let mut client = connect(port).await;
+
+    let status = client.set_clipboard(large(1, 2048)).await.unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    let stream = tokio_stream::iter(chunk::split(&large(1, 2048)));
+    let status = client.upload_clipboard(stream).await.unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+
+    assert!(client.set_clipboard(Content::from("small")).await.is_ok());
+    cancel_token.cancel();
+}
+
+#[tokio::test]
+async fn downloads_serve_only_the_announced_clip() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    start_server(port, Features::default(), &cancel_token).await;
+    let mut sender = connect(port).await;
+    let mut receiver = connect(port).await;
+    let mut stream = receiver
+        .polling_clipboard(as_peer("receiver", Empty {}))
+        .await
+        .unwrap()
+        .into_inner();
+    stream.message().await.unwrap();
+
+    let mut announced = Vec::new();
+    for byte in [1, 2] {
+        let chunks = tokio_stream::iter(chunk::split(&large(byte, 2 * CHUNK_SIZE)));
+        sender
+            .upload_clipboard(as_peer("sender", chunks))
+            .await
+            .unwrap();
+        let placeholder = stream.message().await.unwrap().unwrap();
+        assert!(placeholder.chunked > 0);
+        announced.push(placeholder.chunked_hash);
+    }
+
+    let download = |id: &'static str, hash: u64| {
+        let mut client = receiver.clone();
+        async move {
+            let mut chunks = client
+                .download_clipboard(as_peer(id, Download { hash }))
+                .await?
+                .into_inner();
+            let mut assembler = Assembler::new("Test", DEFAULT_MAX_PAYLOAD);
+            while let Some(chunk) = chunks.message().await? {
+                assembler.push(chunk).unwrap();
+            }
+            Ok::<_, Status>(assembler.finish().unwrap())
+        }
+    };
+    // Replaced since it was announced.
+    let status = download("receiver", announced[0]).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let downloaded = download("receiver", announced[1]).await.unwrap();
+    assert_eq!(downloaded, large(2, 2 * CHUNK_SIZE));
+    // Never sent back where it came from.
+    let status = download("sender", announced[1]).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    cancel_token.cancel();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn large_clips_sync_in_chunks_both_ways() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+
+    let mut server_backend = MemoryBackend::new("initial");
+    let server = start_server(port, Features::default(), &cancel_token).await;
+    let mut server_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(server_backend.clone()),
+        server,
+        20,
+        cancel_token.clone(),
+    );
+    let server_handle = server_clipboard.start();
+
+    let mut client_backend = MemoryBackend::new("initial");
+    let client = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut client_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(client_backend.clone()),
+        client,
+        20,
+        cancel_token.clone(),
+    );
+    let client_handle = client_clipboard.start();
+
+    let from_client = large(1, 6 * CHUNK_SIZE);
+    client_backend.set(from_client.clone()).unwrap();
+    wait_for(&mut server_backend, &from_client).await;
+
+    let from_server = large(2, 5 * CHUNK_SIZE);
+    server_backend.set(from_server.clone()).unwrap();
+    wait_for(&mut client_backend, &from_server).await;
+
+    cancel_token.cancel();
+    client_clipboard.shutdown().await.unwrap();
+    server_clipboard.shutdown().await.unwrap();
+    client_handle.await.unwrap();
+    server_handle.await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clips_over_the_limit_stay_local_and_syncing_goes_on() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+
+    let mut server_backend = MemoryBackend::new("initial");
+    let server = start_server(port, Features::default(), &cancel_token).await;
+    let mut server_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(server_backend.clone()),
+        server,
+        20,
+        cancel_token.clone(),
+    );
+    let server_handle = server_clipboard.start();
+
+    let mut client_backend = MemoryBackend::new("initial");
+    let client = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        limited(1024),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let state = client.watch_state();
+    let mut client_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(client_backend.clone()),
+        client,
+        20,
+        cancel_token.clone(),
+    );
+    let client_handle = client_clipboard.start();
+
+    client_backend.set(large(1, 4096)).unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(server_backend.get().unwrap(), Content::from("initial"));
+    assert_eq!(*state.borrow(), ConnectionState::Connected);
+
+    let small = Content::from("small");
+    client_backend.set(small.clone()).unwrap();
+    wait_for(&mut server_backend, &small).await;
+
+    // Nor does the server send the client more than it accepts.
+    server_backend.set(large(2, 4096)).unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(client_backend.get().unwrap(), small);
+    assert_eq!(*state.borrow(), ConnectionState::Connected);
+
+    cancel_token.cancel();
+    client_clipboard.shutdown().await.unwrap();
+    server_clipboard.shutdown().await.unwrap();
+    client_handle.await.unwrap();
+    server_handle.await.unwrap();
+}
@@ -0,0 +1,41 @@
+#![allow(dead_code)]
+
+use std::net::{SocketAddr, TcpListener};
+use std::time::Duration;
+
+use synclip::clipboard::backend::{ClipboardBackend, MemoryBackend};
+use synclip::synclip_client::SynclipClient;
+use synclip::Content;
+use tonic::transport::Channel;
+
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+pub fn listen(port: u16) -> SocketAddr {
+    ([127, 0, 0, 1], port).into()
+}
+
+pub async fn wait_for(backend: &mut MemoryBackend, expected: &Content) {
+    for _ in 0..100 {
+        if &backend.get().unwrap() == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("clipboard never became {expected}");
+}
+
+pub async fn connect(port: u16) -> SynclipClient<Channel> {
+    for _ in 0..50 {
+        if let Ok(client) = SynclipClient::connect(format!("http://127.0.0.1:{port}")).await {
+            return client;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("server never came up");
+}
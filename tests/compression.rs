@@ -0,0 +1,174 @@
+mod common;
+
+use common::{connect, free_port};
+use prost::Message;
+use synclip::compression::{self, DEFAULT_THRESHOLD, GZIP};
+use synclip::handshake::Features;
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::synclip_client::SynclipClient;
+use synclip::util::PEER_ID_HEADER;
+use synclip::{Content, Empty};
+use tokio_util::sync::CancellationToken;
+use tonic::codec::CompressionEncoding;
+use tonic::transport::Channel;
+use tonic::Request;
+
+fn as_peer<T>(id: &str, message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert(PEER_ID_HEADER, id.parse().unwrap());
+    request
+}
+
+fn log_lines(lines: usize) -> Content {
+    let text: String = (0..lines)
+        .map(|line| format!("{{\"line\": {line}, \"level\": \"info\"}}\n"))
+        .collect();
+    Content::from_text(text)
+}
+
+async fn start_server(port: u16, cancel_token: &CancellationToken) -> SynclipServer {
+    SynclipServer::new(
+        common::listen(port),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap()
+}
+
+async fn say_hello(client: &mut SynclipClient<Channel>, id: &str, features: &Features) {
+    client.hello(as_peer(id, features.hello(id))).await.unwrap();
+}
+
+#[test]
+fn small_clips_are_not_compressed() {
+    let features = Features::default();
+    assert_eq!(features.codec(), Some(GZIP));
+    assert_eq!(features.compress(DEFAULT_THRESHOLD - 1), None);
+    assert_eq!(
+        features.compress(DEFAULT_THRESHOLD),
+        Some(CompressionEncoding::Gzip)
+    );
+}
+
+#[test]
+fn compression_needs_a_codec_both_sides_know() {
+    let ours = Features::default();
+    let mut theirs = Features::default().hello("them");
+    theirs.compression = vec!["zstd".to_string()];
+
+    let narrowed = ours.narrow(&theirs);
+    assert_eq!(narrowed.codec(), None);
+    assert_eq!(narrowed.compress(u64::MAX), None);
+}
+
+#[tokio::test]
+async fn server_reports_the_negotiated_codec() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    start_server(port, &cancel_token).await;
+    let mut client = connect(port).await;
+
+    say_hello(&mut client, "gzip", &Features::default()).await;
+    let plain = Features {
+        compression: Vec::new(),
+        ..Features::default()
+    };
+    say_hello(&mut client, "plain", &plain).await;
+    let _gzip = client.polling_clipboard(as_peer("gzip", Empty {})).await;
+    let _plain = client.polling_clipboard(as_peer("plain", Empty {})).await;
+
+    let peers = client
+        .list_peers(Empty {})
+        .await
+        .unwrap()
+        .into_inner()
+        .peers;
+    let codec = |id: &str| {
+        peers
+            .iter()
+            .find(|peer| peer.id == id)
+            .map(|peer| peer.compression.clone())
+    };
+    assert_eq!(codec("gzip").as_deref(), Some(GZIP));
+    assert_eq!(codec("plain").as_deref(), Some(""));
+    cancel_token.cancel();
+}
+
+#[tokio::test]
+async fn only_clips_past_the_threshold_are_gzipped_on_the_way_down() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    start_server(port, &cancel_token).await;
+    let mut gzip = connect(port).await;
+    let mut plain = connect(port).await;
+    let mut sender = connect(port).await;
+
+    say_hello(&mut gzip, "gzip", &Features::default()).await;
+    let response = gzip
+        .polling_clipboard(as_peer("gzip", Empty {}))
+        .await
+        .unwrap();
+    // Each clip is gzipped on its own, never the whole stream.
+    assert!(response.metadata().get("grpc-encoding").is_none());
+    let mut gzip_stream = response.into_inner();
+    gzip_stream.message().await.unwrap();
+    // Never said hello, it gets the clips as they are.
+    let mut plain_stream = plain
+        .polling_clipboard(as_peer("plain", Empty {}))
+        .await
+        .unwrap()
+        .into_inner();
+    plain_stream.message().await.unwrap();
+
+    let small = Content::from_text("short");
+    sender
+        .set_clipboard(as_peer("sender", small.clone()))
+        .await
+        .unwrap();
+    for stream in [&mut gzip_stream, &mut plain_stream] {
+        let received = stream.message().await.unwrap().unwrap();
+        assert!(received.gzipped.is_empty());
+        assert_eq!(received.text, small.text);
+    }
+
+    let logs = log_lines(1000);
+    sender
+        .set_clipboard(as_peer("sender", logs.clone()))
+        .await
+        .unwrap();
+    let received = gzip_stream.message().await.unwrap().unwrap();
+    assert!(received.text.is_empty());
+    assert!((received.gzipped.len() as u64) < logs.encoded_len() as u64);
+    let unpacked = compression::unpack(&received, u64::MAX).unwrap();
+    assert_eq!(unpacked.text, logs.text);
+    assert_eq!(unpacked.stamp, received.stamp);
+    // Worth compressing is no reason to download it.
+    let received = plain_stream.message().await.unwrap().unwrap();
+    assert_eq!(received.chunked, 0);
+    assert!(received.gzipped.is_empty());
+    assert_eq!(received.text, logs.text);
+    cancel_token.cancel();
+}
+
+#[test]
+fn unpacking_refuses_a_clip_over_the_limit() {
+    let logs = log_lines(1000);
+    let packed = compression::pack(&logs).unwrap();
+    let error = compression::unpack(&packed, logs.encoded_len() as u64 - 1).unwrap_err();
+    assert!(matches!(error, synclip::Error::PayloadTooLarge(_)));
+
+    let corrupt = Content {
+        gzipped: b"not gzip".to_vec(),
+        ..Content::default()
+    };
+    let error = compression::unpack(&corrupt, u64::MAX).unwrap_err();
+    assert!(matches!(error, synclip::Error::Corrupt(_)));
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::fs;
+
+use synclip::config::Config;
+use synclip::direction::Direction;
+use synclip::filter::Action;
+use synclip::Error;
+
+const EXAMPLE: &str = r#"
+[server]
+listen = "127.0.0.1:6000"
+
+[server.peer_directions]
+"10.0.0.12" = "receive"
+
+[client]
+address = "https://clip.example:6000"
+
+[auth]
+token = "secret-token"
+
+[sync]
+poll_interval = 250
+"#;
+
+fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let map: HashMap<String, String> = pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    move |name| map.get(name).cloned()
+}
+
+#[test]
+fn file_overrides_defaults() {
+    let config = Config::parse(EXAMPLE).unwrap();
+    assert_eq!(config.server.listen, "127.0.0.1:6000".parse().unwrap());
+    assert_eq!(
+        config.client.address.as_deref(),
+        Some("https://clip.example:6000")
+    );
+    assert_eq!(config.sync.poll_interval, 250);
+    assert_eq!(
+        config.features().peer_directions.get("10.0.0.12"),
+        Some(&Direction::Receive)
+    );
+    assert!(config.tls.cert.is_none());
+    assert_eq!(Config::parse("").unwrap(), Config::default());
+    assert!(
+        !Config::default().discovery.advertise,
+        "advertising is opt-in"
+    );
+}
+
+#[test]
+fn env_overrides_file() {
+    let mut config = Config::parse(EXAMPLE).unwrap();
+    config
+        .apply_vars(vars(&[
+            ("SYNCLIP_LISTEN", "0.0.0.0:7000"),
+            ("SYNCLIP_POLL_INTERVAL", "1000"),
+            ("SYNCLIP_REQUIRE_CLIENT_CERT", "yes"),
+            ("SYNCLIP_MAX_CLIP_SIZE", "1048576"),
+            ("SYNCLIP_COMPRESSION", "none"),
+            ("SYNCLIP_FILTER_BUILTIN", "false"),
+            ("SYNCLIP_FILTER_HINTS", "redact"),
+            ("SYNCLIP_DIRECTION", "send-only"),
+            ("SYNCLIP_CONTROL_SOCKET", "/run/user/1000/clip.sock"),
+            ("SYNCLIP_ADVERTISE", "true"),
+        ]))
+        .unwrap();
+    assert_eq!(config.server.listen, "0.0.0.0:7000".parse().unwrap());
+    assert_eq!(config.sync.poll_interval, 1000);
+    assert!(config.tls.require_client_cert);
+    assert_eq!(config.sync.features().max_payload, 1048576);
+    assert_eq!(config.sync.features().codec(), None);
+    assert!(!config.filter.builtin);
+    assert_eq!(config.filter.hints, Action::Redact);
+    assert_eq!(config.sync.features().direction, Direction::Send);
+    assert_eq!(
+        config.daemon.socket().unwrap(),
+        std::path::PathBuf::from("/run/user/1000/clip.sock")
+    );
+    assert_eq!(config.auth.token.as_deref(), Some("secret-token"));
+    assert!(config.discovery.advertise);
+
+    let error = config
+        .apply_vars(vars(&[("SYNCLIP_POLL_INTERVAL", "soon")]))
+        .unwrap_err();
+    assert!(matches!(error, Error::Config(_)), "{error:?}");
+    assert!(error.to_string().contains("SYNCLIP_POLL_INTERVAL"));
+}
+
+#[test]
+fn env_secrets_replace_their_files() {
+    let mut config = Config::parse(
+        "[auth]\ntoken_file = \"/etc/synclip/token\"\n\
+         [encryption]\npassphrase_file = \"/etc/synclip/passphrase\"\ngroup = \"desks\"\n",
+    )
+    .unwrap();
+    config
+        .apply_vars(vars(&[
+            ("SYNCLIP_TOKEN", "s3cret"),
+            ("SYNCLIP_PASSPHRASE", "correct horse"),
+        ]))
+        .unwrap();
+    assert_eq!(config.auth.token.as_deref(), Some("s3cret"));
+    assert!(config.auth.token_file.is_none());
+    assert!(config.encryption.passphrase_file.is_none());
+    config.validate().unwrap();
+
+    let dir = std::env::temp_dir().join(format!("synclip-secrets-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (token_file, passphrase_file) = (dir.join("token"), dir.join("passphrase"));
+    fs::write(&token_file, "s3cret\n").unwrap();
+    fs::write(&passphrase_file, "correct horse\n").unwrap();
+    config
+        .apply_vars(vars(&[
+            ("SYNCLIP_TOKEN_FILE", token_file.to_str().unwrap()),
+            ("SYNCLIP_PASSPHRASE_FILE", passphrase_file.to_str().unwrap()),
+        ]))
+        .unwrap();
+    assert!(config.auth.token.is_none());
+    assert_eq!(config.auth.token_file.as_ref(), Some(&token_file));
+    assert!(config.encryption.passphrase.is_none());
+    config.validate().unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unknown_keys_are_rejected() {
+    assert!(Config::parse("[sync]\npoll_intervall = 5").is_err());
+}
+
+#[test]
+fn explicit_path_must_exist() {
+    let dir = std::env::temp_dir().join(format!("synclip-config-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    let error = Config::load(Some(&path)).unwrap_err();
+    assert!(matches!(error, Error::Io { .. }), "{error:?}");
+
+    fs::write(&path, EXAMPLE).unwrap();
+    assert_eq!(
+        Config::load(Some(&path)).unwrap(),
+        Config::parse(EXAMPLE).unwrap()
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn validate_catches_mistakes() {
+    assert!(Config::default().validate().is_ok());
+
+    let mut config = Config::default();
+    config.tls.cert = Some("cert.pem".into());
+    assert!(config.validate().is_err());
+
+    let mut config = Config::default();
+    config.tls.require_client_cert = true;
+    assert!(config.validate().is_err());
+
+    let mut config = Config::default();
+    config.sync.poll_interval = 0;
+    assert!(config.validate().is_err());
+
+    let mut config = Config::default();
+    config.sync.max_clip_size = 0;
+    assert!(config.validate().is_err());
+
+    let mut config = Config::default();
+    config.sync.compression = vec!["zstd".to_string()];
+    let error = config.validate().unwrap_err();
+    assert!(error.to_string().contains("gzip"));
+
+    let mut config = Config::default();
+    config.auth.token_file = Some("/nonexistent/synclip-token".into());
+    assert!(config.validate().is_err());
+
+    let mut config = Config::default();
+    config.encryption.passphrase = Some("correct horse".into());
+    let error = config.validate().unwrap_err();
+    assert!(error.to_string().contains("encryption.group"), "{error}");
+    config.encryption.group = Some("desks".into());
+    assert!(config.validate().is_ok());
+
+    let mut config = Config::default();
+    config
+        .server
+        .peer_directions
+        .insert("kiosk".into(), Direction::Receive);
+    let error = config.validate().unwrap_err();
+    assert!(matches!(error, Error::Config(_)), "{error:?}");
+    config.server.peer_directions.clear();
+    config
+        .server
+        .peer_directions
+        .insert("10.0.0.12".into(), Direction::Receive);
+    assert!(config.validate().is_ok());
+}
+
+#[test]
+fn redacted_hides_secrets() {
+    let mut config = Config::parse(EXAMPLE).unwrap();
+    config.encryption.passphrase = Some("hunter2".into());
+    let printed = config.redacted().to_toml().unwrap();
+    assert!(!printed.contains("secret-token"));
+    assert!(!printed.contains("hunter2"));
+    assert_eq!(
+        Config::parse(&printed).unwrap().server.listen,
+        config.server.listen
+    );
+}
@@ -0,0 +1,354 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use common::{free_port, wait_for};
+use synclip::client::SynclipClient;
+use synclip::clipboard::backend::{ClipboardBackend, MemoryBackend};
+use synclip::clipboard::local_clipboard::LocalClipboard;
+use synclip::clipboard::Clipboard;
+use synclip::control::{self, ControlServer};
+use synclip::daemon::{read_pid, PidFile};
+use synclip::handshake::Features;
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::{Content, Empty, HistoryEntryId, HistoryQuery, PauseRequest};
+use tokio_util::sync::CancellationToken;
+use tonic::Code;
+
+fn runtime_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("synclip-control-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn server_clipboard(
+    port: u16,
+    backend: &MemoryBackend,
+    cancel_token: &CancellationToken,
+) -> Clipboard<SynclipServer, MemoryBackend> {
+    let server = SynclipServer::new(
+        common::listen(port),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    Clipboard::new(
+        LocalClipboard::with_backend(backend.clone()),
+        server,
+        20,
+        cancel_token.clone(),
+    )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_running_node_is_controlled_over_its_socket() {
+    let port = free_port();
+    let socket = runtime_dir("node").join("control.sock");
+    let cancel_token = CancellationToken::new();
+
+    let mut server_backend = MemoryBackend::new("initial");
+    let mut clipboard = server_clipboard(port, &server_backend, &cancel_token).await;
+    let control_server =
+        ControlServer::new(&socket, clipboard.clone(), "server", cancel_token.clone())
+            .await
+            .unwrap();
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    let handle = clipboard.start();
+
+    let mut client_backend = MemoryBackend::new("initial");
+    let client = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut client_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(client_backend.clone()),
+        client,
+        20,
+        cancel_token.clone(),
+    );
+    let client_handle = client_clipboard.start();
+
+    let mut control = control::connect(&socket).await.unwrap();
+    let status = control.status(Empty {}).await.unwrap().into_inner();
+    assert_eq!(status.mode, "server");
+    assert_eq!(status.pid, std::process::id());
+    assert_eq!(status.direction, "both");
+    assert!(!status.paused);
+
+    let status = control
+        .pause(PauseRequest { seconds: 0 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(status.paused);
+    assert_eq!(status.paused_until, 0);
+    client_backend.set(Content::from("while paused")).unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(server_backend.get().unwrap(), Content::from("initial"));
+
+    let status = control.resume(Empty {}).await.unwrap().into_inner();
+    assert!(!status.paused);
+    client_backend.set(Content::from("after resume")).unwrap();
+    wait_for(&mut server_backend, &Content::from("after resume")).await;
+
+    let peers = control
+        .list_peers(Empty {})
+        .await
+        .unwrap()
+        .into_inner()
+        .peers;
+    assert_eq!(peers.len(), 1);
+    assert_eq!(peers[0].direction, "both");
+
+    control.stop(Empty {}).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(2), cancel_token.cancelled())
+        .await
+        .expect("stop cancels the node");
+    client_clipboard.shutdown().await.unwrap();
+    clipboard.shutdown().await.unwrap();
+    client_handle.await.unwrap();
+    handle.await.unwrap();
+    control_server.shutdown().await.unwrap();
+    assert!(!socket.exists(), "the socket goes with the node");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pauses_run_out_and_skips_last_one_copy() {
+    let port = free_port();
+    let socket = runtime_dir("skip").join("control.sock");
+    let cancel_token = CancellationToken::new();
+
+    let mut server_backend = MemoryBackend::new("initial");
+    let mut clipboard = server_clipboard(port, &server_backend, &cancel_token).await;
+    let control_server =
+        ControlServer::new(&socket, clipboard.clone(), "server", cancel_token.clone())
+            .await
+            .unwrap();
+    let handle = clipboard.start();
+    let mut control = control::connect(&socket).await.unwrap();
+
+    let mut client_backend = MemoryBackend::new("initial");
+    let client = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut client_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(client_backend.clone()),
+        client,
+        20,
+        cancel_token.clone(),
+    );
+    let client_handle = client_clipboard.start();
+
+    let status = control
+        .pause(PauseRequest { seconds: 60 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(status.paused);
+    assert!(status.paused_until > status.started_at);
+    control.resume(Empty {}).await.unwrap();
+
+    clipboard.pause_for(Duration::from_millis(200));
+    assert!(clipboard.is_paused());
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let status = control.status(Empty {}).await.unwrap().into_inner();
+    assert!(!status.paused, "a timed pause ends by itself");
+
+    client_clipboard.skip_next();
+    client_backend.set(Content::from("one-off")).unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(server_backend.get().unwrap(), Content::from("initial"));
+    assert!(!client_clipboard.skips_next());
+
+    client_backend.set(Content::from("shared again")).unwrap();
+    wait_for(&mut server_backend, &Content::from("shared again")).await;
+
+    let status = control.skip_next(Empty {}).await.unwrap().into_inner();
+    assert!(status.skip_next);
+
+    cancel_token.cancel();
+    client_clipboard.shutdown().await.unwrap();
+    clipboard.shutdown().await.unwrap();
+    client_handle.await.unwrap();
+    handle.await.unwrap();
+    control_server.shutdown().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_client_daemon_serves_its_own_history() {
+    let port = free_port();
+    let socket = runtime_dir("history").join("control.sock");
+    let cancel_token = CancellationToken::new();
+
+    let mut server_backend = MemoryBackend::new("initial");
+    let mut clipboard = server_clipboard(port, &server_backend, &cancel_token).await;
+    let handle = clipboard.start();
+
+    let mut client_backend = MemoryBackend::new("initial");
+    let client = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut client_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(client_backend.clone()),
+        client,
+        20,
+        cancel_token.clone(),
+    );
+    let control_server = ControlServer::new(
+        &socket,
+        client_clipboard.clone(),
+        "client",
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let client_handle = client_clipboard.start();
+    let mut control = control::connect(&socket).await.unwrap();
+
+    client_backend.set(Content::from("first")).unwrap();
+    wait_for(&mut server_backend, &Content::from("first")).await;
+    server_backend.set(Content::from("second")).unwrap();
+    wait_for(&mut client_backend, &Content::from("second")).await;
+
+    let entries = control
+        .list_history(HistoryQuery { limit: 0 })
+        .await
+        .unwrap()
+        .into_inner()
+        .entries;
+    let summaries = entries
+        .iter()
+        .map(|entry| entry.summary.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summaries,
+        [
+            "\"second\" text/plain(6 bytes)",
+            "\"first\" text/plain(5 bytes)",
+            "\"initial\" text/plain(7 bytes)"
+        ]
+    );
+    assert_eq!(entries[0].origin, "server");
+    let first = entries[1].id;
+    let entry = control
+        .get_history_entry(HistoryEntryId { id: first })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(entry.content.unwrap().text, "first");
+
+    // Copied again on the client, so it goes to the server too.
+    let replaced = control
+        .apply_history_entry(HistoryEntryId { id: first })
+        .await
+        .unwrap()
+        .into_inner()
+        .replaced;
+    assert!(replaced);
+    assert_eq!(client_backend.get().unwrap(), Content::from("first"));
+    wait_for(&mut server_backend, &Content::from("first")).await;
+
+    let status = control
+        .get_history_entry(HistoryEntryId { id: 999 })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    cancel_token.cancel();
+    client_clipboard.shutdown().await.unwrap();
+    clipboard.shutdown().await.unwrap();
+    client_handle.await.unwrap();
+    handle.await.unwrap();
+    control_server.shutdown().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn one_node_per_socket() {
+    let socket = runtime_dir("taken").join("control.sock");
+    let cancel_token = CancellationToken::new();
+    let backend = MemoryBackend::new("initial");
+    let clipboard = server_clipboard(free_port(), &backend, &cancel_token).await;
+
+    let first = ControlServer::new(&socket, clipboard.clone(), "server", cancel_token.clone())
+        .await
+        .unwrap();
+    let error = ControlServer::new(&socket, clipboard.clone(), "server", cancel_token.clone())
+        .await
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("already listens"));
+
+    cancel_token.cancel();
+    first.shutdown().await.unwrap();
+    // A socket left behind by a crash is taken over.
+    std::fs::write(&socket, "").unwrap();
+    let cancel_token = CancellationToken::new();
+    ControlServer::new(&socket, clipboard, "server", cancel_token.clone())
+        .await
+        .unwrap();
+    cancel_token.cancel();
+}
+
+#[tokio::test]
+async fn no_daemon_no_connection() {
+    let socket = runtime_dir("absent").join("control.sock");
+    let error = control::connect(&socket).await.err().unwrap();
+    assert!(error.to_string().contains("No synclip daemon"));
+}
+
+#[test]
+fn pid_files_guard_against_a_second_daemon() {
+    let path = runtime_dir("pid").join("synclip.pid");
+
+    let pid_file = PidFile::create(&path).unwrap();
+    assert_eq!(read_pid(&path), Some(std::process::id() as i32));
+    drop(pid_file);
+    assert!(!path.exists());
+
+    // init always runs.
+    std::fs::write(&path, "1\n").unwrap();
+    let error = PidFile::create(&path).err().unwrap();
+    assert!(error.to_string().contains("already runs with pid 1"));
+    // Checked before forking too, without writing the file.
+    assert!(PidFile::check(&path).is_err());
+    assert_eq!(read_pid(&path), Some(1));
+
+    // A pid no process has is stale.
+    std::fs::write(&path, format!("{}\n", i32::MAX)).unwrap();
+    PidFile::check(&path).unwrap();
+    PidFile::create(&path).unwrap();
+}
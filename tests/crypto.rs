@@ -0,0 +1,138 @@
+mod common;
+
+use common::{connect, free_port, wait_for};
+use synclip::client::SynclipClient;
+use synclip::clipboard::backend::{ClipboardBackend, MemoryBackend};
+use synclip::clipboard::local_clipboard::LocalClipboard;
+use synclip::clipboard::Clipboard;
+use synclip::content::IMAGE_PNG;
+use synclip::crypto::Cipher;
+use synclip::handshake::Features;
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::stamp::Stamper;
+use synclip::{Content, Empty};
+use synclip::{Error, Stamp};
+use tokio_util::sync::CancellationToken;
+
+#[test]
+fn sealed_clips_only_open_with_the_same_passphrase() {
+    let cipher = Cipher::from_passphrase("correct horse", "test-desks").unwrap();
+    let content = Content::from("hunter2").with(IMAGE_PNG, vec![1, 2, 3]);
+
+    let first = cipher.seal(&content).unwrap();
+    let second = cipher.seal(&content).unwrap();
+    assert!(first.text.is_empty() && first.representations.is_empty());
+    assert_ne!(
+        first.sealed.as_ref().unwrap().nonce,
+        second.sealed.as_ref().unwrap().nonce
+    );
+    assert_eq!(cipher.open(&first).unwrap(), content);
+
+    let other = Cipher::from_passphrase("battery staple", "test-desks").unwrap();
+    let error = other.open(&first).unwrap_err();
+    assert!(matches!(error, Error::Crypto(_)), "{error:?}");
+    assert!(error.to_string().contains("another passphrase"), "{error}");
+
+    let mut tampered = first.clone();
+    tampered.sealed.as_mut().unwrap().ciphertext[0] ^= 1;
+    assert!(cipher.open(&tampered).is_err());
+    assert!(cipher.open(&content).is_err());
+
+    // The same passphrase in another group derives another key.
+    let elsewhere = Cipher::from_passphrase("correct horse", "other-desks").unwrap();
+    assert!(elsewhere.open(&first).is_err());
+    assert!(Cipher::from_passphrase("correct horse", "").is_err());
+}
+
+#[test]
+fn sealed_clips_only_open_under_their_stamp() {
+    let cipher = Cipher::from_passphrase("correct horse", "test-desks").unwrap();
+    let stamp = Stamper::new("desk").next();
+    let sealed = cipher
+        .seal(&Content::from("hunter2").with_stamp(stamp.clone()))
+        .unwrap();
+    assert_eq!(sealed.stamp.as_ref(), Some(&stamp));
+    assert_eq!(cipher.open(&sealed).unwrap(), Content::from("hunter2"));
+
+    // A relay replaying the clip as a newer copy.
+    let replayed = sealed.clone().with_stamp(Stamp {
+        timestamp: stamp.timestamp + 1,
+        ..stamp.clone()
+    });
+    let error = cipher.open(&replayed).unwrap_err();
+    assert!(matches!(error, Error::Crypto(_)), "{error:?}");
+    assert!(cipher.open(&sealed.unstamped()).is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn relay_server_only_sees_ciphertext() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    let server = SynclipServer::new(
+        common::listen(port),
+        Content::default(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut observer = connect(port).await;
+    let mut observed = observer
+        .polling_clipboard(Empty {})
+        .await
+        .unwrap()
+        .into_inner();
+    observed.message().await.unwrap();
+
+    let cipher = Cipher::from_passphrase("correct horse", "test-desks").unwrap();
+    let mut backends = vec![];
+    let mut clipboards = vec![];
+    for _ in 0..2 {
+        let backend = MemoryBackend::default();
+        let client = SynclipClient::new(
+            format!("http://127.0.0.1:{port}"),
+            Content::default(),
+            None,
+            None,
+            History::default(),
+            Features::default(),
+            cancel_token.clone(),
+        )
+        .await
+        .unwrap();
+        let mut clipboard = Clipboard::new(
+            LocalClipboard::with_backend(backend.clone()),
+            client,
+            20,
+            cancel_token.clone(),
+        )
+        .with_cipher(cipher.clone());
+        let handle = clipboard.start();
+        backends.push(backend);
+        clipboards.push((clipboard, handle));
+    }
+
+    let secret = Content::from("hunter2");
+    backends[0].set(secret.clone()).unwrap();
+    wait_for(&mut backends[1], &secret).await;
+
+    loop {
+        let relayed = observed.message().await.unwrap().unwrap();
+        assert!(relayed.is_sealed());
+        assert!(relayed.text.is_empty());
+        if cipher.open(&relayed).unwrap() == secret {
+            break;
+        }
+    }
+
+    cancel_token.cancel();
+    for (clipboard, handle) in clipboards {
+        clipboard.shutdown().await.unwrap();
+        handle.await.unwrap();
+    }
+    server.shutdown().await.unwrap();
+}
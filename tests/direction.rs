@@ -0,0 +1,333 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use common::{connect, free_port, wait_for};
+use synclip::client::SynclipClient;
+use synclip::clipboard::backend::{ClipboardBackend, MemoryBackend};
+use synclip::clipboard::local_clipboard::LocalClipboard;
+use synclip::clipboard::{Clipboard, VirtualClipboard};
+use synclip::direction::Direction;
+use synclip::handshake::Features;
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::util::PEER_ID_HEADER;
+use synclip::{Content, Empty, Error};
+use tokio_util::sync::CancellationToken;
+use tonic::{Code, Request};
+
+fn as_peer<T>(id: &str, message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert(PEER_ID_HEADER, id.parse().unwrap());
+    request
+}
+
+fn directed(direction: Direction) -> Features {
+    Features {
+        direction,
+        ..Features::default()
+    }
+}
+
+async fn start_server(
+    port: u16,
+    peer_directions: &[(&str, Direction)],
+    cancel_token: &CancellationToken,
+) -> SynclipServer {
+    let features = Features {
+        peer_directions: peer_directions
+            .iter()
+            .map(|(peer, direction)| (peer.to_string(), *direction))
+            .collect::<BTreeMap<_, _>>(),
+        ..Features::default()
+    };
+    SynclipServer::new(
+        common::listen(port),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        features,
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap()
+}
+
+#[test]
+fn directions_narrow_to_what_both_allow() {
+    assert_eq!(
+        Direction::Both.narrow(Direction::Send),
+        Some(Direction::Send)
+    );
+    assert_eq!(
+        Direction::Receive.narrow(Direction::Both),
+        Some(Direction::Receive)
+    );
+    assert_eq!(Direction::Send.narrow(Direction::Receive), None);
+    assert_eq!(
+        "receive-only".parse::<Direction>().unwrap(),
+        Direction::Receive
+    );
+    let error = "sideways".parse::<Direction>().unwrap_err();
+    assert!(matches!(error, Error::Config(_)), "{error:?}");
+}
+
+#[tokio::test]
+async fn receive_only_peers_cannot_set_the_clipboard() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    start_server(port, &[], &cancel_token).await;
+    let mut client = connect(port).await;
+
+    // Said so in its hello.
+    let hello = directed(Direction::Receive).hello("reader");
+    let reply = client.hello(as_peer("reader", hello)).await.unwrap();
+    assert_eq!(reply.into_inner().direction, "receive");
+    let status = client
+        .set_clipboard(as_peer("reader", Content::from("nope")))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let replaced = client
+        .set_clipboard(as_peer("other", Content::from("yes")))
+        .await
+        .unwrap()
+        .into_inner()
+        .replaced;
+    assert!(replaced);
+    cancel_token.cancel();
+}
+
+#[tokio::test]
+async fn the_server_makes_peers_receive_only_by_address() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    start_server(port, &[("127.0.0.1", Direction::Receive)], &cancel_token).await;
+    let mut client = connect(port).await;
+
+    // Even without a hello.
+    let status = client
+        .set_clipboard(as_peer("kiosk", Content::from("nope")))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let status = client
+        .upload_clipboard(as_peer(
+            "kiosk",
+            tokio_stream::iter(synclip::chunk::split(&Content::from("nope"))),
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    cancel_token.cancel();
+}
+
+#[tokio::test]
+async fn peer_ids_cannot_widen_what_the_address_allows() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    let mut features = Features::default();
+    features
+        .peer_directions
+        .insert("127.0.0.1".into(), Direction::Receive);
+    // Rules by id are never looked up, the id is whatever a client sends.
+    features
+        .peer_directions
+        .insert("desk".into(), Direction::Both);
+    SynclipServer::new(
+        common::listen(port),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        features,
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut client = connect(port).await;
+
+    let status = client
+        .set_clipboard(as_peer("desk", Content::from("nope")))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let reply = client
+        .hello(as_peer("desk", Features::default().hello("desk")))
+        .await
+        .unwrap();
+    assert_eq!(reply.into_inner().direction, "receive");
+    let status = client
+        .set_clipboard(as_peer("desk", Content::from("nope")))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    cancel_token.cancel();
+}
+
+#[tokio::test]
+async fn a_peer_cannot_claim_what_the_server_forbids() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    start_server(port, &[("127.0.0.1", Direction::Receive)], &cancel_token).await;
+    let mut client = connect(port).await;
+
+    let status = client
+        .hello(as_peer("kiosk", directed(Direction::Send).hello("kiosk")))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let reply = client
+        .hello(as_peer("kiosk", Features::default().hello("kiosk")))
+        .await
+        .unwrap();
+    assert_eq!(reply.into_inner().direction, "receive");
+    cancel_token.cancel();
+}
+
+#[tokio::test]
+async fn send_only_peers_get_no_clips() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    start_server(port, &[], &cancel_token).await;
+    let mut client = connect(port).await;
+
+    let hello = directed(Direction::Send).hello("builder");
+    client.hello(as_peer("builder", hello)).await.unwrap();
+    let mut stream = client
+        .polling_clipboard(as_peer("builder", Empty {}))
+        .await
+        .unwrap()
+        .into_inner();
+    client
+        .set_clipboard(as_peer("other", Content::from("for others")))
+        .await
+        .unwrap();
+    let message = tokio::time::timeout(Duration::from_millis(300), stream.message()).await;
+    assert!(message.is_err(), "no clip reaches a send-only peer");
+
+    let peers = client
+        .list_peers(Empty {})
+        .await
+        .unwrap()
+        .into_inner()
+        .peers;
+    let builder = peers.iter().find(|peer| peer.id == "builder").unwrap();
+    assert_eq!(builder.direction, "send");
+    cancel_token.cancel();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn receive_only_nodes_keep_their_copies() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+
+    let mut server_backend = MemoryBackend::new("initial");
+    let server = start_server(port, &[], &cancel_token).await;
+    let mut server_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(server_backend.clone()),
+        server,
+        20,
+        cancel_token.clone(),
+    );
+    let server_handle = server_clipboard.start();
+
+    let mut kiosk_backend = MemoryBackend::new("initial");
+    let kiosk = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        directed(Direction::Receive),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut kiosk_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(kiosk_backend.clone()),
+        kiosk,
+        20,
+        cancel_token.clone(),
+    )
+    .with_direction(Direction::Receive);
+    let kiosk_handle = kiosk_clipboard.start();
+
+    server_backend.set(Content::from("announcement")).unwrap();
+    wait_for(&mut kiosk_backend, &Content::from("announcement")).await;
+
+    kiosk_backend
+        .set(Content::from("typed on the kiosk"))
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(server_backend.get().unwrap(), Content::from("announcement"));
+
+    cancel_token.cancel();
+    kiosk_clipboard.shutdown().await.unwrap();
+    server_clipboard.shutdown().await.unwrap();
+    kiosk_handle.await.unwrap();
+    server_handle.await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_learn_their_direction_from_the_server() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    start_server(port, &[("127.0.0.1", Direction::Receive)], &cancel_token).await;
+
+    let client = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    for _ in 0..50 {
+        if client.features().is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(client.features().unwrap().direction, Direction::Receive);
+
+    // A copy on a client the server only lets receive stays local and the
+    // client keeps running.
+    client.remote().set(Content::from("local")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(client.state(), synclip::client::ConnectionState::Connected);
+    cancel_token.cancel();
+}
+
+#[tokio::test]
+async fn send_only_clients_are_refused_by_a_receive_only_policy() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    start_server(port, &[("127.0.0.1", Direction::Receive)], &cancel_token).await;
+    connect(port).await;
+
+    let error = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        directed(Direction::Send),
+        cancel_token.clone(),
+    )
+    .await
+    .err()
+    .unwrap();
+    assert!(matches!(error, synclip::Error::Forbidden(_)), "{error:?}");
+    assert!(format!("{error:?}").contains("not allowed"));
+    cancel_token.cancel();
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use synclip::discovery::{Discovered, Discovery};
+use synclip::PROTOCOL_VERSION;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nodes_are_found_on_loopback() {
+    let node = format!("synclip-test-{}", std::process::id());
+    let mut advertiser = Discovery::loopback().unwrap();
+    advertiser.advertise(&node, 5599, false).unwrap();
+
+    let browser = Discovery::loopback().unwrap();
+    let found = browser.find(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(found.node, node);
+    assert_eq!(found.port, 5599);
+    assert_eq!(found.protocol, PROTOCOL_VERSION);
+    assert_eq!(found.url().as_deref(), Some("http://127.0.0.1:5599"));
+
+    let all = browser.browse(Duration::from_secs(2)).await.unwrap();
+    assert!(all.iter().any(|found| found.node == node));
+
+    advertiser.shutdown().unwrap();
+    browser.shutdown().unwrap();
+}
+
+#[test]
+fn urls_follow_tls_and_address_family() {
+    let mut found = Discovered {
+        node: "box".into(),
+        addresses: vec!["::1".parse().unwrap()],
+        port: 5505,
+        protocol: PROTOCOL_VERSION,
+        tls: true,
+    };
+    assert_eq!(found.url().as_deref(), Some("https://[::1]:5505"));
+    found.addresses.push("10.0.0.2".parse().unwrap());
+    found.tls = false;
+    assert_eq!(found.url().as_deref(), Some("http://10.0.0.2:5505"));
+    found.protocol += 1;
+    assert!(!found.is_compatible());
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{free_port, wait_for};
+use synclip::chunk::CHUNK_SIZE;
+use synclip::client::SynclipClient;
+use synclip::clipboard::backend::{ClipboardBackend, MemoryBackend};
+use synclip::clipboard::local_clipboard::LocalClipboard;
+use synclip::clipboard::Clipboard;
+use synclip::content::{IMAGE_PNG, TEXT_HTML};
+use synclip::handshake::Features;
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::Content;
+use tokio_util::sync::CancellationToken;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_and_client_sync_both_ways() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+
+    let mut server_backend = MemoryBackend::new("initial");
+    let server = SynclipServer::new(
+        common::listen(port),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut server_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(server_backend.clone()),
+        server,
+        20,
+        cancel_token.clone(),
+    );
+    let server_handle = server_clipboard.start();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut client_backend = MemoryBackend::new("initial");
+    let client = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut client_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(client_backend.clone()),
+        client,
+        20,
+        cancel_token.clone(),
+    );
+    let client_handle = client_clipboard.start();
+
+    let from_client = Content::from("from client");
+    client_backend.set(from_client.clone()).unwrap();
+    wait_for(&mut server_backend, &from_client).await;
+
+    let from_server = Content::from("from server");
+    server_backend.set(from_server.clone()).unwrap();
+    wait_for(&mut client_backend, &from_server).await;
+
+    let screenshot = Content::default()
+        .with(IMAGE_PNG, vec![0x89, b'P', b'N', b'G'])
+        .with(TEXT_HTML, "<img src=\"screenshot.png\">");
+    client_backend.set(screenshot.clone()).unwrap();
+    wait_for(&mut server_backend, &screenshot).await;
+
+    // Only an image, too large for one message.
+    let mut png = vec![0x89, b'P', b'N', b'G'];
+    png.resize(CHUNK_SIZE * 2, 0x42);
+    let image = Content::default().with(IMAGE_PNG, png);
+    server_backend.set(image.clone()).unwrap();
+    wait_for(&mut client_backend, &image).await;
+
+    cancel_token.cancel();
+    client_clipboard.shutdown().await.unwrap();
+    server_clipboard.shutdown().await.unwrap();
+    client_handle.await.unwrap();
+    server_handle.await.unwrap();
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use std::time::Duration;
+
+use color_eyre::eyre::eyre;
+use common::free_port;
+use synclip::client::{self, ConnectionState, SynclipClient};
+use synclip::clipboard::backend::ClipboardBackend;
+use synclip::clipboard::local_clipboard::LocalClipboard;
+use synclip::clipboard::remote_clipboard::RemoteClipboard;
+use synclip::error::TransportError;
+use synclip::handshake::Features;
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::{Content, Error};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tonic::{Code, Status};
+
+/// A clipboard that is never there.
+struct Unplugged;
+
+impl ClipboardBackend for Unplugged {
+    fn get(&mut self) -> color_eyre::Result<Content> {
+        Err(eyre!("No display"))
+    }
+
+    fn set(&mut self, _: Content) -> color_eyre::Result<()> {
+        Err(eyre!("No display"))
+    }
+}
+
+#[test]
+fn errors_travel_as_matching_statuses() {
+    let cases = [
+        (Error::Auth("bad token".into()), Code::Unauthenticated),
+        (
+            Error::PayloadTooLarge("too big".into()),
+            Code::ResourceExhausted,
+        ),
+        (
+            Error::Protocol("protocol 2".into()),
+            Code::FailedPrecondition,
+        ),
+        (Error::Corrupt("cut short".into()), Code::DataLoss),
+        (
+            Error::Forbidden("receive-only".into()),
+            Code::PermissionDenied,
+        ),
+        (Error::NotFound("no entry".into()), Code::NotFound),
+    ];
+    for (error, code) in cases {
+        let message = error.to_string();
+        let status = Status::from(error);
+        assert_eq!(status.code(), code);
+        assert!(message.contains(status.message()), "{message}");
+        let back = Error::from(status);
+        assert_eq!(Status::from(back).code(), code);
+    }
+    assert_eq!(Status::from(Error::Shutdown).code(), Code::Unavailable);
+    assert!(matches!(
+        Error::from(Status::unavailable("down")),
+        Error::Transport {
+            source: TransportError::Status(_),
+            ..
+        }
+    ));
+    assert!(matches!(
+        Error::from(Status::internal("oops")),
+        Error::Remote(_)
+    ));
+    let error = Error::NotFound("No history entry 3".into()).context("Apply");
+    assert_eq!(error.to_string(), "Apply: No history entry 3");
+}
+
+#[tokio::test]
+async fn local_clipboard_failures_are_backend_errors() {
+    let local = LocalClipboard::with_backend(Unplugged);
+    let error = local.get().await.unwrap_err();
+    assert!(matches!(error, Error::Backend { .. }), "{error:?}");
+    assert!(error.to_string().contains("No display"));
+}
+
+#[tokio::test]
+async fn a_closed_remote_is_shut_down() {
+    let (sender, _) = watch::channel(Content::default());
+    let (closed, receiver) = watch::channel(Content::default());
+    drop(closed);
+    let remote = RemoteClipboard::new(sender, receiver);
+    assert!(matches!(remote.get_new().await, Err(Error::Shutdown)));
+}
+
+#[tokio::test]
+async fn unreachable_servers_are_transport_errors() {
+    let address = format!("http://127.0.0.1:{}", free_port());
+    let error = client::connect(&address, None, None).await.err().unwrap();
+    assert!(
+        matches!(
+            error,
+            Error::Transport {
+                source: TransportError::Connect(_),
+                ..
+            }
+        ),
+        "{error:?}"
+    );
+    assert!(error.to_string().contains(&address));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refused_tokens_are_auth_errors() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    SynclipServer::new(
+        common::listen(port),
+        "initial".into(),
+        None,
+        Some("right".into()),
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+
+    let client_token = CancellationToken::new();
+    let client = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        "initial".into(),
+        None,
+        Some("wrong".into()),
+        History::default(),
+        Features::default(),
+        client_token.clone(),
+    )
+    .await
+    .unwrap();
+    let missing = client.apply_history(42).await.unwrap_err();
+    assert!(matches!(missing, Error::NotFound(_)), "{missing:?}");
+
+    let mut state = client.watch_state();
+    tokio::time::timeout(
+        Duration::from_secs(2),
+        state.wait_for(|state| *state == ConnectionState::Stopped),
+    )
+    .await
+    .expect("a refused token stops the client")
+    .unwrap();
+    let error = client.shutdown().await.unwrap_err();
+    assert!(matches!(error, Error::Auth(_)), "{error:?}");
+    cancel_token.cancel();
+}
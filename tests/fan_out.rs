@@ -0,0 +1,99 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{connect, free_port};
+use synclip::handshake::Features;
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::util::PEER_ID_HEADER;
+use synclip::{Content, Empty};
+use tokio_util::sync::CancellationToken;
+use tonic::Request;
+
+fn as_peer<T>(id: &str, message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert(PEER_ID_HEADER, id.parse().unwrap());
+    request
+}
+
+#[tokio::test]
+async fn changes_fan_out_to_other_peers_only() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    let server = SynclipServer::new(
+        common::listen(port),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut alice = connect(port).await;
+    let mut bob = connect(port).await;
+
+    let mut alice_stream = alice
+        .polling_clipboard(as_peer("alice", Empty {}))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut bob_stream = bob
+        .polling_clipboard(as_peer("bob", Empty {}))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(
+        alice_stream.message().await.unwrap().unwrap().text,
+        "initial"
+    );
+    assert_eq!(bob_stream.message().await.unwrap().unwrap().text, "initial");
+
+    let content = Content::from("from alice");
+    alice
+        .set_clipboard(as_peer("alice", content))
+        .await
+        .unwrap();
+    assert_eq!(
+        bob_stream.message().await.unwrap().unwrap().text,
+        "from alice"
+    );
+    let echo = tokio::time::timeout(Duration::from_millis(200), alice_stream.message()).await;
+    assert!(echo.is_err(), "alice received her own clip back");
+
+    let content = Content::from("from bob");
+    bob.set_clipboard(as_peer("bob", content)).await.unwrap();
+    assert_eq!(
+        alice_stream.message().await.unwrap().unwrap().text,
+        "from bob"
+    );
+
+    let peers = alice
+        .list_peers(as_peer("alice", Empty {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .peers;
+    let ids = peers
+        .iter()
+        .map(|peer| peer.id.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, ["alice", "bob"]);
+    assert_eq!(server.peers().len(), 2);
+
+    drop(bob_stream);
+    for _ in 0..50 {
+        if server.peers().len() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(server.peers()[0].id, "alice");
+
+    cancel_token.cancel();
+    server.shutdown().await.unwrap();
+}
@@ -0,0 +1,336 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::{connect, free_port};
+use synclip::client::SynclipClient;
+use synclip::content::{IMAGE_PNG, TEXT_HTML, TEXT_PLAIN};
+use synclip::handshake::{Features, DEFAULT_MAX_PAYLOAD};
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::synclip_server::{self, Synclip};
+use synclip::util::PEER_ID_HEADER;
+use synclip::{
+    Chunk, Content, Download, Empty, Greeting, HistoryEntries, HistoryEntry, HistoryEntryId,
+    HistoryQuery, Peers, Replaced, PROTOCOL_VERSION,
+};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tokio_util::sync::CancellationToken;
+use tonic::{Code, Request, Response, Status, Streaming};
+
+fn features(content_types: &[&str], compression: &[&str], max_payload: u64) -> Features {
+    Features {
+        content_types: content_types.iter().map(|t| t.to_string()).collect(),
+        compression: compression.iter().map(|c| c.to_string()).collect(),
+        max_payload,
+        ..Features::default()
+    }
+}
+
+#[test]
+fn narrowing_keeps_what_both_sides_support() {
+    let ours = features(&[TEXT_PLAIN, TEXT_HTML, IMAGE_PNG], &["gzip"], 4096);
+    let theirs = features(&[TEXT_HTML, TEXT_PLAIN], &[], 1024).hello("them");
+
+    assert_eq!(
+        ours.narrow(&theirs),
+        features(&[TEXT_PLAIN, TEXT_HTML], &[], 1024)
+    );
+}
+
+#[test]
+fn unspecified_max_payload_keeps_ours() {
+    let ours = Features::default();
+    let mut theirs = ours.hello("them");
+    theirs.max_payload = 0;
+
+    assert_eq!(ours.narrow(&theirs).max_payload, DEFAULT_MAX_PAYLOAD);
+}
+
+#[test]
+fn filter_drops_unsupported_representations() {
+    let content = Content::from_text("text")
+        .with(TEXT_HTML, "<b>text</b>")
+        .with(IMAGE_PNG, vec![0x89, 0x50]);
+
+    let filtered = features(&[TEXT_PLAIN, TEXT_HTML], &[], 1024).filter(content);
+
+    assert_eq!(filtered.text, "text");
+    assert_eq!(
+        filtered.mime_types().collect::<Vec<_>>(),
+        [TEXT_PLAIN, TEXT_HTML]
+    );
+}
+
+async fn start_server(port: u16, cancel_token: &CancellationToken) -> SynclipServer {
+    SynclipServer::new(
+        common::listen(port),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn server_refuses_other_protocol_versions() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    start_server(port, &cancel_token).await;
+    let mut client = connect(port).await;
+
+    let mut hello = Features::default().hello("future");
+    hello.protocol_version = PROTOCOL_VERSION + 1;
+    let status = client.hello(hello).await.unwrap_err();
+
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert!(status.message().contains("protocol"));
+    cancel_token.cancel();
+}
+
+#[tokio::test]
+async fn server_remembers_what_each_peer_negotiated() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    let server = start_server(port, &cancel_token).await;
+    let mut client = connect(port).await;
+
+    let mut request = Request::new(features(&[TEXT_PLAIN], &[], 1024).hello("text-only"));
+    request
+        .metadata_mut()
+        .insert(PEER_ID_HEADER, "text-only".parse().unwrap());
+    let response = client.hello(request).await.unwrap().into_inner();
+    let _stream = client
+        .polling_clipboard(as_peer("text-only", Empty {}))
+        .await
+        .unwrap();
+
+    assert_eq!(response.protocol_version, PROTOCOL_VERSION);
+    assert_eq!(
+        server.negotiated("text-only", address_of(&server, "text-only")),
+        Some(features(&[TEXT_PLAIN], &[], 1024))
+    );
+    cancel_token.cancel();
+}
+
+fn as_peer<T>(id: &str, message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert(PEER_ID_HEADER, id.parse().unwrap());
+    request
+}
+
+/// Where a connected peer's stream comes from.
+fn address_of(server: &SynclipServer, id: &str) -> Option<SocketAddr> {
+    server
+        .peers()
+        .into_iter()
+        .find(|peer| peer.id == id)
+        .and_then(|peer| peer.address)
+}
+
+#[tokio::test]
+async fn a_hello_under_another_peers_id_changes_nothing_for_it() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    let server = start_server(port, &cancel_token).await;
+    let mut peer = connect(port).await;
+    let mut impostor = connect(port).await;
+    let rich = features(&[TEXT_PLAIN, TEXT_HTML], &[], 4096);
+
+    peer.hello(as_peer("peer", rich.hello("peer")))
+        .await
+        .unwrap();
+    let _stream = peer
+        .polling_clipboard(as_peer("peer", Empty {}))
+        .await
+        .unwrap();
+    let address = address_of(&server, "peer");
+    impostor
+        .hello(as_peer(
+            "peer",
+            features(&[TEXT_PLAIN], &[], 1).hello("peer"),
+        ))
+        .await
+        .unwrap();
+
+    assert_eq!(server.negotiated("peer", address), Some(rich));
+    cancel_token.cancel();
+}
+
+#[tokio::test]
+async fn server_forgets_what_was_negotiated_once_the_stream_ends() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    let server = start_server(port, &cancel_token).await;
+    let mut client = connect(port).await;
+    let text_only = features(&[TEXT_PLAIN], &[], 1024);
+
+    client
+        .hello(as_peer("peer", text_only.hello("peer")))
+        .await
+        .unwrap();
+    let first = client
+        .polling_clipboard(as_peer("peer", Empty {}))
+        .await
+        .unwrap()
+        .into_inner();
+    let address = address_of(&server, "peer");
+    // Said hello again before the first stream ended, as on reconnect.
+    client
+        .hello(as_peer("peer", text_only.hello("peer")))
+        .await
+        .unwrap();
+    let second = client
+        .polling_clipboard(as_peer("peer", Empty {}))
+        .await
+        .unwrap()
+        .into_inner();
+    drop(first);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(server.negotiated("peer", address), Some(text_only));
+
+    drop(second);
+    for _ in 0..50 {
+        if server.negotiated("peer", address).is_none() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(server.negotiated("peer", address), None);
+    cancel_token.cancel();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_narrows_features_with_the_server() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    let server = start_server(port, &cancel_token).await;
+
+    let client = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        features(&[TEXT_PLAIN, TEXT_HTML], &[], DEFAULT_MAX_PAYLOAD),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+
+    let expected = features(&[TEXT_PLAIN, TEXT_HTML], &[], DEFAULT_MAX_PAYLOAD);
+    for _ in 0..100 {
+        // Listed once its stream is open, shortly after the handshake.
+        if client.features().is_some() && address_of(&server, client.id()).is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(client.features(), Some(expected.clone()));
+    let address = address_of(&server, client.id());
+    assert_eq!(server.negotiated(client.id(), address), Some(expected));
+
+    cancel_token.cancel();
+    client.shutdown().await.unwrap();
+}
+
+/// A server from a release that speaks another protocol.
+struct FutureServer;
+
+#[tonic::async_trait]
+impl Synclip for FutureServer {
+    type PollingClipboardStream = tokio_stream::Empty<Result<Content, Status>>;
+    type DownloadClipboardStream = tokio_stream::Empty<Result<Chunk, Status>>;
+
+    async fn hello(&self, _: Request<Greeting>) -> Result<Response<Greeting>, Status> {
+        let mut hello = Features::default().hello("future");
+        hello.protocol_version = PROTOCOL_VERSION + 1;
+        Ok(Response::new(hello))
+    }
+
+    async fn polling_clipboard(
+        &self,
+        _: Request<Empty>,
+    ) -> Result<Response<Self::PollingClipboardStream>, Status> {
+        Ok(Response::new(tokio_stream::empty()))
+    }
+
+    async fn set_clipboard(&self, _: Request<Content>) -> Result<Response<Replaced>, Status> {
+        Ok(Response::new(Replaced { replaced: true }))
+    }
+
+    async fn upload_clipboard(
+        &self,
+        _: Request<Streaming<Chunk>>,
+    ) -> Result<Response<Replaced>, Status> {
+        Ok(Response::new(Replaced { replaced: true }))
+    }
+
+    async fn download_clipboard(
+        &self,
+        _: Request<Download>,
+    ) -> Result<Response<Self::DownloadClipboardStream>, Status> {
+        Ok(Response::new(tokio_stream::empty()))
+    }
+
+    async fn list_peers(&self, _: Request<Empty>) -> Result<Response<Peers>, Status> {
+        Ok(Response::new(Peers::default()))
+    }
+
+    async fn list_history(
+        &self,
+        _: Request<HistoryQuery>,
+    ) -> Result<Response<HistoryEntries>, Status> {
+        Ok(Response::new(HistoryEntries::default()))
+    }
+
+    async fn get_history_entry(
+        &self,
+        _: Request<HistoryEntryId>,
+    ) -> Result<Response<HistoryEntry>, Status> {
+        Err(Status::not_found("No history"))
+    }
+
+    async fn apply_history_entry(
+        &self,
+        _: Request<HistoryEntryId>,
+    ) -> Result<Response<Replaced>, Status> {
+        Err(Status::not_found("No history"))
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_refuses_a_server_with_another_protocol() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(synclip_server::SynclipServer::new(FutureServer))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    let result = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        CancellationToken::new(),
+    )
+    .await;
+
+    let error = result.err().expect("an incompatible server is refused");
+    assert!(matches!(error, synclip::Error::Protocol(_)), "{error:?}");
+    let message = format!("{error:?}");
+    assert!(message.contains("not compatible"), "{message}");
+    assert!(message.contains("protocol"), "{message}");
+}
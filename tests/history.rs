@@ -0,0 +1,145 @@
+mod common;
+
+use std::fs;
+
+use common::{connect, free_port};
+use synclip::clipboard::VirtualClipboard;
+use synclip::content::{IMAGE_PNG, SUMMARY_CHARS};
+use synclip::handshake::Features;
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::{Content, Empty, HistoryEntryId, HistoryQuery};
+use tokio_util::sync::CancellationToken;
+use tonic::Code;
+
+fn texts(history: &History) -> Vec<String> {
+    history
+        .list(0)
+        .into_iter()
+        .map(|entry| history.get(entry.id).unwrap().content.unwrap().text)
+        .collect()
+}
+
+#[test]
+fn history_is_bounded_by_count_and_bytes() {
+    let history = History::new(3, 1024);
+    for text in ["one", "two", "two", "", "three", "four"] {
+        history.record(None, &text.into());
+    }
+    assert_eq!(texts(&history), ["four", "three", "two"]);
+    assert!(history.list(1)[0].content.is_none());
+    assert_eq!(history.list(1)[0].summary, "\"four\" text/plain(4 bytes)");
+
+    let history = History::new(100, 64);
+    history.record(None, &"a".repeat(40).into());
+    history.record(Some("alice"), &"b".repeat(40).into());
+    assert_eq!(history.len(), 1);
+    assert_eq!(history.list(0)[0].origin, "alice");
+    assert!(history.record(None, &"c".repeat(100).into()).is_none());
+    assert!(history.bytes() <= 64);
+}
+
+#[test]
+fn summaries_hold_a_glimpse_of_the_clip() {
+    let history = History::new(10, 1 << 20);
+    let secret = format!("{}\nsecond line", "x".repeat(500));
+    let content = Content::from(secret.as_str()).with(IMAGE_PNG, vec![0; 16]);
+    history.record(None, &content);
+
+    let summary = history.list(1)[0].summary.clone();
+    assert!(summary.starts_with(&format!("\"{}…\"", "x".repeat(SUMMARY_CHARS))));
+    assert!(!summary.contains("second line"), "{summary}");
+    assert!(summary.ends_with("text/plain(512 bytes) +image/png(16 bytes)"));
+}
+
+#[test]
+fn history_survives_restarts() {
+    let dir = std::env::temp_dir().join(format!("synclip-history-{}", std::process::id()));
+    let path = dir.join("history.bin");
+    let history = History::persistent(10, 1024, &path).unwrap();
+    history.record(None, &"first".into());
+    let second = history.record(None, &"second".into()).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600, "only the user reads the history");
+    }
+
+    let history = History::persistent(10, 1024, &path).unwrap();
+    assert_eq!(texts(&history), ["second", "first"]);
+    let third = history.record(None, &"third".into()).unwrap();
+    assert!(third > second);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_lists_fetches_and_reapplies_clips() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    let server = SynclipServer::new(
+        common::listen(port),
+        "initial".into(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut alice = connect(port).await;
+    let mut bob = connect(port).await;
+    let mut bob_stream = bob.polling_clipboard(Empty {}).await.unwrap().into_inner();
+    assert_eq!(bob_stream.message().await.unwrap().unwrap().text, "initial");
+
+    alice.set_clipboard(Content::from("snippet")).await.unwrap();
+    alice.set_clipboard(Content::from("oops")).await.unwrap();
+    let entries = alice
+        .list_history(HistoryQuery { limit: 0 })
+        .await
+        .unwrap()
+        .into_inner()
+        .entries;
+    let summaries: Vec<_> = entries.iter().map(|entry| entry.summary.as_str()).collect();
+    assert_eq!(
+        summaries,
+        [
+            "\"oops\" text/plain(4 bytes)",
+            "\"snippet\" text/plain(7 bytes)",
+            "\"initial\" text/plain(7 bytes)"
+        ]
+    );
+
+    let snippet = entries[1].id;
+    let entry = alice
+        .get_history_entry(HistoryEntryId { id: snippet })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(entry.content.unwrap().text, "snippet");
+
+    let replaced = alice
+        .apply_history_entry(HistoryEntryId { id: snippet })
+        .await
+        .unwrap()
+        .into_inner()
+        .replaced;
+    assert!(replaced);
+    loop {
+        let content = bob_stream.message().await.unwrap().unwrap();
+        if content.text == "snippet" {
+            break;
+        }
+    }
+    assert_eq!(server.remote().current().await.unwrap().text, "snippet");
+
+    let missing = alice
+        .get_history_entry(HistoryEntryId { id: 999 })
+        .await
+        .unwrap_err();
+    assert_eq!(missing.code(), Code::NotFound);
+
+    cancel_token.cancel();
+    server.shutdown().await.unwrap();
+}
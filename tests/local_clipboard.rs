@@ -0,0 +1,121 @@
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use synclip::clipboard::backend::{ClipboardBackend, MemoryBackend, Transient};
+use synclip::clipboard::local_clipboard::LocalClipboard;
+use synclip::content::TEXT_HTML;
+use synclip::{Content, Error};
+use tokio::sync::watch;
+
+/// A memory clipboard that fails or loses writes as told.
+#[derive(Clone, Default)]
+struct BrokenBackend {
+    memory: MemoryBackend,
+    reads_empty: bool,
+    fails_writes: bool,
+    loses_writes: bool,
+}
+
+impl ClipboardBackend for BrokenBackend {
+    fn get(&mut self) -> Result<Content> {
+        match self.memory.get()? {
+            content if self.reads_empty && content.text.is_empty() => Err(Transient::Empty.into()),
+            content => Ok(content),
+        }
+    }
+
+    fn set(&mut self, content: Content) -> Result<()> {
+        if self.fails_writes {
+            return Err(eyre!("The display went away"));
+        }
+        if !self.loses_writes {
+            self.memory.set(content)?;
+        }
+        Ok(())
+    }
+
+    fn watch(&mut self) -> Option<watch::Receiver<()>> {
+        self.memory.watch()
+    }
+}
+
+#[tokio::test]
+async fn equal_content_is_not_written() {
+    let mut backend = MemoryBackend::new(Content::from("same").with(TEXT_HTML, "<b>same</b>"));
+    let writes = backend.watch().unwrap();
+    let local = LocalClipboard::with_backend(backend.clone());
+
+    let replaced = local
+        .set(Content::from("same").with(TEXT_HTML, "<b>same</b>"))
+        .await
+        .unwrap();
+    assert!(!replaced);
+    assert!(!writes.has_changed().unwrap());
+}
+
+#[tokio::test]
+async fn different_content_is_written() {
+    let mut backend = MemoryBackend::new("before");
+    let writes = backend.watch().unwrap();
+    let local = LocalClipboard::with_backend(backend.clone());
+
+    assert!(local.set(Content::from("after")).await.unwrap());
+    assert!(writes.has_changed().unwrap());
+    assert_eq!(backend.get().unwrap(), Content::from("after"));
+
+    // Another representation of the same text is different content too.
+    let html = Content::from("after").with(TEXT_HTML, "<i>after</i>");
+    assert!(local.set(html.clone()).await.unwrap());
+    assert_eq!(backend.get().unwrap(), html);
+}
+
+#[tokio::test]
+async fn empty_content_is_written_and_read_back() {
+    let mut backend = MemoryBackend::new("before");
+    let local = LocalClipboard::with_backend(backend.clone());
+    assert!(local.set(Content::default()).await.unwrap());
+    assert!(backend.get().unwrap().is_empty());
+    assert!(!local.set(Content::default()).await.unwrap());
+
+    // A clipboard that reads empty as an error, like the system one, is
+    // still written and verified.
+    let backend = BrokenBackend {
+        memory: MemoryBackend::new("before"),
+        reads_empty: true,
+        ..BrokenBackend::default()
+    };
+    let local = LocalClipboard::with_backend(backend.clone());
+    assert!(local.set(Content::default()).await.unwrap());
+    assert!(local.set(Content::from("after")).await.unwrap());
+    assert_eq!(
+        backend.memory.clone().get().unwrap(),
+        Content::from("after")
+    );
+}
+
+#[tokio::test]
+async fn failed_writes_are_errors() {
+    let backend = BrokenBackend {
+        memory: MemoryBackend::new("before"),
+        fails_writes: true,
+        ..BrokenBackend::default()
+    };
+    let local = LocalClipboard::with_backend(backend.clone());
+    let error = local.set(Content::from("after")).await.err().unwrap();
+    assert!(matches!(error, Error::Backend { .. }));
+    assert!(error.to_string().contains("The display went away"));
+    // Nothing to write, nothing to fail.
+    assert!(!local.set(Content::from("before")).await.unwrap());
+
+    let backend = BrokenBackend {
+        memory: MemoryBackend::new("before"),
+        loses_writes: true,
+        ..BrokenBackend::default()
+    };
+    let local = LocalClipboard::with_backend(backend.clone());
+    let error = local.set(Content::from("after")).await.err().unwrap();
+    assert!(error.to_string().contains("did not keep"));
+    assert_eq!(
+        backend.memory.clone().get().unwrap(),
+        Content::from("before")
+    );
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use common::{free_port, listen, wait_for};
+use synclip::clipboard::backend::{ClipboardBackend, MemoryBackend, Transient};
+use synclip::node::SynclipNode;
+use synclip::{Content, Error};
+use tokio::sync::watch;
+
+/// A memory clipboard whose next reads fail as queued.
+#[derive(Clone, Default)]
+struct FlakyBackend {
+    memory: MemoryBackend,
+    failures: Arc<Mutex<VecDeque<Option<Transient>>>>,
+}
+
+impl FlakyBackend {
+    /// Fail the next read, for good if `transient` is `None`.
+    fn fail(&self, transient: Option<Transient>) {
+        self.failures.lock().unwrap().push_back(transient);
+    }
+}
+
+impl ClipboardBackend for FlakyBackend {
+    fn get(&mut self) -> Result<Content> {
+        match self.failures.lock().unwrap().pop_front() {
+            Some(Some(transient)) => Err(transient.into()),
+            Some(None) => Err(eyre!("The display went away")),
+            None => self.memory.get(),
+        }
+    }
+
+    fn set(&mut self, content: Content) -> Result<()> {
+        self.memory.set(content)
+    }
+
+    fn watch(&mut self) -> Option<watch::Receiver<()>> {
+        self.memory.watch()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn transient_errors_are_retried_and_counted() {
+    let port = free_port();
+    let mut server_backend = MemoryBackend::new("initial");
+    let server = SynclipNode::builder()
+        .server(listen(port))
+        .backend(server_backend.clone())
+        .poll_interval(20)
+        .start()
+        .await
+        .unwrap();
+
+    let backend = FlakyBackend::default();
+    // Starting on an empty clipboard is no error either.
+    backend.fail(Some(Transient::Empty));
+    let client = SynclipNode::builder()
+        .client(format!("http://127.0.0.1:{port}"))
+        .backend(backend.clone())
+        .poll_interval(20)
+        .start()
+        .await
+        .unwrap();
+
+    // Settle first, applying the server's clip reads the clipboard too.
+    wait_for(&mut backend.memory.clone(), &Content::from("initial")).await;
+
+    backend.fail(Some(Transient::Empty));
+    backend.fail(Some(Transient::UnsupportedFormat));
+    backend.fail(Some(Transient::OwnerBusy));
+    backend.memory.clone().set(Content::from("copied")).unwrap();
+    wait_for(&mut server_backend, &Content::from("copied")).await;
+
+    let metrics = client.metrics();
+    assert_eq!(metrics.transient(Transient::Empty), 1);
+    assert_eq!(metrics.transient(Transient::UnsupportedFormat), 1);
+    assert_eq!(metrics.transient(Transient::OwnerBusy), 1);
+    assert_eq!(metrics.transient_errors(), 3);
+    assert_eq!(metrics.fatal_errors(), 0);
+
+    client.shutdown().await.unwrap();
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fatal_errors_stop_the_node() {
+    let backend = FlakyBackend::default();
+    let node = SynclipNode::builder()
+        .server(listen(free_port()))
+        .backend(backend.clone())
+        .poll_interval(20)
+        .start()
+        .await
+        .unwrap();
+
+    backend.fail(None);
+    backend.memory.clone().set(Content::from("copied")).unwrap();
+    let stopped = tokio::time::timeout(Duration::from_secs(2), node.stopped())
+        .await
+        .expect("a fatal error stops the node");
+    assert!(matches!(stopped, Err(Error::Backend { .. })), "{stopped:?}");
+    assert_eq!(node.metrics().fatal_errors(), 1);
+    assert_eq!(node.metrics().transient_errors(), 0);
+    let error = node.shutdown().await.unwrap_err();
+    assert!(
+        error.to_string().contains("The display went away"),
+        "{error}"
+    );
+}
+
+#[test]
+fn transient_errors_survive_context() {
+    let error =
+        Error::backend(eyre!(Transient::OwnerBusy).wrap_err("Poll")).context("Read the clipboard");
+    assert_eq!(error.transient(), Some(Transient::OwnerBusy));
+    let error = Error::backend(Transient::Empty.into()).context("Read the clipboard");
+    assert_eq!(error.transient(), Some(Transient::Empty));
+    assert_eq!(Error::backend(eyre!("gone")).transient(), None);
+    assert_eq!(Error::Auth("denied".into()).transient(), None);
+}
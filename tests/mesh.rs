@@ -0,0 +1,121 @@
+mod common;
+
+use std::time::Duration;
+
+use common::free_port;
+use synclip::clipboard::VirtualClipboard;
+use synclip::handshake::Features;
+use synclip::history::History;
+use synclip::mesh::{MeshNode, PeerAddress};
+use synclip::Content;
+use tokio_util::sync::CancellationToken;
+
+async fn node(port: u16, peers: &[u16], cancel_token: &CancellationToken) -> MeshNode {
+    let peers = peers
+        .iter()
+        .map(|peer| PeerAddress::new(format!("http://127.0.0.1:{peer}"), None))
+        .collect();
+    MeshNode::new(
+        common::listen(port),
+        None,
+        peers,
+        Content::default(),
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap()
+}
+
+/// What the clipboard of a node was last given.
+async fn wait_for_clip(node: &MeshNode, expected: &str) {
+    for _ in 0..100 {
+        if node.remote().current().await.unwrap().text == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("node {} never got {expected:?}", node.id());
+}
+
+async fn copy(node: &MeshNode, text: &str) {
+    node.remote().set(text.into()).await.unwrap();
+}
+
+async fn wait_connected(nodes: &[&MeshNode]) {
+    for node in nodes {
+        for client in node.clients() {
+            let mut state = client.watch_state();
+            tokio::time::timeout(
+                Duration::from_secs(5),
+                state.wait_for(|state| state.to_string() == "connected"),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clips_cross_a_chain_both_ways() {
+    let cancel_token = CancellationToken::new();
+    let (port_a, port_b, port_c) = (free_port(), free_port(), free_port());
+    let a = node(port_a, &[], &cancel_token).await;
+    let b = node(port_b, &[port_a], &cancel_token).await;
+    let c = node(port_c, &[port_b], &cancel_token).await;
+    wait_connected(&[&a, &b, &c]).await;
+
+    copy(&c, "from c").await;
+    wait_for_clip(&a, "from c").await;
+    wait_for_clip(&b, "from c").await;
+
+    copy(&a, "from a").await;
+    wait_for_clip(&c, "from a").await;
+    wait_for_clip(&b, "from a").await;
+    let stamp = a.current().stamp.unwrap();
+    assert_eq!(stamp.origin, a.id());
+    assert_eq!(c.current().stamp, Some(stamp));
+
+    cancel_token.cancel();
+    for node in [a, b, c] {
+        node.shutdown().await.unwrap();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_copies_settle_without_ping_pong() {
+    let cancel_token = CancellationToken::new();
+    let (port_a, port_b, port_c) = (free_port(), free_port(), free_port());
+    // Every node both serves and connects, a ring with two paths between any pair.
+    let a = node(port_a, &[port_c], &cancel_token).await;
+    let b = node(port_b, &[port_a], &cancel_token).await;
+    let c = node(port_c, &[port_b], &cancel_token).await;
+    wait_connected(&[&a, &b, &c]).await;
+
+    tokio::join!(copy(&a, "from a"), copy(&b, "from b"));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let winner = a.current();
+    assert!(winner.stamp.is_some());
+    assert_eq!(b.current(), winner);
+    assert_eq!(c.current(), winner);
+    // Only the nodes that lost apply the winner, its origin already has it.
+    for node in [&a, &b, &c] {
+        if Some(node.id()) != winner.stamp.as_ref().map(|stamp| stamp.origin.as_str()) {
+            wait_for_clip(node, &winner.text).await;
+        }
+    }
+
+    // A settled mesh stays quiet.
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    for node in [&a, &b, &c] {
+        assert_eq!(node.current(), winner);
+    }
+
+    cancel_token.cancel();
+    for node in [a, b, c] {
+        node.shutdown().await.unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{free_port, listen, wait_for};
+use synclip::clipboard::backend::{ClipboardBackend, MemoryBackend};
+use synclip::direction::Direction;
+use synclip::node::SynclipNode;
+use synclip::{Content, Error};
+use tokio_util::sync::CancellationToken;
+
+// A single threaded runtime, as an embedding app may have: nothing of the
+// node may block it.
+#[tokio::test]
+async fn nodes_run_on_the_callers_runtime() {
+    let port = free_port();
+    let mut server_backend = MemoryBackend::new("initial");
+    let server = SynclipNode::builder()
+        .server(listen(port))
+        .backend(server_backend.clone())
+        .poll_interval(20)
+        .start()
+        .await
+        .unwrap();
+    assert_eq!(server.role(), "server");
+
+    let mut client_backend = MemoryBackend::new("initial");
+    let client = SynclipNode::builder()
+        .client(format!("http://127.0.0.1:{port}"))
+        .backend(client_backend.clone())
+        .poll_interval(20)
+        .start()
+        .await
+        .unwrap();
+
+    client_backend
+        .set(Content::from("from the client"))
+        .unwrap();
+    wait_for(&mut server_backend, &Content::from("from the client")).await;
+    server_backend
+        .set(Content::from("from the server"))
+        .unwrap();
+    wait_for(&mut client_backend, &Content::from("from the server")).await;
+    assert_eq!(server.peers().len(), 1);
+
+    server.pause();
+    client_backend.set(Content::from("while paused")).unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(
+        server_backend.get().unwrap(),
+        Content::from("from the server")
+    );
+    server.resume();
+
+    client.shutdown().await.unwrap();
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn nodes_stop_with_the_callers_token() {
+    let cancel_token = CancellationToken::new();
+    let node = SynclipNode::builder()
+        .server(listen(free_port()))
+        .backend(MemoryBackend::new("initial"))
+        .direction(Direction::Receive)
+        .cancel_token(cancel_token.clone())
+        .start()
+        .await
+        .unwrap();
+
+    cancel_token.cancel();
+    tokio::time::timeout(Duration::from_secs(2), node.stopped())
+        .await
+        .expect("the node stops with the token")
+        .unwrap();
+    node.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn misconfigured_nodes_are_errors() {
+    let error = SynclipNode::builder()
+        .backend(MemoryBackend::default())
+        .start()
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(error, Error::Config(_)), "{error:?}");
+    assert!(error.to_string().contains("No role"));
+
+    let error = SynclipNode::builder()
+        .server(listen(free_port()))
+        .backend(MemoryBackend::default())
+        .poll_interval(0)
+        .start()
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(error, Error::Config(_)), "{error:?}");
+    assert!(error.to_string().contains("poll interval"));
+}
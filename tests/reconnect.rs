@@ -0,0 +1,131 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{free_port, wait_for};
+use synclip::client::{Backoff, ConnectionState, SynclipClient};
+use synclip::clipboard::backend::{ClipboardBackend, MemoryBackend};
+use synclip::clipboard::local_clipboard::LocalClipboard;
+use synclip::clipboard::Clipboard;
+use synclip::handshake::Features;
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::Content;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+async fn wait_state(
+    state: &mut watch::Receiver<ConnectionState>,
+    matches: impl Fn(&ConnectionState) -> bool,
+) {
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        state.wait_for(|state| matches(state)),
+    )
+    .await
+    .expect("connection state never matched")
+    .unwrap();
+}
+
+async fn start_server(
+    port: u16,
+    backend: &MemoryBackend,
+) -> (Clipboard<SynclipServer, MemoryBackend>, CancellationToken) {
+    let cancel_token = CancellationToken::new();
+    let server = SynclipServer::new(
+        common::listen(port),
+        Content::default(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut clipboard = Clipboard::new(
+        LocalClipboard::with_backend(backend.clone()),
+        server,
+        20,
+        cancel_token.clone(),
+    );
+    clipboard.start();
+    (clipboard, cancel_token)
+}
+
+#[test]
+fn backoff_grows_with_jitter_and_resets() {
+    let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(350));
+    let delays = (0..4).map(|_| backoff.next_delay()).collect::<Vec<_>>();
+    assert!((50..=100).contains(&delays[0].as_millis()));
+    assert!((100..=200).contains(&delays[1].as_millis()));
+    assert!((175..=350).contains(&delays[2].as_millis()));
+    assert!((175..=350).contains(&delays[3].as_millis()));
+    assert_eq!(backoff.attempt(), 4);
+
+    backoff.reset();
+    assert!(backoff.next_delay() <= Duration::from_millis(100));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_survives_server_restarts() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+
+    let mut client_backend = MemoryBackend::default();
+    let client = SynclipClient::new(
+        format!("http://127.0.0.1:{port}"),
+        Content::default(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut state = client.watch_state();
+    let mut client_clipboard = Clipboard::new(
+        LocalClipboard::with_backend(client_backend.clone()),
+        client,
+        20,
+        cancel_token.clone(),
+    );
+    let client_handle = client_clipboard.start();
+    wait_state(&mut state, |state| {
+        matches!(state, ConnectionState::Reconnecting { .. })
+    })
+    .await;
+
+    let mut first_backend = MemoryBackend::default();
+    let (first, first_token) = start_server(port, &first_backend).await;
+    wait_state(&mut state, |state| state == &ConnectionState::Connected).await;
+    let online = Content::from("online");
+    client_backend.set(online.clone()).unwrap();
+    wait_for(&mut first_backend, &online).await;
+
+    first_token.cancel();
+    first.shutdown().await.unwrap();
+    wait_state(&mut state, |state| {
+        matches!(state, ConnectionState::Reconnecting { .. })
+    })
+    .await;
+    let offline = Content::from("copied while offline");
+    client_backend.set(offline.clone()).unwrap();
+
+    let mut second_backend = MemoryBackend::default();
+    let (second, second_token) = start_server(port, &second_backend).await;
+    wait_state(&mut state, |state| state == &ConnectionState::Connected).await;
+    wait_for(&mut second_backend, &offline).await;
+
+    let from_server = Content::from("from the new server");
+    second_backend.set(from_server.clone()).unwrap();
+    wait_for(&mut client_backend, &from_server).await;
+
+    cancel_token.cancel();
+    second_token.cancel();
+    client_clipboard.shutdown().await.unwrap();
+    second.shutdown().await.unwrap();
+    client_handle.await.unwrap();
+    assert_eq!(state.borrow().clone(), ConnectionState::Stopped);
+}
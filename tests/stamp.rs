@@ -0,0 +1,232 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{connect, free_port};
+use synclip::clipboard::backend::{ClipboardBackend, MemoryBackend};
+use synclip::clipboard::local_clipboard::LocalClipboard;
+use synclip::clipboard::remote_clipboard::RemoteClipboard;
+use synclip::clipboard::{Clipboard, VirtualClipboard};
+use synclip::content::TEXT_HTML;
+use synclip::handshake::Features;
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::stamp::{Stamper, MAX_CLOCK_SKEW};
+use synclip::util::now_millis;
+use synclip::{Content, Stamp};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+fn stamp(origin: &str, timestamp: u64, seq: u64) -> Stamp {
+    Stamp {
+        origin: origin.into(),
+        timestamp,
+        seq,
+    }
+}
+
+#[test]
+fn last_writer_wins_by_timestamp_then_node() {
+    assert!(stamp("a", 2, 1).is_newer(Some(&stamp("b", 1, 9))));
+    assert!(stamp("b", 1, 1).is_newer(Some(&stamp("a", 1, 1))));
+    assert!(!stamp("a", 1, 1).is_newer(Some(&stamp("a", 1, 1))));
+    assert!(stamp("a", 1, 1).is_newer(None));
+
+    let stamped = Content::from("x").with_stamp(stamp("a", 1, 1));
+    assert!(!Content::from("x").supersedes(&Content::from("x")));
+    assert!(Content::from("y").supersedes(&stamped));
+    assert!(stamped.same_clip(&Content::from("x")));
+
+    let rich = Content::from("x").with(TEXT_HTML, "<b>x</b>");
+    assert!(rich.reads_back_as(&Content::from("x")));
+    assert!(!rich.reads_back_as(&Content::from("y")));
+    assert!(!Content::from("x").reads_back_as(&rich));
+}
+
+#[test]
+fn stamper_stays_ahead_of_observed_clips() {
+    let stamper = Stamper::new("a");
+    let first = stamper.next();
+    let second = stamper.next();
+    assert_eq!((first.seq, second.seq), (1, 2));
+    assert!(second.is_newer(Some(&first)));
+
+    // A peer whose clock runs half an hour ahead.
+    let ahead = stamp("z", second.timestamp + 1_800_000, 1);
+    stamper.observe(&ahead);
+    assert!(stamper.next().is_newer(Some(&ahead)));
+
+    // One forged to win forever neither overflows nor drags the clock along.
+    let forged = stamp("z", u64::MAX, 1);
+    assert!(forged.is_too_far_ahead());
+    assert!(!ahead.is_too_far_ahead());
+    stamper.observe(&forged);
+    let next = stamper.next();
+    assert!(next.timestamp <= now_millis() + MAX_CLOCK_SKEW + 1);
+    assert!(stamper.next().is_newer(Some(&next)));
+}
+
+/// A remote that is just the two ends of a channel.
+#[derive(Clone)]
+struct Loopback {
+    remote: RemoteClipboard,
+}
+
+impl VirtualClipboard for Loopback {
+    fn id(&self) -> &str {
+        "local"
+    }
+
+    fn remote(&self) -> &RemoteClipboard {
+        &self.remote
+    }
+
+    async fn shutdown(self) -> synclip::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn engine_ignores_echoes_and_older_clips() {
+    let (sender, mut sent) = watch::channel(Content::default());
+    let (incoming, receiver) = watch::channel(Content::default());
+    let mut backend = MemoryBackend::default();
+    let cancel_token = CancellationToken::new();
+    let mut clipboard = Clipboard::new(
+        LocalClipboard::with_backend(backend.clone()),
+        Loopback {
+            remote: RemoteClipboard::new(sender, receiver),
+        },
+        10,
+        cancel_token.clone(),
+    );
+    let handle = clipboard.start();
+
+    let ahead = now_millis() + 60_000;
+    let newer = Content::from("newer").with_stamp(stamp("peer", ahead, 2));
+    incoming.send_replace(newer);
+    common::wait_for(&mut backend, &"newer".into()).await;
+
+    let forged = Content::from("forged").with_stamp(stamp("peer", u64::MAX, 3));
+    incoming.send_replace(forged);
+
+    let older = Content::from("older").with_stamp(stamp("peer", 1, 1));
+    incoming.send_replace(older);
+    let echo = Content::from("echo").with_stamp(stamp("local", u64::MAX, 9));
+    incoming.send_replace(echo);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(backend.get().unwrap(), "newer".into());
+    // The applied clip is not sent back either.
+    assert!(!sent.has_changed().unwrap());
+
+    backend.set("copied".into()).unwrap();
+    tokio::time::timeout(
+        Duration::from_secs(2),
+        sent.wait_for(|sent| sent.same_clip(&"copied".into())),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    let copied = sent.borrow().stamp.clone().unwrap();
+    assert_eq!(copied.origin, "local");
+    assert!(
+        copied.timestamp > ahead,
+        "copy must win over the clip it follows"
+    );
+
+    cancel_token.cancel();
+    handle.await.unwrap();
+}
+
+/// A clipboard that, like most system ones, only reads back text.
+#[derive(Clone, Default)]
+struct TextOnlyBackend {
+    memory: MemoryBackend,
+}
+
+impl ClipboardBackend for TextOnlyBackend {
+    fn get(&mut self) -> color_eyre::Result<Content> {
+        Ok(Content::from(self.memory.get()?.text))
+    }
+
+    fn set(&mut self, content: Content) -> color_eyre::Result<()> {
+        self.memory.set(content)
+    }
+
+    fn watch(&mut self) -> Option<watch::Receiver<()>> {
+        self.memory.watch()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rich_clips_applied_locally_are_not_sent_back() {
+    let (sender, sent) = watch::channel(Content::default());
+    let (incoming, receiver) = watch::channel(Content::default());
+    let mut backend = TextOnlyBackend::default();
+    let cancel_token = CancellationToken::new();
+    let mut clipboard = Clipboard::new(
+        LocalClipboard::with_backend(backend.clone()),
+        Loopback {
+            remote: RemoteClipboard::new(sender, receiver),
+        },
+        10,
+        cancel_token.clone(),
+    );
+    let handle = clipboard.start();
+
+    let rich = Content::from("rich")
+        .with(TEXT_HTML, "<b>rich</b>")
+        .with_stamp(stamp("peer", now_millis() + 60_000, 1));
+    incoming.send_replace(rich);
+    for _ in 0..100 {
+        if backend.get().unwrap().text == "rich" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(
+        backend.memory.get().unwrap().get(TEXT_HTML),
+        Some(&b"<b>rich</b>"[..])
+    );
+    // Read back as text only, it is still the clip that was applied.
+    assert!(!sent.has_changed().unwrap());
+
+    cancel_token.cancel();
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn server_keeps_the_last_writer() {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    let server = SynclipServer::new(
+        common::listen(port),
+        Content::default(),
+        None,
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    let mut client = connect(port).await;
+
+    let set = |text: &str, stamp: Stamp| Content::from(text).with_stamp(stamp);
+    let mut replaced = Vec::new();
+    for content in [
+        set("b at 200", stamp("b", 200, 1)),
+        set("a at 100", stamp("a", 100, 1)),
+        set("a at 200", stamp("a", 200, 2)),
+        set("c at 200", stamp("c", 200, 1)),
+    ] {
+        let response = client.set_clipboard(content).await.unwrap();
+        replaced.push(response.into_inner().replaced);
+    }
+    assert_eq!(replaced, [true, false, false, true]);
+    assert_eq!(server.remote().current().await.unwrap().text, "c at 200");
+
+    cancel_token.cancel();
+    server.shutdown().await.unwrap();
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use common::free_port;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa};
+use synclip::handshake::Features;
+use synclip::history::History;
+use synclip::server::SynclipServer;
+use synclip::synclip_client::SynclipClient;
+use synclip::{tls, Empty};
+use tokio_util::sync::CancellationToken;
+use tonic::transport::{Channel, ClientTlsConfig};
+
+/// A throwaway CA with a server certificate for localhost and a client certificate.
+fn write_pki(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("synclip-tls-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut ca_params = CertificateParams::new(vec![]);
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = Certificate::from_params(ca_params).unwrap();
+    fs::write(dir.join("ca.pem"), ca.serialize_pem().unwrap()).unwrap();
+
+    for (name, san) in [("server", "localhost"), ("client", "client")] {
+        let cert = Certificate::from_params(CertificateParams::new(vec![san.to_string()])).unwrap();
+        let pem = cert.serialize_pem_with_signer(&ca).unwrap();
+        fs::write(dir.join(format!("{name}.pem")), pem).unwrap();
+        fs::write(
+            dir.join(format!("{name}.key")),
+            cert.serialize_private_key_pem(),
+        )
+        .unwrap();
+    }
+    dir
+}
+
+async fn start_server(dir: &Path, require_client_cert: bool) -> (u16, CancellationToken) {
+    let port = free_port();
+    let cancel_token = CancellationToken::new();
+    let config = tls::server_tls_config(
+        &dir.join("server.pem"),
+        &dir.join("server.key"),
+        Some(&dir.join("ca.pem")),
+        require_client_cert,
+    )
+    .unwrap();
+    SynclipServer::new(
+        common::listen(port),
+        "initial".into(),
+        Some(config),
+        None,
+        History::default(),
+        Features::default(),
+        cancel_token.clone(),
+    )
+    .await
+    .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    (port, cancel_token)
+}
+
+async fn list_peers(
+    address: String,
+    tls: Option<ClientTlsConfig>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut endpoint = Channel::from_shared(address)?;
+    if let Some(tls) = tls {
+        endpoint = endpoint.tls_config(tls)?;
+    }
+    let mut client = SynclipClient::new(endpoint.connect().await?);
+    let peers = client.list_peers(Empty {}).await?.into_inner().peers;
+    Ok(peers.len())
+}
+
+#[tokio::test]
+async fn mutual_tls_accepts_client_certificate() {
+    let dir = write_pki("mutual");
+    let (port, cancel_token) = start_server(&dir, true).await;
+
+    let config = tls::client_tls_config(
+        Some(&dir.join("ca.pem")),
+        Some((&dir.join("client.pem"), &dir.join("client.key"))),
+        Some("localhost"),
+    )
+    .unwrap();
+    let peers = list_peers(format!("https://127.0.0.1:{port}"), Some(config)).await;
+    assert_eq!(peers.unwrap(), 0);
+
+    cancel_token.cancel();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn required_client_certificate_rejects_anonymous_client() {
+    let dir = write_pki("required");
+    let (port, cancel_token) = start_server(&dir, true).await;
+
+    let config =
+        tls::client_tls_config(Some(&dir.join("ca.pem")), None, Some("localhost")).unwrap();
+    let peers = list_peers(format!("https://127.0.0.1:{port}"), Some(config)).await;
+    assert!(peers.is_err());
+
+    cancel_token.cancel();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn optional_client_certificate_accepts_anonymous_client() {
+    let dir = write_pki("optional");
+    let (port, cancel_token) = start_server(&dir, false).await;
+
+    let config =
+        tls::client_tls_config(Some(&dir.join("ca.pem")), None, Some("localhost")).unwrap();
+    let peers = list_peers(format!("https://127.0.0.1:{port}"), Some(config)).await;
+    assert_eq!(peers.unwrap(), 0);
+
+    let plaintext = list_peers(format!("http://127.0.0.1:{port}"), None).await;
+    assert!(plaintext.is_err());
+
+    cancel_token.cancel();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn requiring_client_certificate_needs_client_ca() {
+    let dir = write_pki("no-ca");
+    let config =
+        tls::server_tls_config(&dir.join("server.pem"), &dir.join("server.key"), None, true);
+    assert!(config.is_err());
+    fs::remove_dir_all(dir).unwrap();
+}
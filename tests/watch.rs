@@ -0,0 +1,123 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use color_eyre::Result;
+use synclip::clipboard::backend::{ClipboardBackend, MemoryBackend};
+use synclip::clipboard::local_clipboard::LocalClipboard;
+use synclip::clipboard::remote_clipboard::RemoteClipboard;
+use synclip::clipboard::{Clipboard, VirtualClipboard};
+use synclip::Content;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+/// A remote that is just the two ends of a channel.
+#[derive(Clone)]
+struct Loopback {
+    remote: RemoteClipboard,
+}
+
+impl VirtualClipboard for Loopback {
+    fn id(&self) -> &str {
+        "loopback"
+    }
+
+    fn remote(&self) -> &RemoteClipboard {
+        &self.remote
+    }
+
+    async fn shutdown(self) -> synclip::Result<()> {
+        Ok(())
+    }
+}
+
+/// Counts reads, and hides the change notifications unless `watched`.
+#[derive(Clone)]
+struct Counting {
+    inner: MemoryBackend,
+    reads: Arc<AtomicUsize>,
+    watched: bool,
+}
+
+impl ClipboardBackend for Counting {
+    fn get(&mut self) -> Result<Content> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.inner.get()
+    }
+
+    fn set(&mut self, content: Content) -> Result<()> {
+        self.inner.set(content)
+    }
+
+    fn watch(&mut self) -> Option<watch::Receiver<()>> {
+        self.watched.then(|| self.inner.watch()).flatten()
+    }
+}
+
+fn engine(
+    watched: bool,
+    frequency: u64,
+) -> (
+    Clipboard<Loopback, Counting>,
+    Counting,
+    watch::Receiver<Content>,
+) {
+    let (sender, sent) = watch::channel(Content::default());
+    let (_, receiver) = watch::channel(Content::default());
+    let backend = Counting {
+        inner: MemoryBackend::default(),
+        reads: Arc::new(AtomicUsize::new(0)),
+        watched,
+    };
+    let remote = Loopback {
+        remote: RemoteClipboard::new(sender, receiver),
+    };
+    let clipboard = Clipboard::new(
+        LocalClipboard::with_backend(backend.clone()),
+        remote,
+        frequency,
+        CancellationToken::new(),
+    );
+    (clipboard, backend, sent)
+}
+
+async fn wait_sent(sent: &mut watch::Receiver<Content>, expected: &Content) {
+    tokio::time::timeout(
+        Duration::from_secs(2),
+        sent.wait_for(|sent| sent.same_clip(expected)),
+    )
+    .await
+    .expect("clip was never sent")
+    .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn change_events_are_forwarded_without_polling() {
+    let (mut clipboard, mut backend, mut sent) = engine(true, 60_000);
+    clipboard.start();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let idle_reads = backend.reads.load(Ordering::Relaxed);
+
+    let copied = Content::from("copied");
+    backend.set(copied.clone()).unwrap();
+    wait_sent(&mut sent, &copied).await;
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(backend.reads.load(Ordering::Relaxed), idle_reads + 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn poll_frequency_is_adjustable_at_runtime() {
+    let (mut clipboard, mut backend, mut sent) = engine(false, 60_000);
+    clipboard.start();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let copied = Content::from("copied");
+    backend.set(copied.clone()).unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!sent.borrow().same_clip(&copied));
+
+    clipboard.set_frequency(10);
+    assert_eq!(clipboard.frequency(), 10);
+    wait_sent(&mut sent, &copied).await;
+}
@@ -0,0 +1,205 @@
+#![cfg(all(unix, not(target_os = "macos")))]
+
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use synclip::clipboard::backend::{ClipboardBackend, SystemBackend};
+use synclip::content::{IMAGE_PNG, TEXT_HTML};
+use synclip::Content;
+use x11rb::connection::{Connection, RequestConnection as _};
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt as _, CreateWindowAux, WindowClass};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::{CURRENT_TIME, NONE};
+
+/// A virtual X server, gone with the test.
+struct Xvfb {
+    child: Child,
+}
+
+impl Xvfb {
+    /// `None` if Xvfb is not installed.
+    fn start() -> Option<Self> {
+        let display = (90..200)
+            .find(|n| !Path::new(&format!("/tmp/.X11-unix/X{n}")).exists())
+            .unwrap();
+        let child = Command::new("Xvfb")
+            .args([format!(":{display}").as_str(), "-nolisten", "tcp"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let socket = format!("/tmp/.X11-unix/X{display}");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !Path::new(&socket).exists() {
+            assert!(Instant::now() < deadline, "Xvfb never came up");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        std::env::set_var("DISPLAY", format!(":{display}"));
+        Some(Self { child })
+    }
+}
+
+impl Drop for Xvfb {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Another application reading the clipboard.
+struct Reader {
+    connection: RustConnection,
+    window: u32,
+    clipboard: Atom,
+}
+
+impl Reader {
+    fn new() -> Self {
+        let (connection, screen) = x11rb::connect(None).unwrap();
+        let root = connection.setup().roots[screen].root;
+        let window = connection.generate_id().unwrap();
+        connection
+            .create_window(
+                0,
+                window,
+                root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_ONLY,
+                x11rb::COPY_FROM_PARENT,
+                &CreateWindowAux::new(),
+            )
+            .unwrap();
+        let clipboard = atom(&connection, "CLIPBOARD");
+        Self {
+            connection,
+            window,
+            clipboard,
+        }
+    }
+
+    /// What the owner answers for `target`, `None` if it refuses.
+    fn convert(&self, target: &str) -> Option<(Atom, Vec<u8>)> {
+        let target = atom(&self.connection, target);
+        let property = atom(&self.connection, "SYNCLIP_TEST");
+        self.connection
+            .convert_selection(self.window, self.clipboard, target, property, CURRENT_TIME)
+            .unwrap();
+        self.connection.flush().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let notify = loop {
+            match self.connection.poll_for_event().unwrap() {
+                Some(Event::SelectionNotify(notify)) if notify.target == target => break notify,
+                Some(_) => continue,
+                None => {
+                    assert!(Instant::now() < deadline, "the owner never answered");
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+            }
+        };
+        if notify.property == NONE {
+            return None;
+        }
+        let reply = self
+            .connection
+            .get_property(true, self.window, property, AtomEnum::ANY, 0, u32::MAX / 4)
+            .unwrap()
+            .reply()
+            .unwrap();
+        Some((reply.type_, reply.value))
+    }
+
+    fn targets(&self) -> Vec<String> {
+        let (_, value) = self.convert("TARGETS").unwrap();
+        value
+            .chunks_exact(4)
+            .map(|atom| {
+                let atom = u32::from_ne_bytes(atom.try_into().unwrap());
+                let name = self
+                    .connection
+                    .get_atom_name(atom)
+                    .unwrap()
+                    .reply()
+                    .unwrap();
+                String::from_utf8(name.name).unwrap()
+            })
+            .collect()
+    }
+}
+
+fn atom(connection: &RustConnection, name: &str) -> Atom {
+    connection
+        .intern_atom(false, name.as_bytes())
+        .unwrap()
+        .reply()
+        .unwrap()
+        .atom
+}
+
+#[test]
+fn written_clips_are_served_until_another_application_copies() {
+    let Some(_xvfb) = Xvfb::start() else {
+        eprintln!("Xvfb is not installed, skipping");
+        return;
+    };
+    let mut backend = SystemBackend::new().unwrap();
+    let copied = Content::from("copied").with(TEXT_HTML, "<b>copied</b>");
+    backend.set(copied).unwrap();
+
+    // Served from the backend's own thread, long after the write.
+    std::thread::sleep(Duration::from_millis(200));
+    let reader = Reader::new();
+    let targets = reader.targets();
+    for target in ["TARGETS", "UTF8_STRING", "STRING", "text/plain", TEXT_HTML] {
+        assert!(
+            targets.iter().any(|t| t == target),
+            "no {target} in {targets:?}"
+        );
+    }
+    let (type_, text) = reader.convert("UTF8_STRING").unwrap();
+    assert_eq!(type_, atom(&reader.connection, "UTF8_STRING"));
+    assert_eq!(text, b"copied");
+    assert_eq!(reader.convert(TEXT_HTML).unwrap().1, b"<b>copied</b>");
+    assert_eq!(reader.convert(IMAGE_PNG), None);
+    // Read back with what else was copied.
+    let read = backend.get().unwrap();
+    assert_eq!(read.text, "copied");
+    assert_eq!(read.get(TEXT_HTML), Some(&b"<b>copied</b>"[..]));
+
+    // Nothing but an image.
+    let image = Content::default().with(IMAGE_PNG, vec![0x89, b'P', b'N', b'G']);
+    backend.set(image.clone()).unwrap();
+    assert_eq!(backend.get().unwrap(), image);
+    assert_eq!(reader.convert("UTF8_STRING"), None);
+
+    // Not ASCII, not Latin-1 either.
+    backend.set(Content::from("kopiert ✓")).unwrap();
+    assert_eq!(
+        reader.convert("UTF8_STRING").unwrap().1,
+        "kopiert ✓".as_bytes()
+    );
+    assert_eq!(reader.convert("STRING"), None);
+    assert_eq!(reader.convert(TEXT_HTML), None);
+
+    // Another application copies, then the backend writes again.
+    let mut other = SystemBackend::new().unwrap();
+    other.set(Content::from("elsewhere")).unwrap();
+    assert_eq!(reader.convert("UTF8_STRING").unwrap().1, b"elsewhere");
+    assert_eq!(backend.get().unwrap().text, "elsewhere");
+    drop(other);
+    backend.set(Content::from("taken back")).unwrap();
+    assert_eq!(reader.convert("UTF8_STRING").unwrap().1, b"taken back");
+
+    // Larger than one request may carry, sent piece by piece.
+    let mut png = vec![0x89, b'P', b'N', b'G'];
+    png.resize(reader.connection.maximum_request_bytes() + 1024, 0x42);
+    let large = Content::default().with(IMAGE_PNG, png);
+    backend.set(large.clone()).unwrap();
+    let mut other = SystemBackend::new().unwrap();
+    assert_eq!(other.get().unwrap(), large);
+}